@@ -1,7 +1,7 @@
 mod modify;
+pub mod serialize;
 use itertools::Itertools;
 pub use modify::modify;
-use std::collections::BTreeMap;
 use std::fmt::{self};
 
 // Node
@@ -90,9 +90,31 @@ pub enum Statement {
         identifier: Identifier,
         expression: Expression,
     },
+    // `const <identifier> = <expression>;`, cannot be redeclared in the same scope
+    Const {
+        identifier: Identifier,
+        expression: Expression,
+    },
+    // `let [a, b] = <expression>;`, `let {a, b} = <expression>;`
+    Destructure {
+        pattern: Pattern,
+        expression: Expression,
+    },
+    // `import "<path>" as <identifier>;`, `import {<identifier>, ...} from "<path>";`
+    Import {
+        path: String,
+        binding: ImportBinding,
+    },
+    // `export let ...;`, `export const ...;`, only meaningful at the top level of a module
+    Export(Box<Statement>),
     Return(Expression),
     Expression(Expression),
     Block(BlockStatement),
+    // the comments around a statement, e.g. `// doc\nlet a = 1; // note`; only
+    // produced when parsing with `Lexer::new_with_comments`, like `Comment`
+    Commented(Comments, Box<Statement>),
+    // comments followed by no statement in their block
+    Comment(String),
 }
 
 impl fmt::Display for Statement {
@@ -102,9 +124,47 @@ impl fmt::Display for Statement {
                 identifier,
                 expression,
             } => write!(f, "let {} = {};", identifier, expression),
+            Statement::Const {
+                identifier,
+                expression,
+            } => write!(f, "const {} = {};", identifier, expression),
+            Statement::Destructure {
+                pattern,
+                expression,
+            } => write!(f, "let {} = {};", pattern, expression),
+            Statement::Import { path, binding } => match binding {
+                ImportBinding::Module(id) => write!(f, r#"import "{}" as {};"#, path, id),
+                ImportBinding::Names(names) => {
+                    write!(
+                        f,
+                        r#"import {{{}}} from "{}";"#,
+                        names.iter().join(", "),
+                        path
+                    )
+                }
+            },
+            Statement::Export(stmt) => write!(f, "export {}", stmt),
             Statement::Return(expr) => write!(f, "return {};", expr),
             Statement::Expression(expr) => write!(f, "{}", expr),
             Statement::Block(it) => write!(f, "{}", it),
+            Statement::Commented(_, it) => write!(f, "{}", it),
+            Statement::Comment(it) => write!(f, "{}", it),
+        }
+    }
+}
+
+impl Statement {
+    // `self` with `comments` around it, nothing if there are none
+    pub(crate) fn with_comments(self, comments: Comments) -> Self {
+        if comments.is_empty() {
+            return self;
+        }
+        match self {
+            Statement::Commented(mut it, stmt) => {
+                it.extend(comments);
+                Statement::Commented(it, stmt)
+            }
+            stmt => Statement::Commented(comments, stmt.into()),
         }
     }
 }
@@ -115,6 +175,57 @@ impl std::convert::From<BlockStatement> for Statement {
     }
 }
 
+impl Statement {
+    // the identifiers exported by `export <statement>`
+    pub fn exported_identifiers(&self) -> Vec<&Identifier> {
+        match self {
+            Statement::Commented(_, stmt) => stmt.exported_identifiers(),
+            Statement::Export(stmt) => match stmt.as_ref() {
+                Statement::Let { identifier, .. } | Statement::Const { identifier, .. } => {
+                    vec![identifier]
+                }
+                Statement::Destructure { pattern, .. } => pattern.identifiers(),
+                _ => vec![],
+            },
+            _ => vec![],
+        }
+    }
+}
+
+// Comments
+
+// comments kept as trivia of the node they are attached to
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Default)]
+pub struct Comments {
+    // before the node, on lines of their own or inline
+    pub leading: Vec<String>,
+    // after the node, on the line it ends
+    pub trailing: Vec<String>,
+}
+
+impl Comments {
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_empty()
+    }
+
+    fn extend(&mut self, other: Comments) {
+        let mut leading = other.leading;
+        leading.append(&mut self.leading);
+        self.leading = leading;
+        self.trailing.extend(other.trailing);
+    }
+}
+
+// ImportBinding
+
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub enum ImportBinding {
+    // `as lib`, the module object
+    Module(Identifier),
+    // `{a, b}`, the exported bindings
+    Names(Vec<Identifier>),
+}
+
 // Identifier
 
 #[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
@@ -169,7 +280,9 @@ pub enum Expression {
     Boolean(bool),
     String(String),
     Array(Vec<Expression>),
-    Hash(BTreeMap<Expression, Expression>),
+    Hash(Vec<HashEntry>),
+    // `...a`, only an element of an array or an argument
+    Spread(Box<Expression>),
     Prefix {
         operator: PrefixOperator,
         right: Box<Expression>,
@@ -184,6 +297,11 @@ pub enum Expression {
         consequence: BlockStatement,
         alternative: Option<BlockStatement>,
     },
+    Try {
+        block: BlockStatement,
+        catch: Option<CatchClause>,
+        finally: Option<BlockStatement>,
+    },
     Function(FunctionExpression),
     Call {
         function: CallExpressionFunction,
@@ -193,9 +311,30 @@ pub enum Expression {
         left: Box<Expression>,
         index: Box<Expression>,
     },
+    OptionalIndex {
+        left: Box<Expression>,
+        index: Box<Expression>,
+    },
+    // `lib.name`, same as `lib["name"]`
+    Member {
+        left: Box<Expression>,
+        name: Identifier,
+    },
+    OptionalCall {
+        function: Box<Expression>,
+        args: Vec<Expression>,
+    },
+    // `match (<value>) { <pattern> [if <guard>] => <body>, ... }`
+    Match {
+        value: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
     Quote(Box<Expression>),
     Unquote(Box<Expression>),
     Macro(MacroExpression),
+    // the comments around an expression, e.g. `[1, /* two */ 2]`; only produced
+    // when parsing with `Lexer::new_with_comments`
+    Commented(Comments, Box<Expression>),
 }
 
 impl fmt::Display for Expression {
@@ -210,12 +349,10 @@ impl fmt::Display for Expression {
                 write!(f, "[{}]", s)
             }
             Expression::Hash(h) => {
-                let s = h
-                    .into_iter()
-                    .map(|(k, v)| format!("{}: {}", k, v))
-                    .join(", ");
+                let s = h.iter().join(", ");
                 write!(f, "{{{}}}", s)
             }
+            Expression::Spread(expr) => write!(f, "...{}", expr),
             Expression::Prefix { operator, right } => write!(f, "({}{})", operator, right),
             Expression::Infix {
                 left,
@@ -234,15 +371,55 @@ impl fmt::Display for Expression {
                     Ok(())
                 }
             }
+            Expression::Try {
+                block,
+                catch,
+                finally,
+            } => {
+                write!(f, "try {{ {} }}", block)?;
+                if let Some(c) = catch {
+                    write!(f, " {}", c)?;
+                }
+                if let Some(fin) = finally {
+                    write!(f, " finally {{ {} }}", fin)?;
+                }
+                Ok(())
+            }
+            Expression::Match { value, arms } => {
+                write!(f, "match ({}) {{ {} }}", value, arms.iter().join(", "))
+            }
             Expression::Function(func) => write!(f, "{}", func),
             Expression::Call { function, args } => {
                 let args = args.into_iter().join(", ");
                 write!(f, "{}({})", function, args)
             }
             Expression::Index { left, index } => write!(f, "({}[{}])", left, index),
+            Expression::OptionalIndex { left, index } => write!(f, "({}?.[{}])", left, index),
+            Expression::Member { left, name } => write!(f, "({}.{})", left, name),
+            Expression::OptionalCall { function, args } => {
+                let args = args.iter().join(", ");
+                write!(f, "{}?.({})", function, args)
+            }
             Expression::Quote(expr) => write!(f, "quote({})", expr),
             Expression::Unquote(expr) => write!(f, "unquote({})", expr),
             Expression::Macro(it) => write!(f, "{}", it),
+            Expression::Commented(_, expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl Expression {
+    // `self` with `comments` around it, nothing if there are none
+    pub(crate) fn with_comments(self, comments: Comments) -> Self {
+        if comments.is_empty() {
+            return self;
+        }
+        match self {
+            Expression::Commented(mut it, expr) => {
+                it.extend(comments);
+                Expression::Commented(it, expr)
+            }
+            expr => Expression::Commented(comments, expr.into()),
         }
     }
 }
@@ -264,6 +441,7 @@ impl std::convert::From<CallExpressionFunction> for Expression {
         match call {
             CallExpressionFunction::Identifier(id) => id.into(),
             CallExpressionFunction::Function(f) => f.into(),
+            CallExpressionFunction::Expression(expr) => *expr,
         }
     }
 }
@@ -274,6 +452,24 @@ impl std::convert::From<MacroExpression> for Expression {
     }
 }
 
+// HashEntry
+
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub enum HashEntry {
+    Pair(Expression, Expression),
+    // `...h`
+    Spread(Expression),
+}
+
+impl fmt::Display for HashEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashEntry::Pair(k, v) => write!(f, "{}: {}", k, v),
+            HashEntry::Spread(h) => write!(f, "...{}", h),
+        }
+    }
+}
+
 // PrefixOperator
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
@@ -304,6 +500,7 @@ pub enum InfixOperator {
     GT,
     Eq,
     NotEq,
+    NullCoalescing,
 }
 
 impl fmt::Display for InfixOperator {
@@ -317,16 +514,164 @@ impl fmt::Display for InfixOperator {
             InfixOperator::GT => ">",
             InfixOperator::Eq => "==",
             InfixOperator::NotEq => "!=",
+            InfixOperator::NullCoalescing => "??",
         };
         write!(f, "{}", s)
     }
 }
 
+// CatchClause
+
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub struct CatchClause {
+    pub identifier: Identifier,
+    pub body: BlockStatement,
+}
+
+impl fmt::Display for CatchClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "catch ({}) {{ {} }}", self.identifier, self.body)
+    }
+}
+
+// Pattern
+
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub enum Pattern {
+    Identifier(Identifier),
+    // `_`, matches anything without binding
+    Wildcard,
+    // `1`, `-1`, `"a"`, `true`, matches an equal value
+    Literal(Expression),
+    // `[a, [b, c], ...rest]`
+    Array {
+        elements: Vec<Pattern>,
+        rest: Option<Identifier>,
+    },
+    // `{name, age: years}`, the values of the string keys `"name"` and `"age"`
+    Hash(Vec<(Identifier, Pattern)>),
+}
+
+impl Pattern {
+    // the bound identifiers
+    pub fn identifiers(&self) -> Vec<&Identifier> {
+        match self {
+            Pattern::Identifier(it) => vec![it],
+            Pattern::Wildcard | Pattern::Literal(_) => vec![],
+            Pattern::Array { elements, rest } => elements
+                .iter()
+                .flat_map(Pattern::identifiers)
+                .chain(rest)
+                .collect(),
+            Pattern::Hash(fields) => fields.iter().flat_map(|(_, p)| p.identifiers()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Identifier(it) => write!(f, "{}", it),
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Literal(it) => write!(f, "{}", it),
+            Pattern::Array { elements, rest } => {
+                let rest = rest.iter().map(|r| format!("...{}", r));
+                write!(
+                    f,
+                    "[{}]",
+                    elements
+                        .iter()
+                        .map(Pattern::to_string)
+                        .chain(rest)
+                        .join(", ")
+                )
+            }
+            Pattern::Hash(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, p)| match p {
+                        Pattern::Identifier(id) if id == key => key.to_string(),
+                        p => format!("{}: {}", key, p),
+                    })
+                    .join(", ");
+                write!(f, "{{{}}}", fields)
+            }
+        }
+    }
+}
+
+impl std::convert::From<Identifier> for Pattern {
+    fn from(id: Identifier) -> Self {
+        Self::Identifier(id)
+    }
+}
+
+// MatchArm
+
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Expression,
+}
+
+impl fmt::Display for MatchArm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)?;
+        if let Some(guard) = &self.guard {
+            write!(f, " if {}", guard)?;
+        }
+        write!(f, " => {}", self.body)
+    }
+}
+
+// Parameter
+
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+pub enum Parameter {
+    Identifier(Identifier),
+    // `b = 2`, only followed by parameters with default values
+    Default(Identifier, Expression),
+    // `...rest`, only the last parameter
+    Rest(Identifier),
+    // `[a, b]`, `{a, b}`
+    Pattern(Pattern),
+}
+
+impl Parameter {
+    // the bound identifiers
+    pub fn identifiers(&self) -> Vec<&Identifier> {
+        match self {
+            Parameter::Identifier(it) | Parameter::Default(it, _) | Parameter::Rest(it) => {
+                vec![it]
+            }
+            Parameter::Pattern(p) => p.identifiers(),
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parameter::Identifier(it) => write!(f, "{}", it),
+            Parameter::Default(it, default) => write!(f, "{} = {}", it, default),
+            Parameter::Rest(it) => write!(f, "...{}", it),
+            Parameter::Pattern(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+impl std::convert::From<Identifier> for Parameter {
+    fn from(id: Identifier) -> Self {
+        Self::Identifier(id)
+    }
+}
+
 // FunctionExpression
 
 #[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
 pub struct FunctionExpression {
-    pub params: Vec<Identifier>,
+    pub params: Vec<Parameter>,
     pub body: BlockStatement,
 }
 
@@ -343,6 +688,8 @@ impl fmt::Display for FunctionExpression {
 pub enum CallExpressionFunction {
     Identifier(Identifier),
     Function(FunctionExpression),
+    // `lib.f`, `h["f"]`
+    Expression(Box<Expression>),
 }
 
 impl fmt::Display for CallExpressionFunction {
@@ -350,6 +697,7 @@ impl fmt::Display for CallExpressionFunction {
         match self {
             CallExpressionFunction::Identifier(it) => write!(f, "{}", it),
             CallExpressionFunction::Function(it) => write!(f, "{}", it),
+            CallExpressionFunction::Expression(it) => write!(f, "{}", it),
         }
     }
 }
@@ -370,7 +718,7 @@ impl std::convert::From<FunctionExpression> for CallExpressionFunction {
 
 #[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
 pub struct MacroExpression {
-    pub params: Vec<Identifier>,
+    pub params: Vec<Parameter>,
     pub body: BlockStatement,
 }
 
@@ -37,6 +37,28 @@ fn modify_statement<F: Modifier>(stmt: Statement, modifier: &mut F) -> Result<St
                 expression,
             })
         }
+        Statement::Const {
+            identifier,
+            expression,
+        } => {
+            let expression = modify_expression(expression, modifier)?;
+            Ok(Statement::Const {
+                identifier,
+                expression,
+            })
+        }
+        Statement::Destructure {
+            pattern,
+            expression,
+        } => {
+            let expression = modify_expression(expression, modifier)?;
+            Ok(Statement::Destructure {
+                pattern,
+                expression,
+            })
+        }
+        Statement::Import { .. } => Ok(stmt),
+        Statement::Export(stmt) => Ok(Statement::Export(modify_statement(*stmt, modifier)?.into())),
         Statement::Return(expr) => Ok(Statement::Return(modify_expression(expr, modifier)?)),
         Statement::Expression(expr) => {
             Ok(Statement::Expression(modify_expression(expr, modifier)?))
@@ -45,6 +67,11 @@ fn modify_statement<F: Modifier>(stmt: Statement, modifier: &mut F) -> Result<St
             let block = modify_block_statement(block, modifier)?;
             Ok(block.into())
         }
+        Statement::Commented(comments, stmt) => Ok(Statement::Commented(
+            comments,
+            modify_statement(*stmt, modifier)?.into(),
+        )),
+        Statement::Comment(it) => Ok(Statement::Comment(it)),
     }
 }
 
@@ -65,6 +92,10 @@ fn modify_block_statement<F: Modifier>(
 
 fn modify_expression<F: Modifier>(expr: Expression, modifier: &mut F) -> Result<Expression> {
     match expr {
+        Expression::Commented(comments, expr) => Ok(Expression::Commented(
+            comments,
+            modify_expression(*expr, modifier)?.into(),
+        )),
         Expression::Array(ary) => {
             let mut elements = Vec::new();
             for e in ary {
@@ -72,14 +103,24 @@ fn modify_expression<F: Modifier>(expr: Expression, modifier: &mut F) -> Result<
             }
             Ok(Expression::Array(elements))
         }
-        Expression::Hash(map) => {
-            let mut new_map = BTreeMap::new();
-            for (k, v) in map {
-                let k = modify_expression(k, modifier)?;
-                let v = modify_expression(v, modifier)?;
-                new_map.insert(k, v);
+        Expression::Hash(entries) => {
+            let mut new_entries = Vec::new();
+            for entry in entries {
+                let entry = match entry {
+                    HashEntry::Pair(k, v) => {
+                        let k = modify_expression(k, modifier)?;
+                        let v = modify_expression(v, modifier)?;
+                        HashEntry::Pair(k, v)
+                    }
+                    HashEntry::Spread(h) => HashEntry::Spread(modify_expression(h, modifier)?),
+                };
+                new_entries.push(entry);
             }
-            Ok(Expression::Hash(new_map))
+            Ok(Expression::Hash(new_entries))
+        }
+        Expression::Spread(expr) => {
+            let expr = modify_expression(*expr, modifier)?;
+            Ok(Expression::Spread(expr.into()))
         }
         Expression::Prefix { operator, right } => {
             let right = modify_expression(*right, modifier)?;
@@ -119,12 +160,55 @@ fn modify_expression<F: Modifier>(expr: Expression, modifier: &mut F) -> Result<
                 alternative,
             })
         }
+        Expression::Try {
+            block,
+            catch,
+            finally,
+        } => {
+            let block = modify_block_statement(block, modifier)?;
+            let catch = if let Some(c) = catch {
+                Some(CatchClause {
+                    identifier: c.identifier,
+                    body: modify_block_statement(c.body, modifier)?,
+                })
+            } else {
+                catch
+            };
+            let finally = if let Some(fin) = finally {
+                Some(modify_block_statement(fin, modifier)?)
+            } else {
+                finally
+            };
+            Ok(Expression::Try {
+                block,
+                catch,
+                finally,
+            })
+        }
+        Expression::Match { value, arms } => {
+            let value = modify_expression(*value, modifier)?;
+            let mut modified = Vec::new();
+            for arm in arms {
+                let guard = if let Some(g) = arm.guard {
+                    Some(modify_expression(g, modifier)?)
+                } else {
+                    None
+                };
+                modified.push(MatchArm {
+                    pattern: arm.pattern,
+                    guard,
+                    body: modify_expression(arm.body, modifier)?,
+                });
+            }
+            Ok(Expression::Match {
+                value: value.into(),
+                arms: modified,
+            })
+        }
         Expression::Function(f) => {
+            let params = modify_params(f.params, modifier)?;
             let body = modify_block_statement(f.body, modifier)?;
-            let f = FunctionExpression {
-                params: f.params,
-                body,
-            };
+            let f = FunctionExpression { params, body };
             Ok(Expression::Function(f))
         }
         Expression::Index { left, index } => {
@@ -135,15 +219,77 @@ fn modify_expression<F: Modifier>(expr: Expression, modifier: &mut F) -> Result<
                 index: index.into(),
             })
         }
+        Expression::OptionalIndex { left, index } => {
+            let left = modify_expression(*left, modifier)?;
+            let index = modify_expression(*index, modifier)?;
+            Ok(Expression::OptionalIndex {
+                left: left.into(),
+                index: index.into(),
+            })
+        }
+        Expression::Member { left, name } => {
+            let left = modify_expression(*left, modifier)?;
+            Ok(Expression::Member {
+                left: left.into(),
+                name,
+            })
+        }
+        // the call itself is also passed to the modifier (e.g. for macro calls)
+        Expression::Call { function, args } => {
+            let function = match function {
+                CallExpressionFunction::Function(f) => {
+                    let params = modify_params(f.params, modifier)?;
+                    let body = modify_block_statement(f.body, modifier)?;
+                    CallExpressionFunction::Function(FunctionExpression { params, body })
+                }
+                CallExpressionFunction::Expression(expr) => {
+                    CallExpressionFunction::Expression(modify_expression(*expr, modifier)?.into())
+                }
+                id => id,
+            };
+            let args = modify_expressions(args, modifier)?;
+            Ok(modifier(Expression::Call { function, args }.into()).expression()?)
+        }
+        Expression::OptionalCall { function, args } => {
+            let function = modify_expression(*function, modifier)?;
+            let args = modify_expressions(args, modifier)?;
+            let call = Expression::OptionalCall {
+                function: function.into(),
+                args,
+            };
+            Ok(modifier(call.into()).expression()?)
+        }
         other => Ok(modifier(other.into()).expression()?),
     }
 }
 
+fn modify_expressions<F: Modifier>(
+    exprs: Vec<Expression>,
+    modifier: &mut F,
+) -> Result<Vec<Expression>> {
+    let mut res = Vec::new();
+    for e in exprs {
+        res.push(modify_expression(e, modifier)?);
+    }
+    Ok(res)
+}
+
+// default values are expressions, e.g. `fn(b = unquote(x)) { b }`
+fn modify_params<F: Modifier>(params: Vec<Parameter>, modifier: &mut F) -> Result<Vec<Parameter>> {
+    let mut res = Vec::new();
+    for p in params {
+        res.push(match p {
+            Parameter::Default(id, e) => Parameter::Default(id, modify_expression(e, modifier)?),
+            p => p,
+        });
+    }
+    Ok(res)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ast::modify::modify;
     use crate::ast::*;
-    use std::collections::BTreeMap;
 
     #[test]
     fn modify_integer_expression() -> Result<(), Box<dyn std::error::Error>> {
@@ -170,9 +316,29 @@ mod tests {
 
     #[test]
     fn modify_hash_expression() -> Result<(), Box<dyn std::error::Error>> {
+        let tests = vec![
+            (
+                Expression::Hash(vec![HashEntry::Pair(one(), one())]),
+                Expression::Hash(vec![HashEntry::Pair(two(), two())]),
+            ),
+            (
+                Expression::Hash(vec![HashEntry::Spread(one())]),
+                Expression::Hash(vec![HashEntry::Spread(two())]),
+            ),
+        ];
+        for (expr, expected) in tests {
+            let node = Node::from(expr);
+            let res = modify(node, turn_one_into_two)?;
+            assert_eq!(res, expected.into());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn modify_spread_expression() -> Result<(), Box<dyn std::error::Error>> {
         let tests = vec![(
-            Expression::Hash(vec![(one(), one())].into_iter().collect::<BTreeMap<_, _>>()),
-            Expression::Hash(vec![(two(), two())].into_iter().collect::<BTreeMap<_, _>>()),
+            Expression::Array(vec![Expression::Spread(one().into())]),
+            Expression::Array(vec![Expression::Spread(two().into())]),
         )];
         for (expr, expected) in tests {
             let node = Node::from(expr);
@@ -269,20 +435,36 @@ mod tests {
     }
 
     #[test]
-    fn modify_function_expression() -> Result<(), Box<dyn std::error::Error>> {
+    fn modify_try_expression() -> Result<(), Box<dyn std::error::Error>> {
         let tests = vec![(
-            Expression::Function(FunctionExpression {
-                params: Vec::new(),
-                body: BlockStatement {
+            Expression::Try {
+                block: BlockStatement {
                     statements: vec![Statement::Expression(one())],
                 },
-            }),
-            Expression::Function(FunctionExpression {
-                params: Vec::new(),
-                body: BlockStatement {
+                catch: Some(CatchClause {
+                    identifier: "e".into(),
+                    body: BlockStatement {
+                        statements: vec![Statement::Expression(one())],
+                    },
+                }),
+                finally: Some(BlockStatement {
+                    statements: vec![Statement::Expression(one())],
+                }),
+            },
+            Expression::Try {
+                block: BlockStatement {
                     statements: vec![Statement::Expression(two())],
                 },
-            }),
+                catch: Some(CatchClause {
+                    identifier: "e".into(),
+                    body: BlockStatement {
+                        statements: vec![Statement::Expression(two())],
+                    },
+                }),
+                finally: Some(BlockStatement {
+                    statements: vec![Statement::Expression(two())],
+                }),
+            },
         )];
         for (expr, expected) in tests {
             let node = Node::from(expr);
@@ -292,6 +474,46 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn modify_function_expression() -> Result<(), Box<dyn std::error::Error>> {
+        let tests = vec![
+            (
+                Expression::Function(FunctionExpression {
+                    params: Vec::new(),
+                    body: BlockStatement {
+                        statements: vec![Statement::Expression(one())],
+                    },
+                }),
+                Expression::Function(FunctionExpression {
+                    params: Vec::new(),
+                    body: BlockStatement {
+                        statements: vec![Statement::Expression(two())],
+                    },
+                }),
+            ),
+            (
+                Expression::Function(FunctionExpression {
+                    params: vec![Parameter::Default(Identifier("a".into()), one())],
+                    body: BlockStatement {
+                        statements: Vec::new(),
+                    },
+                }),
+                Expression::Function(FunctionExpression {
+                    params: vec![Parameter::Default(Identifier("a".into()), two())],
+                    body: BlockStatement {
+                        statements: Vec::new(),
+                    },
+                }),
+            ),
+        ];
+        for (expr, expected) in tests {
+            let node = Node::from(expr);
+            let res = modify(node, turn_one_into_two)?;
+            assert_eq!(res, expected.into());
+        }
+        Ok(())
+    }
+
     #[test]
     fn modify_index_expression() -> Result<(), Box<dyn std::error::Error>> {
         let tests = vec![(
@@ -312,6 +534,74 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn modify_optional_index_expression() -> Result<(), Box<dyn std::error::Error>> {
+        let tests = vec![(
+            Expression::OptionalIndex {
+                left: one().into(),
+                index: one().into(),
+            },
+            Expression::OptionalIndex {
+                left: two().into(),
+                index: two().into(),
+            },
+        )];
+        for (expr, expected) in tests {
+            let node = Node::from(expr);
+            let res = modify(node, turn_one_into_two)?;
+            assert_eq!(res, expected.into());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn modify_call_expression() -> Result<(), Box<dyn std::error::Error>> {
+        let function = |e: Expression| FunctionExpression {
+            params: vec![],
+            body: BlockStatement {
+                statements: vec![Statement::Expression(e)],
+            },
+        };
+        let tests = vec![
+            (
+                Expression::Call {
+                    function: CallExpressionFunction::Identifier(Identifier("f".into())),
+                    args: vec![one(), one()],
+                },
+                Expression::Call {
+                    function: CallExpressionFunction::Identifier(Identifier("f".into())),
+                    args: vec![two(), two()],
+                },
+            ),
+            (
+                Expression::Call {
+                    function: CallExpressionFunction::Function(function(one())),
+                    args: vec![one()],
+                },
+                Expression::Call {
+                    function: CallExpressionFunction::Function(function(two())),
+                    args: vec![two()],
+                },
+            ),
+            (
+                Expression::OptionalCall {
+                    function: one().into(),
+                    args: vec![one()],
+                },
+                Expression::OptionalCall {
+                    function: two().into(),
+                    args: vec![two()],
+                },
+            ),
+        ];
+        for (expr, expected) in tests {
+            let node = Node::from(expr);
+            let res = modify(node, turn_one_into_two)?;
+            assert_eq!(res, expected.into());
+        }
+        Ok(())
+    }
+
     #[test]
     fn modify_program_statement() -> Result<(), Box<dyn std::error::Error>> {
         let node = Program {
@@ -329,16 +619,32 @@ mod tests {
 
     #[test]
     fn modify_let_statement() -> Result<(), Box<dyn std::error::Error>> {
-        let tests = vec![(
-            Statement::Let {
-                identifier: "foo".into(),
-                expression: one(),
-            },
-            Statement::Let {
-                identifier: "foo".into(),
-                expression: two(),
-            },
-        )];
+        let pattern = || Pattern::Array {
+            elements: vec![Pattern::Identifier("foo".into())],
+            rest: None,
+        };
+        let tests = vec![
+            (
+                Statement::Let {
+                    identifier: "foo".into(),
+                    expression: one(),
+                },
+                Statement::Let {
+                    identifier: "foo".into(),
+                    expression: two(),
+                },
+            ),
+            (
+                Statement::Destructure {
+                    pattern: pattern(),
+                    expression: one(),
+                },
+                Statement::Destructure {
+                    pattern: pattern(),
+                    expression: two(),
+                },
+            ),
+        ];
         for (expr, expected) in tests {
             let node = Node::from(expr);
             let res = modify(node, turn_one_into_two)?;
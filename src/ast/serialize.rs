@@ -0,0 +1,826 @@
+use crate::ast::*;
+use crate::formatter;
+use crate::lexer::Lexer;
+use crate::parser;
+use serde_json::{json, Map, Value};
+
+// A stable serialized form of the AST for tools outside of Rust.
+//
+// In JSON, every node is an object whose "type" is the name of the variant and
+// whose other fields are named after the Rust fields. Identifiers are strings,
+// blocks are arrays of statements, operators are their source text and absent
+// optional parts are `null`:
+//
+//     {"type": "Let", "identifier": "x", "expression": {"type": "Integer", "value": 1}}
+//
+// The S-expression form writes the same tree with the fields in key order and
+// arrays in brackets:
+//
+//     (Let :expression (Integer :value 1) :identifier "x")
+
+pub type Error = String;
+pub type Result<T> = std::result::Result<T, Error>;
+
+// `monkey parse --emit ast-json <file>`
+pub fn to_json(
+    input: impl Into<String>,
+) -> std::result::Result<String, Box<dyn std::error::Error>> {
+    let program = parser::parse(Lexer::new_with_comments(input))?;
+    Ok(serde_json::to_string_pretty(&program_to_json(&program))?)
+}
+
+// `monkey parse --emit ast-sexp <file>`
+pub fn to_sexp(
+    input: impl Into<String>,
+) -> std::result::Result<String, Box<dyn std::error::Error>> {
+    let program = parser::parse(Lexer::new_with_comments(input))?;
+    Ok(program_to_sexp(&program))
+}
+
+// the formatted source of a program serialized by `to_json`
+pub fn from_json(input: &str) -> std::result::Result<String, Box<dyn std::error::Error>> {
+    let value = serde_json::from_str(input)?;
+    Ok(formatter::format_program(&program_from_json(&value)?))
+}
+
+// the formatted source of a program serialized by `to_sexp`
+pub fn from_sexp(input: &str) -> std::result::Result<String, Box<dyn std::error::Error>> {
+    Ok(formatter::format_program(&program_from_sexp(input)?))
+}
+
+pub fn program_to_json(program: &Program) -> Value {
+    json!({"type": "Program", "statements": statements(&program.statements)})
+}
+
+pub fn program_from_json(value: &Value) -> Result<Program> {
+    let node = Fields::new(value)?;
+    match node.ty {
+        "Program" => Ok(Program {
+            statements: node.list("statements", statement_from_json)?,
+        }),
+        ty => Err(format!("expected a Program, got {}", ty)),
+    }
+}
+
+pub fn program_to_sexp(program: &Program) -> String {
+    let mut res = String::new();
+    write_sexp(&program_to_json(program), &mut res);
+    res
+}
+
+pub fn program_from_sexp(input: &str) -> Result<Program> {
+    let mut reader = SexpReader {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.pos < reader.chars.len() {
+        return Err(reader.unexpected());
+    }
+    program_from_json(&value)
+}
+
+// to JSON
+
+fn statements(stmts: &[Statement]) -> Value {
+    Value::Array(stmts.iter().map(statement).collect())
+}
+
+fn statement(stmt: &Statement) -> Value {
+    match stmt {
+        Statement::Let {
+            identifier,
+            expression: e,
+        } => json!({"type": "Let", "identifier": identifier.0, "expression": expression(e)}),
+        Statement::Const {
+            identifier,
+            expression: e,
+        } => json!({"type": "Const", "identifier": identifier.0, "expression": expression(e)}),
+        Statement::Destructure {
+            pattern: p,
+            expression: e,
+        } => json!({"type": "Destructure", "pattern": pattern(p), "expression": expression(e)}),
+        Statement::Import { path, binding } => {
+            let binding = match binding {
+                ImportBinding::Module(id) => json!({"type": "Module", "name": id.0}),
+                ImportBinding::Names(ids) => {
+                    json!({"type": "Names", "names": ids.iter().map(|id| &id.0).collect::<Vec<_>>()})
+                }
+            };
+            json!({"type": "Import", "path": path, "binding": binding})
+        }
+        Statement::Export(stmt) => json!({"type": "Export", "statement": statement(stmt)}),
+        Statement::Return(e) => json!({"type": "Return", "expression": expression(e)}),
+        Statement::Expression(e) => json!({"type": "Expression", "expression": expression(e)}),
+        Statement::Block(b) => json!({"type": "Block", "statements": block(b)}),
+        Statement::Commented(c, stmt) => {
+            json!({"type": "Commented", "comments": comments(c), "statement": statement(stmt)})
+        }
+        Statement::Comment(text) => json!({"type": "Comment", "text": text}),
+    }
+}
+
+fn comments(comments: &Comments) -> Value {
+    json!({"type": "Comments", "leading": comments.leading, "trailing": comments.trailing})
+}
+
+fn block(block: &BlockStatement) -> Value {
+    statements(&block.statements)
+}
+
+fn expressions(exprs: &[Expression]) -> Value {
+    Value::Array(exprs.iter().map(expression).collect())
+}
+
+fn expression(expr: &Expression) -> Value {
+    match expr {
+        Expression::Identifier(id) => identifier(id),
+        Expression::Integer(n) => json!({"type": "Integer", "value": n}),
+        Expression::Boolean(b) => json!({"type": "Boolean", "value": b}),
+        Expression::String(s) => json!({"type": "String", "value": s}),
+        Expression::Array(elements) => json!({"type": "Array", "elements": expressions(elements)}),
+        Expression::Hash(entries) => {
+            let entries = entries
+                .iter()
+                .map(|entry| match entry {
+                    HashEntry::Pair(k, v) => {
+                        json!({"type": "Pair", "key": expression(k), "value": expression(v)})
+                    }
+                    HashEntry::Spread(e) => json!({"type": "Spread", "expression": expression(e)}),
+                })
+                .collect::<Vec<_>>();
+            json!({"type": "Hash", "entries": entries})
+        }
+        Expression::Spread(e) => json!({"type": "Spread", "expression": expression(e)}),
+        Expression::Prefix { operator, right } => json!({
+            "type": "Prefix",
+            "operator": operator.to_string(),
+            "right": expression(right),
+        }),
+        Expression::Infix {
+            left,
+            operator,
+            right,
+        } => json!({
+            "type": "Infix",
+            "left": expression(left),
+            "operator": operator.to_string(),
+            "right": expression(right),
+        }),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+        } => json!({
+            "type": "If",
+            "condition": expression(condition),
+            "consequence": block(consequence),
+            "alternative": alternative.as_ref().map(block),
+        }),
+        Expression::Try {
+            block: b,
+            catch,
+            finally,
+        } => {
+            let catch = catch.as_ref().map(
+                |c| json!({"type": "Catch", "identifier": c.identifier.0, "body": block(&c.body)}),
+            );
+            json!({
+                "type": "Try",
+                "block": block(b),
+                "catch": catch,
+                "finally": finally.as_ref().map(block),
+            })
+        }
+        Expression::Function(f) => function("Function", &f.params, &f.body),
+        Expression::Call { function: f, args } => {
+            let f = match f {
+                CallExpressionFunction::Identifier(id) => identifier(id),
+                CallExpressionFunction::Function(f) => function("Function", &f.params, &f.body),
+                CallExpressionFunction::Expression(e) => expression(e),
+            };
+            json!({"type": "Call", "function": f, "args": expressions(args)})
+        }
+        Expression::Index { left, index } => {
+            json!({"type": "Index", "left": expression(left), "index": expression(index)})
+        }
+        Expression::OptionalIndex { left, index } => {
+            json!({"type": "OptionalIndex", "left": expression(left), "index": expression(index)})
+        }
+        Expression::Member { left, name } => {
+            json!({"type": "Member", "left": expression(left), "name": name.0})
+        }
+        Expression::OptionalCall { function: f, args } => {
+            json!({"type": "OptionalCall", "function": expression(f), "args": expressions(args)})
+        }
+        Expression::Match { value, arms } => {
+            let arms = arms
+                .iter()
+                .map(|arm| {
+                    json!({
+                        "type": "Arm",
+                        "pattern": pattern(&arm.pattern),
+                        "guard": arm.guard.as_ref().map(expression),
+                        "body": expression(&arm.body),
+                    })
+                })
+                .collect::<Vec<_>>();
+            json!({"type": "Match", "value": expression(value), "arms": arms})
+        }
+        Expression::Quote(e) => json!({"type": "Quote", "expression": expression(e)}),
+        Expression::Unquote(e) => json!({"type": "Unquote", "expression": expression(e)}),
+        Expression::Macro(m) => function("Macro", &m.params, &m.body),
+        Expression::Commented(c, expr) => {
+            json!({"type": "Commented", "comments": comments(c), "expression": expression(expr)})
+        }
+    }
+}
+
+fn identifier(id: &Identifier) -> Value {
+    json!({"type": "Identifier", "name": id.0})
+}
+
+fn function(ty: &str, params: &[Parameter], body: &BlockStatement) -> Value {
+    let params = params.iter().map(parameter).collect::<Vec<_>>();
+    json!({"type": ty, "params": params, "body": block(body)})
+}
+
+fn parameter(param: &Parameter) -> Value {
+    match param {
+        Parameter::Identifier(id) => identifier(id),
+        Parameter::Default(id, e) => {
+            json!({"type": "Default", "name": id.0, "default": expression(e)})
+        }
+        Parameter::Rest(id) => json!({"type": "Rest", "name": id.0}),
+        Parameter::Pattern(p) => json!({"type": "Pattern", "pattern": pattern(p)}),
+    }
+}
+
+fn pattern(p: &Pattern) -> Value {
+    match p {
+        Pattern::Identifier(id) => identifier(id),
+        Pattern::Wildcard => json!({"type": "Wildcard"}),
+        Pattern::Literal(e) => json!({"type": "Literal", "expression": expression(e)}),
+        Pattern::Array { elements, rest } => json!({
+            "type": "Array",
+            "elements": elements.iter().map(pattern).collect::<Vec<_>>(),
+            "rest": rest.as_ref().map(|id| &id.0),
+        }),
+        Pattern::Hash(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(key, p)| json!({"type": "Field", "key": key.0, "pattern": pattern(p)}))
+                .collect::<Vec<_>>();
+            json!({"type": "Hash", "fields": fields})
+        }
+    }
+}
+
+// from JSON
+
+// the fields of a node
+struct Fields<'a> {
+    ty: &'a str,
+    map: &'a Map<String, Value>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(value: &'a Value) -> Result<Self> {
+        let map = value
+            .as_object()
+            .ok_or_else(|| format!("expected a node, got `{}`", value))?;
+        let ty = map
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("missing \"type\" in `{}`", value))?;
+        Ok(Self { ty, map })
+    }
+
+    fn get(&self, key: &str) -> Result<&'a Value> {
+        self.map
+            .get(key)
+            .ok_or_else(|| format!("missing \"{}\" in {}", key, self.ty))
+    }
+
+    fn node<T>(&self, key: &str, f: fn(&Value) -> Result<T>) -> Result<T> {
+        f(self.get(key)?)
+    }
+
+    fn boxed(&self, key: &str) -> Result<Box<Expression>> {
+        self.node(key, expression_from_json).map(Box::new)
+    }
+
+    // `null` if absent
+    fn optional<T>(&self, key: &str, f: fn(&Value) -> Result<T>) -> Result<Option<T>> {
+        match self.map.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => f(value).map(Some),
+        }
+    }
+
+    fn list<T>(&self, key: &str, f: fn(&Value) -> Result<T>) -> Result<Vec<T>> {
+        list_from_json(self.get(key)?, f)
+    }
+
+    fn string(&self, key: &str) -> Result<String> {
+        self.node(key, string_from_json)
+    }
+
+    fn identifier(&self, key: &str) -> Result<Identifier> {
+        self.string(key).map(Identifier)
+    }
+
+    fn unknown(&self, kind: &str) -> Error {
+        format!("unknown {} type {}", kind, self.ty)
+    }
+}
+
+fn list_from_json<T>(value: &Value, f: fn(&Value) -> Result<T>) -> Result<Vec<T>> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("expected an array, got `{}`", value))?
+        .iter()
+        .map(f)
+        .collect()
+}
+
+fn string_from_json(value: &Value) -> Result<String> {
+    value
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| format!("expected a string, got `{}`", value))
+}
+
+fn identifier_from_json(value: &Value) -> Result<Identifier> {
+    string_from_json(value).map(Identifier)
+}
+
+fn block_from_json(value: &Value) -> Result<BlockStatement> {
+    Ok(BlockStatement {
+        statements: list_from_json(value, statement_from_json)?,
+    })
+}
+
+fn statement_from_json(value: &Value) -> Result<Statement> {
+    let node = Fields::new(value)?;
+    let stmt = match node.ty {
+        "Let" => Statement::Let {
+            identifier: node.identifier("identifier")?,
+            expression: node.node("expression", expression_from_json)?,
+        },
+        "Const" => Statement::Const {
+            identifier: node.identifier("identifier")?,
+            expression: node.node("expression", expression_from_json)?,
+        },
+        "Destructure" => Statement::Destructure {
+            pattern: node.node("pattern", pattern_from_json)?,
+            expression: node.node("expression", expression_from_json)?,
+        },
+        "Import" => Statement::Import {
+            path: node.string("path")?,
+            binding: node.node("binding", |value| {
+                let binding = Fields::new(value)?;
+                match binding.ty {
+                    "Module" => Ok(ImportBinding::Module(binding.identifier("name")?)),
+                    "Names" => Ok(ImportBinding::Names(
+                        binding.list("names", identifier_from_json)?,
+                    )),
+                    _ => Err(binding.unknown("import binding")),
+                }
+            })?,
+        },
+        "Export" => Statement::Export(Box::new(node.node("statement", statement_from_json)?)),
+        "Return" => Statement::Return(node.node("expression", expression_from_json)?),
+        "Expression" => Statement::Expression(node.node("expression", expression_from_json)?),
+        "Block" => Statement::Block(node.node("statements", block_from_json)?),
+        "Commented" => Statement::Commented(
+            node.node("comments", comments_from_json)?,
+            Box::new(node.node("statement", statement_from_json)?),
+        ),
+        "Comment" => Statement::Comment(node.string("text")?),
+        _ => return Err(node.unknown("statement")),
+    };
+    Ok(stmt)
+}
+
+fn comments_from_json(value: &Value) -> Result<Comments> {
+    let node = Fields::new(value)?;
+    match node.ty {
+        "Comments" => Ok(Comments {
+            leading: node.list("leading", string_from_json)?,
+            trailing: node.list("trailing", string_from_json)?,
+        }),
+        _ => Err(node.unknown("comments")),
+    }
+}
+
+fn expression_from_json(value: &Value) -> Result<Expression> {
+    let node = Fields::new(value)?;
+    let expr = match node.ty {
+        "Identifier" => Expression::Identifier(node.identifier("name")?),
+        "Integer" => Expression::Integer(node.node("value", |value| {
+            value
+                .as_i64()
+                .ok_or_else(|| format!("expected an integer, got `{}`", value))
+        })?),
+        "Boolean" => Expression::Boolean(node.node("value", |value| {
+            value
+                .as_bool()
+                .ok_or_else(|| format!("expected a boolean, got `{}`", value))
+        })?),
+        "String" => Expression::String(node.string("value")?),
+        "Array" => Expression::Array(node.list("elements", expression_from_json)?),
+        "Hash" => Expression::Hash(node.list("entries", |value| {
+            let entry = Fields::new(value)?;
+            match entry.ty {
+                "Pair" => Ok(HashEntry::Pair(
+                    entry.node("key", expression_from_json)?,
+                    entry.node("value", expression_from_json)?,
+                )),
+                "Spread" => Ok(HashEntry::Spread(
+                    entry.node("expression", expression_from_json)?,
+                )),
+                _ => Err(entry.unknown("hash entry")),
+            }
+        })?),
+        "Spread" => Expression::Spread(node.boxed("expression")?),
+        "Prefix" => Expression::Prefix {
+            operator: match node.string("operator")?.as_str() {
+                "!" => PrefixOperator::Bang,
+                "-" => PrefixOperator::Minus,
+                op => return Err(format!("unknown prefix operator `{}`", op)),
+            },
+            right: node.boxed("right")?,
+        },
+        "Infix" => Expression::Infix {
+            left: node.boxed("left")?,
+            operator: match node.string("operator")?.as_str() {
+                "+" => InfixOperator::Add,
+                "-" => InfixOperator::Sub,
+                "*" => InfixOperator::Mul,
+                "/" => InfixOperator::Div,
+                "<" => InfixOperator::LT,
+                ">" => InfixOperator::GT,
+                "==" => InfixOperator::Eq,
+                "!=" => InfixOperator::NotEq,
+                "??" => InfixOperator::NullCoalescing,
+                op => return Err(format!("unknown infix operator `{}`", op)),
+            },
+            right: node.boxed("right")?,
+        },
+        "If" => Expression::If {
+            condition: node.boxed("condition")?,
+            consequence: node.node("consequence", block_from_json)?,
+            alternative: node.optional("alternative", block_from_json)?,
+        },
+        "Try" => Expression::Try {
+            block: node.node("block", block_from_json)?,
+            catch: node.optional("catch", |value| {
+                let catch = Fields::new(value)?;
+                Ok(CatchClause {
+                    identifier: catch.identifier("identifier")?,
+                    body: catch.node("body", block_from_json)?,
+                })
+            })?,
+            finally: node.optional("finally", block_from_json)?,
+        },
+        "Function" => Expression::Function(FunctionExpression {
+            params: node.list("params", parameter_from_json)?,
+            body: node.node("body", block_from_json)?,
+        }),
+        "Call" => Expression::Call {
+            function: match node.node("function", expression_from_json)? {
+                Expression::Identifier(id) => CallExpressionFunction::Identifier(id),
+                Expression::Function(f) => CallExpressionFunction::Function(f),
+                e => CallExpressionFunction::Expression(Box::new(e)),
+            },
+            args: node.list("args", expression_from_json)?,
+        },
+        "Index" => Expression::Index {
+            left: node.boxed("left")?,
+            index: node.boxed("index")?,
+        },
+        "OptionalIndex" => Expression::OptionalIndex {
+            left: node.boxed("left")?,
+            index: node.boxed("index")?,
+        },
+        "Member" => Expression::Member {
+            left: node.boxed("left")?,
+            name: node.identifier("name")?,
+        },
+        "OptionalCall" => Expression::OptionalCall {
+            function: node.boxed("function")?,
+            args: node.list("args", expression_from_json)?,
+        },
+        "Match" => Expression::Match {
+            value: node.boxed("value")?,
+            arms: node.list("arms", |value| {
+                let arm = Fields::new(value)?;
+                Ok(MatchArm {
+                    pattern: arm.node("pattern", pattern_from_json)?,
+                    guard: arm.optional("guard", expression_from_json)?,
+                    body: arm.node("body", expression_from_json)?,
+                })
+            })?,
+        },
+        "Quote" => Expression::Quote(node.boxed("expression")?),
+        "Unquote" => Expression::Unquote(node.boxed("expression")?),
+        "Macro" => Expression::Macro(MacroExpression {
+            params: node.list("params", parameter_from_json)?,
+            body: node.node("body", block_from_json)?,
+        }),
+        "Commented" => Expression::Commented(
+            node.node("comments", comments_from_json)?,
+            node.boxed("expression")?,
+        ),
+        _ => return Err(node.unknown("expression")),
+    };
+    Ok(expr)
+}
+
+fn parameter_from_json(value: &Value) -> Result<Parameter> {
+    let node = Fields::new(value)?;
+    let param = match node.ty {
+        "Identifier" => Parameter::Identifier(node.identifier("name")?),
+        "Default" => Parameter::Default(
+            node.identifier("name")?,
+            node.node("default", expression_from_json)?,
+        ),
+        "Rest" => Parameter::Rest(node.identifier("name")?),
+        "Pattern" => Parameter::Pattern(node.node("pattern", pattern_from_json)?),
+        _ => return Err(node.unknown("parameter")),
+    };
+    Ok(param)
+}
+
+fn pattern_from_json(value: &Value) -> Result<Pattern> {
+    let node = Fields::new(value)?;
+    let p = match node.ty {
+        "Identifier" => Pattern::Identifier(node.identifier("name")?),
+        "Wildcard" => Pattern::Wildcard,
+        "Literal" => Pattern::Literal(node.node("expression", expression_from_json)?),
+        "Array" => Pattern::Array {
+            elements: node.list("elements", pattern_from_json)?,
+            rest: node.optional("rest", identifier_from_json)?,
+        },
+        "Hash" => Pattern::Hash(node.list("fields", |value| {
+            let field = Fields::new(value)?;
+            Ok((
+                field.identifier("key")?,
+                field.node("pattern", pattern_from_json)?,
+            ))
+        })?),
+        _ => return Err(node.unknown("pattern")),
+    };
+    Ok(p)
+}
+
+// S-expressions
+
+fn write_sexp(value: &Value, res: &mut String) {
+    match value {
+        Value::Null => res.push_str("nil"),
+        Value::Array(values) => {
+            res.push('[');
+            for (i, v) in values.iter().enumerate() {
+                if i > 0 {
+                    res.push(' ');
+                }
+                write_sexp(v, res);
+            }
+            res.push(']');
+        }
+        Value::Object(map) => {
+            res.push('(');
+            res.push_str(map.get("type").and_then(Value::as_str).unwrap_or_default());
+            for (k, v) in map.iter().filter(|(k, _)| k.as_str() != "type") {
+                res.push_str(" :");
+                res.push_str(k);
+                res.push(' ');
+                write_sexp(v, res);
+            }
+            res.push(')');
+        }
+        // booleans, numbers and strings as in JSON
+        v => res.push_str(&v.to_string()),
+    }
+}
+
+// reads S-expressions into the JSON form
+struct SexpReader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl SexpReader {
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let mut map = Map::new();
+                map.insert("type".into(), Value::String(self.symbol()?));
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(')') => break,
+                        Some(':') => {
+                            self.pos += 1;
+                            let key = self.symbol()?;
+                            let value = self.value()?;
+                            map.insert(key, value);
+                        }
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+                self.pos += 1;
+                Ok(Value::Object(map))
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(']') {
+                        break;
+                    }
+                    values.push(self.value()?);
+                }
+                self.pos += 1;
+                Ok(Value::Array(values))
+            }
+            Some('"') => {
+                let start = self.pos;
+                self.pos += 1;
+                loop {
+                    match self.peek() {
+                        Some('"') => break,
+                        Some('\\') => self.pos += 2,
+                        Some(_) => self.pos += 1,
+                        None => return Err(self.unexpected()),
+                    }
+                }
+                self.pos += 1;
+                let s = self.chars[start..self.pos].iter().collect::<String>();
+                serde_json::from_str(&s).map_err(|e| format!("invalid string {}: {}", s, e))
+            }
+            Some(ch) if ch == '-' || ch.is_ascii_digit() => {
+                let start = self.pos;
+                self.pos += 1;
+                while self.peek().filter(char::is_ascii_digit).is_some() {
+                    self.pos += 1;
+                }
+                let s = self.chars[start..self.pos].iter().collect::<String>();
+                let n = s
+                    .parse::<i64>()
+                    .map_err(|e| format!("invalid integer `{}`: {}", s, e))?;
+                Ok(n.into())
+            }
+            Some(_) => match self.symbol()?.as_str() {
+                "nil" => Ok(Value::Null),
+                "true" => Ok(true.into()),
+                "false" => Ok(false.into()),
+                s => Err(format!("unknown symbol `{}`", s)),
+            },
+            None => Err(self.unexpected()),
+        }
+    }
+
+    fn symbol(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .filter(|ch| ch.is_alphanumeric() || *ch == '_')
+            .is_some()
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.unexpected());
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().filter(|ch| ch.is_whitespace()).is_some() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.peek() {
+            Some(ch) => format!("unexpected `{}` at {}", ch, self.pos),
+            None => "unexpected end of input".into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::serialize::{
+        from_json, from_sexp, program_from_json, program_from_sexp, program_to_json,
+        program_to_sexp, to_json, to_sexp,
+    };
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let inputs = vec![
+            r#"let x = -1 + 2 * 3; const y = !true; let [a, [b], ...c] = [1, [2], ...d];"#,
+            r#"let {name, age: [years]} = {"name": "a", ...h, 1: 2};"#,
+            r#"import "lib.mk" as lib; import {f, g} from "b.mk"; export let z = lib.f(1);"#,
+            r#"if (a < b) { a } else { b }; if (a != b ?? c) { return a; }"#,
+            r#"try { throw("e") } catch (e) { e } finally { puts(1) }; try { 1 } finally { 2 }"#,
+            r#"let f = fn(a, [c], b = 2, ...d) { a }; f(1, ...xs); fn(x) { x }(1); h["f"](2);"#,
+            r#"a?.[1]; a?.(1); a[0]; quote(unquote(a) + 1); macro(x, y) { quote(1) };"#,
+            r#"match (x) { 1 => "a", -2 => "b", [a, _] if a > 1 => a, {k} => k, _ => "\" }"#,
+            r#"let a = 1; // comment"#,
+            "[1, /* one */ 2]; f(1, // x\n 2); {\"a\": /* b */ 1}",
+        ];
+        for input in inputs {
+            let program = parse(Lexer::new_with_comments(input)).unwrap();
+            let json = program_to_json(&program);
+            assert_eq!(program_from_json(&json), Ok(program.clone()), "{}", input);
+            let sexp = program_to_sexp(&program);
+            assert_eq!(program_from_sexp(&sexp), Ok(program), "{}", sexp);
+        }
+    }
+
+    #[test]
+    fn serialized_forms() {
+        let program = parse(Lexer::new(r#"let x = f(1, "a");"#)).unwrap();
+        assert_eq!(
+            program_to_json(&program),
+            json!({
+                "type": "Program",
+                "statements": [{
+                    "type": "Let",
+                    "identifier": "x",
+                    "expression": {
+                        "type": "Call",
+                        "function": {"type": "Identifier", "name": "f"},
+                        "args": [
+                            {"type": "Integer", "value": 1},
+                            {"type": "String", "value": "a"},
+                        ],
+                    },
+                }],
+            })
+        );
+        assert_eq!(
+            program_to_sexp(&program),
+            r#"(Program :statements [(Let :identifier "x" :expression (Call :function (Identifier :name "f") :args [(Integer :value 1) (String :value "a")]))])"#
+        );
+        // back to formatted source
+        let input = "let x = f(1,2);\n// c";
+        let expected = "let x = f(1, 2);\n// c\n";
+        assert_eq!(from_json(&to_json(input).unwrap()).unwrap(), expected);
+        assert_eq!(from_sexp(&to_sexp(input).unwrap()).unwrap(), expected);
+    }
+
+    #[test]
+    fn deserialize_errors() {
+        let tests = vec![
+            (json!([]), "expected a node, got `[]`"),
+            (
+                json!({"statements": []}),
+                r#"missing "type" in `{"statements":[]}`"#,
+            ),
+            (
+                json!({"type": "Program", "statements": [{"type": "Loop"}]}),
+                "unknown statement type Loop",
+            ),
+            (
+                json!({"type": "Program", "statements": [{"type": "Return"}]}),
+                r#"missing "expression" in Return"#,
+            ),
+            (
+                json!({"type": "Program", "statements": [{"type": "Expression", "expression": {
+                    "type": "Infix",
+                    "left": {"type": "Integer", "value": 1},
+                    "operator": "%",
+                    "right": {"type": "Integer", "value": 2},
+                }}]}),
+                "unknown infix operator `%`",
+            ),
+        ];
+        for (value, expected) in tests {
+            assert_eq!(program_from_json(&value), Err(expected.into()));
+        }
+        let tests = vec![
+            ("(Program :statements [", "unexpected end of input"),
+            ("(Program :statements []) x", "unexpected `x` at 25"),
+            ("(Program :statements [yes])", "unknown symbol `yes`"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(program_from_sexp(input), Err(expected.into()));
+        }
+    }
+}
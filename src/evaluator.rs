@@ -1,11 +1,17 @@
-mod builtins;
+pub(crate) mod builtins;
+mod hygiene;
+mod json;
 mod macro_expansion;
+mod modules;
+pub(crate) mod prelude;
 mod quote_unquote;
 use crate::ast::{self};
 use crate::evaluator::quote_unquote::quote;
-use crate::object::{Environment, HashKey, Object};
-pub use macro_expansion::{define_macros, expand_macros};
-use std::collections::{BTreeMap, HashMap};
+use crate::object::{Environment, ErrorKind, HashKey, Object};
+pub use builtins::{Capabilities, Context, ContextGuard};
+use indexmap::IndexMap;
+pub use macro_expansion::{define_macros, expand_macros, expand_macros_traced};
+pub use modules::run_file;
 use std::convert::TryFrom;
 
 // eval
@@ -24,12 +30,15 @@ fn eval_program(program: ast::Program, env: &mut Environment) -> Object {
     let stmts = program.statements;
     let mut res = null_object();
     for s in stmts {
+        if let ast::Statement::Comment(_) = s {
+            continue;
+        }
         res = eval_statement(s, env);
         match res {
             Object::Return(_) => {
                 return unwrap_return_value(res);
             }
-            Object::Error(_) => {
+            Object::Error(..) | Object::Throw(_) => {
                 return res;
             }
             _ => {}
@@ -47,8 +56,21 @@ fn eval_statement(stmt: ast::Statement, env: &mut Environment) -> Object {
         ast::Statement::Let {
             identifier,
             expression,
-        } => eval_let_statement(identifier, expression, env),
+        } => eval_let_statement(identifier, expression, false, env),
+        ast::Statement::Const {
+            identifier,
+            expression,
+        } => eval_let_statement(identifier, expression, true, env),
+        ast::Statement::Destructure {
+            pattern,
+            expression,
+        } => eval_destructure_statement(pattern, expression, env),
+        ast::Statement::Import { path, binding } => eval_import_statement(path, binding, env),
+        // the exports are collected when the file is imported
+        ast::Statement::Export(stmt) => eval_statement(*stmt, env),
         ast::Statement::Block(it) => eval_block_statement(it, env),
+        ast::Statement::Commented(_, stmt) => eval_statement(*stmt, env),
+        ast::Statement::Comment(_) => null_object(),
     }
 }
 
@@ -60,21 +82,154 @@ fn eval_return_statement(expr: ast::Expression, env: &mut Environment) -> Object
     Object::Return(Box::new(v))
 }
 
-fn eval_let_statement(id: ast::Identifier, expr: ast::Expression, env: &mut Environment) -> Object {
-    match expr {
-        ast::Expression::Function(f) => eval_let_function_statement(id, f, env),
-        expr => {
-            let obj = eval_expression(expr, env);
-            if obj.is_error() {
-                return obj;
+fn eval_let_statement(
+    id: ast::Identifier,
+    expr: ast::Expression,
+    is_const: bool,
+    env: &mut Environment,
+) -> Object {
+    if let Err(e) = check_not_const(&id, env) {
+        return *e;
+    }
+    let obj = match expr {
+        ast::Expression::Function(f) => eval_let_function_expression(id.clone(), f, env),
+        expr => eval_expression(expr, env),
+    };
+    if obj.is_error() {
+        return obj;
+    }
+    if is_const {
+        env.set_const(&id, obj);
+    } else {
+        env.set(&id, obj);
+    }
+    Object::Let
+}
+
+fn check_not_const(id: &ast::Identifier, env: &Environment) -> Result<(), Box<Object>> {
+    if env.is_const(id) {
+        return Err(new_error_object(
+            ErrorKind::Name,
+            format!("cannot reassign constant `{}`", id),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn eval_import_statement(
+    path: String,
+    binding: ast::ImportBinding,
+    env: &mut Environment,
+) -> Object {
+    let m = modules::import(&path);
+    if m.is_error() {
+        return m;
+    }
+    match binding {
+        ast::ImportBinding::Module(id) => {
+            if let Err(e) = check_not_const(&id, env) {
+                return *e;
+            }
+            env.set(&id, m);
+        }
+        ast::ImportBinding::Names(names) => {
+            for id in names {
+                let value = index_object(m.clone(), Object::String(id.0.clone()));
+                if value.is_error() {
+                    return value;
+                }
+                if let Err(e) = check_not_const(&id, env) {
+                    return *e;
+                }
+                env.set(&id, value);
+            }
+        }
+    }
+    Object::Let
+}
+
+fn eval_destructure_statement(
+    pattern: ast::Pattern,
+    expr: ast::Expression,
+    env: &mut Environment,
+) -> Object {
+    let obj = eval_expression(expr, env);
+    if obj.is_error() {
+        return obj;
+    }
+    match bind_pattern(pattern, obj, env) {
+        Ok(()) => Object::Let,
+        Err(e) => *e,
+    }
+}
+
+fn bind_pattern(
+    pattern: ast::Pattern,
+    obj: Object,
+    env: &mut Environment,
+) -> Result<(), Box<Object>> {
+    let mismatch = |pattern: &ast::Pattern, obj: &Object, reason: String| {
+        Box::new(new_error_object(
+            ErrorKind::Type,
+            format!(
+                "cannot destructure `{}` with `{}`: {}",
+                obj, pattern, reason
+            ),
+        ))
+    };
+    match (pattern, obj) {
+        (ast::Pattern::Identifier(id), obj) => {
+            check_not_const(&id, env)?;
+            env.set(&id, obj)
+        }
+        (ast::Pattern::Wildcard, _) => {}
+        (ast::Pattern::Literal(expr), obj) => {
+            let expected = eval_expression(expr.clone(), &mut Environment::new());
+            if expected != obj {
+                let reason = format!("expected `{}`", expected);
+                return Err(mismatch(&ast::Pattern::Literal(expr), &obj, reason));
+            }
+        }
+        (ast::Pattern::Array { elements, rest }, Object::Array(values)) => {
+            let n = elements.len();
+            if values.len() < n || (rest.is_none() && values.len() > n) {
+                let pattern = ast::Pattern::Array { elements, rest };
+                let reason = format!("expected {} elements, got {}", n, values.len());
+                return Err(mismatch(&pattern, &Object::Array(values), reason));
             }
-            env.set(&id, obj.clone());
-            Object::Let
+            let mut values = values.into_iter();
+            for (p, v) in elements.into_iter().zip(values.by_ref()) {
+                bind_pattern(p, v, env)?;
+            }
+            if let Some(rest) = rest {
+                check_not_const(&rest, env)?;
+                env.set(&rest, Object::Array(values.collect()));
+            }
+        }
+        (ast::Pattern::Hash(fields), Object::Hash(hash)) => {
+            let key = |id: &ast::Identifier| HashKey::String(id.0.clone());
+            if let Some((id, _)) = fields.iter().find(|(id, _)| !hash.contains_key(&key(id))) {
+                let reason = format!("missing key \"{}\"", id);
+                let pattern = ast::Pattern::Hash(fields.clone());
+                return Err(mismatch(&pattern, &Object::Hash(hash), reason));
+            }
+            for (id, p) in fields {
+                let v = hash[&key(&id)].clone();
+                bind_pattern(p, v, env)?;
+            }
+        }
+        (pattern @ ast::Pattern::Array { .. }, obj) => {
+            return Err(mismatch(&pattern, &obj, "not an array".into()))
+        }
+        (pattern @ ast::Pattern::Hash(_), obj) => {
+            return Err(mismatch(&pattern, &obj, "not a hash".into()))
         }
     }
+    Ok(())
 }
 
-fn eval_let_function_statement(
+fn eval_let_function_expression(
     id: ast::Identifier,
     f: ast::FunctionExpression,
     env: &mut Environment,
@@ -90,9 +245,7 @@ fn eval_let_function_statement(
     let mut fenv = env.clone();
     fenv.set(&id, lf);
 
-    let f = eval_expression(f.into(), &mut fenv);
-    env.set(&id, f.clone());
-    Object::Let
+    eval_expression(f.into(), &mut fenv)
 }
 
 fn eval_block_statement(block: ast::BlockStatement, env: &mut Environment) -> Object {
@@ -100,12 +253,15 @@ fn eval_block_statement(block: ast::BlockStatement, env: &mut Environment) -> Ob
     let stmts = block.statements;
     let mut res = null_object();
     for s in stmts {
+        if let ast::Statement::Comment(_) = s {
+            continue;
+        }
         res = eval_statement(s, &mut env);
         match &res {
             Object::Return(_) => {
                 return res;
             }
-            Object::Error(_) => {
+            Object::Error(..) | Object::Throw(_) => {
                 return res;
             }
             _ => {}
@@ -126,6 +282,11 @@ fn eval_expression(expr: ast::Expression, env: &mut Environment) -> Object {
         ast::Expression::Prefix { operator, right } => {
             eval_prefix_expression(operator, *right, env)
         }
+        ast::Expression::Infix {
+            left,
+            operator: ast::InfixOperator::NullCoalescing,
+            right,
+        } => eval_null_coalescing_expression(*left, *right, env),
         ast::Expression::Infix {
             left,
             operator,
@@ -136,27 +297,63 @@ fn eval_expression(expr: ast::Expression, env: &mut Environment) -> Object {
             consequence,
             alternative,
         } => eval_if_expression(*condition, consequence, alternative, env),
+        ast::Expression::Try {
+            block,
+            catch,
+            finally,
+        } => eval_try_expression(block, catch, finally, env),
+        ast::Expression::Match { value, arms } => eval_match_expression(*value, arms, env),
         ast::Expression::Identifier(id) => eval_identifier_expression(id, env),
         ast::Expression::Function(expr) => eval_function_expression(expr, env),
+        expr @ ast::Expression::Index { .. }
+        | expr @ ast::Expression::OptionalIndex { .. }
+        | expr @ ast::Expression::Member { .. }
+        | expr @ ast::Expression::OptionalCall { .. }
+        | expr @ ast::Expression::Call {
+            function: ast::CallExpressionFunction::Expression(_),
+            ..
+        } => eval_chain(expr, env).unwrap_or(Object::Null),
         ast::Expression::Call { function, args } => eval_call_expression(function, args, env),
-        ast::Expression::Index { left, index } => eval_index_expression(*left, *index, env),
         ast::Expression::Quote(expr) => eval_quote_expression(*expr, env),
-        ast::Expression::Unquote(_) => {
-            new_error_object("`unquote` can only be used in `quote(...)`")
+        ast::Expression::Unquote(_) => new_error_object(
+            ErrorKind::Runtime,
+            "`unquote` can only be used in `quote(...)`",
+        ),
+        ast::Expression::Macro { .. } => {
+            new_error_object(ErrorKind::Runtime, "`macro` is not supported")
         }
-        ast::Expression::Macro { .. } => new_error_object("`macro` is not supported"),
+        ast::Expression::Spread(_) => new_error_object(
+            ErrorKind::Runtime,
+            "`...` can only be used in arrays, hashes and arguments",
+        ),
+        ast::Expression::Commented(_, expr) => eval_expression(*expr, env),
     }
 }
 
 fn eval_expressions(
     exprs: Vec<ast::Expression>,
     env: &mut Environment,
-) -> Result<Vec<Object>, Object> {
+) -> Result<Vec<Object>, Box<Object>> {
     let mut res = Vec::new();
     for expr in exprs {
+        // `[...a]`, `f(...a)`
+        if let ast::Expression::Spread(expr) = expr {
+            match eval_expression(*expr, env) {
+                Object::Array(elements) => res.extend(elements),
+                v if v.is_error() => return Err(v.into()),
+                v => {
+                    return Err(new_error_object(
+                        ErrorKind::Type,
+                        format!("cannot spread `{}`, not an array", v),
+                    )
+                    .into())
+                }
+            }
+            continue;
+        }
         let v = eval_expression(expr, env);
         if v.is_error() {
-            return Err(v);
+            return Err(v.into());
         }
         res.push(v);
     }
@@ -176,30 +373,41 @@ fn eval_string_expression(s: String) -> Object {
 }
 
 fn eval_array_expression(exprs: Vec<ast::Expression>, env: &mut Environment) -> Object {
-    let mut elements = Vec::new();
-    for expr in exprs {
-        let v = eval_expression(expr, env);
-        if v.is_error() {
-            return v;
-        }
-        elements.push(v);
+    match eval_expressions(exprs, env) {
+        Ok(elements) => Object::Array(elements),
+        Err(v) => *v,
     }
-    Object::Array(elements)
 }
 
-fn eval_hash_expression(
-    map: BTreeMap<ast::Expression, ast::Expression>,
-    env: &mut Environment,
-) -> Object {
-    let mut hash = HashMap::new();
-    for (k, v) in map {
+fn eval_hash_expression(entries: Vec<ast::HashEntry>, env: &mut Environment) -> Object {
+    let mut hash = IndexMap::new();
+    for entry in entries {
+        let (k, v) = match entry {
+            ast::HashEntry::Pair(k, v) => (k, v),
+            // `{...h}`, keys of `h` are overwritten by later entries
+            ast::HashEntry::Spread(h) => match eval_expression(h, env) {
+                Object::Hash(h) => {
+                    hash.extend(h);
+                    continue;
+                }
+                o if o.is_error() => return o,
+                o => {
+                    return new_error_object(
+                        ErrorKind::Type,
+                        format!("cannot spread `{}` into a hash", o),
+                    )
+                }
+            },
+        };
         let key = eval_expression(k, env);
         if key.is_error() {
             return key;
         }
         let key = match HashKey::try_from(key) {
             Ok(it) => it,
-            Err((_, o)) => return new_error_object(format!("unusable as hash key: `{}`", o)),
+            Err((_, o)) => {
+                return new_error_object(ErrorKind::Type, format!("unusable as hash key: `{}`", o))
+            }
         };
         let value = eval_expression(v, env);
         if value.is_error() {
@@ -236,7 +444,7 @@ fn eval_bang_prefix_operator_expression(right: Object) -> Object {
 fn eval_minus_prefix_operator_expression(right: Object) -> Object {
     match right {
         Object::Integer(n) => Object::Integer(-n).into(),
-        r => new_error_object(format!("unknown operator: `-{}`", r)),
+        r => new_error_object(ErrorKind::Type, format!("unknown operator: `-{}`", r)),
     }
 }
 
@@ -254,12 +462,27 @@ fn eval_infix_expression(
     if right.is_error() {
         return right;
     }
+    eval_infix_objects(op, left, right)
+}
 
+fn eval_infix_objects(op: ast::InfixOperator, left: Object, right: Object) -> Object {
     match (left, right) {
         (Object::Integer(l), Object::Integer(r)) => eval_integer_infix_expression(op, l, r),
         (Object::Boolean(l), Object::Boolean(r)) => eval_boolean_infix_expression(op, l, r),
         (Object::String(l), Object::String(r)) => eval_string_infix_expression(op, l, r),
-        (l, r) => new_error_object(format!("unknown operator: `{} {} {}`", l, op, r)),
+        (l, r) => eval_structural_infix_expression(op, l, r),
+    }
+}
+
+fn eval_structural_infix_expression(op: ast::InfixOperator, left: Object, right: Object) -> Object {
+    // arrays and hashes are compared element by element, different types are never equal
+    match op {
+        ast::InfixOperator::Eq => Object::Boolean(left == right),
+        ast::InfixOperator::NotEq => Object::Boolean(left != right),
+        _ => new_error_object(
+            ErrorKind::Type,
+            format!("unknown operator: `{} {} {}`", left, op, right),
+        ),
     }
 }
 
@@ -273,6 +496,10 @@ fn eval_integer_infix_expression(op: ast::InfixOperator, left: i64, right: i64)
         ast::InfixOperator::GT => Object::Boolean(left > right),
         ast::InfixOperator::Eq => Object::Boolean(left == right),
         ast::InfixOperator::NotEq => Object::Boolean(left != right),
+        ast::InfixOperator::NullCoalescing => new_error_object(
+            ErrorKind::Type,
+            format!("unknown operator: `{} {} {}`", left, op, right),
+        ),
     }
 }
 
@@ -280,7 +507,10 @@ fn eval_boolean_infix_expression(op: ast::InfixOperator, left: bool, right: bool
     match op {
         ast::InfixOperator::Eq => Object::Boolean(left == right),
         ast::InfixOperator::NotEq => Object::Boolean(left != right),
-        _ => new_error_object(format!("unknown operator: `{} {} {}`", left, op, right)),
+        _ => new_error_object(
+            ErrorKind::Type,
+            format!("unknown operator: `{} {} {}`", left, op, right),
+        ),
     }
 }
 
@@ -289,7 +519,24 @@ fn eval_string_infix_expression(op: ast::InfixOperator, left: String, right: Str
         ast::InfixOperator::Add => Object::String(left + &right),
         ast::InfixOperator::Eq => Object::Boolean(left == right),
         ast::InfixOperator::NotEq => Object::Boolean(left != right),
-        _ => new_error_object(format!("unknown operator: `{} {} {}`", left, op, right)),
+        _ => new_error_object(
+            ErrorKind::Type,
+            format!("unknown operator: `{} {} {}`", left, op, right),
+        ),
+    }
+}
+
+fn eval_null_coalescing_expression(
+    left: ast::Expression,
+    right: ast::Expression,
+    env: &mut Environment,
+) -> Object {
+    // <left> ?? <right>
+    // <right> is evaluated only if <left> is null
+    let left = eval_expression(left, env);
+    match left {
+        Object::Null => eval_expression(right, env),
+        _ => left,
     }
 }
 
@@ -312,13 +559,85 @@ fn eval_if_expression(
     }
 }
 
+fn eval_try_expression(
+    block: ast::BlockStatement,
+    catch: Option<ast::CatchClause>,
+    finally: Option<ast::BlockStatement>,
+    env: &mut Environment,
+) -> Object {
+    let mut res = eval_block_statement(block, env);
+    if let (true, Some(catch)) = (res.is_error(), catch) {
+        // catch (<identifier>) { <body> }
+        let mut catch_env = Environment::new_with_outer(env.clone());
+        catch_env.set(&catch.identifier, error_value(res));
+        res = eval_block_statement(catch.body, &mut catch_env);
+    }
+    if let Some(finally) = finally {
+        // `return` or an error in `finally` overrides the result
+        let finally_res = eval_block_statement(finally, env);
+        if let Object::Return(_) | Object::Error(..) | Object::Throw(_) = finally_res {
+            return finally_res;
+        }
+    }
+    res
+}
+
+fn eval_match_expression(
+    value: ast::Expression,
+    arms: Vec<ast::MatchArm>,
+    env: &mut Environment,
+) -> Object {
+    let value = eval_expression(value, env);
+    if value.is_error() {
+        return value;
+    }
+    for arm in arms {
+        // each arm binds into its own scope
+        let mut arm_env = Environment::new_with_outer(env.clone());
+        if bind_pattern(arm.pattern, value.clone(), &mut arm_env).is_err() {
+            continue;
+        }
+        if let Some(guard) = arm.guard {
+            let guard = eval_expression(guard, &mut arm_env);
+            if guard.is_error() {
+                return guard;
+            }
+            if !is_truthy(guard) {
+                continue;
+            }
+        }
+        return eval_expression(arm.body, &mut arm_env);
+    }
+    new_error_object(
+        ErrorKind::Match,
+        format!("no match arm matched `{}`", value),
+    )
+}
+
+fn error_value(err: Object) -> Object {
+    // the value bound by `catch (e)`
+    match err {
+        Object::Throw(value) => *value,
+        Object::Error(kind, message) => {
+            let mut hash = IndexMap::new();
+            hash.insert(
+                HashKey::String("kind".into()),
+                Object::String(kind.to_string()),
+            );
+            hash.insert(HashKey::String("message".into()), Object::String(message));
+            Object::Hash(hash)
+        }
+        other => other,
+    }
+}
+
 fn eval_identifier_expression(id: ast::Identifier, env: &Environment) -> Object {
     if let Some(v) = env.get(&id) {
         v
     } else if let Some(f) = builtins::get(&id) {
         Object::Builtin(f)
     } else {
-        new_error_object(format!("identifier not found: `{}`", id))
+        new_error_object(ErrorKind::Name, format!("identifier not found: `{}`", id))
     }
 }
 
@@ -335,61 +654,171 @@ fn eval_call_expression(
     env: &mut Environment,
 ) -> Object {
     let f = eval_expression(f.into(), env);
+    call_object(f, args, env)
+}
+
+fn call_object(f: Object, args: Vec<ast::Expression>, env: &mut Environment) -> Object {
     if f.is_error() {
         return f;
     }
     match eval_expressions(args, env) {
-        Ok(args) => match f {
-            Object::Function { body, params, env } => {
-                let mut env = extend_function_env(env, params, args);
-                unwrap_return_value(eval_statement(body.into(), &mut env))
-            }
-            Object::LetFunction {
-                id,
-                body,
-                params,
-                mut env,
-            } => {
-                // let f = fn(n) { if (n == 0) { 1 } else { n * f(n - 1) } };
-                //                                              ^^^^^^^^
-                // set <id> in `env` for recursive calls
-                env.set(
-                    &id,
-                    Object::LetFunction {
-                        id: id.clone(),
-                        body: body.clone(),
-                        params: params.clone(),
-                        env: env.clone(),
-                    },
-                );
-                let mut env = extend_function_env(env, params, args);
-                unwrap_return_value(eval_statement(body.into(), &mut env))
-            }
-            Object::Builtin(f) => f(args),
-            _ => new_error_object(format!("not a function: `{}`", f)),
+        Ok(args) => apply_function(f, args),
+        Err(v) => *v,
+    }
+}
+
+// the value of a chain of `[...]`, `.name` and `(...)`, None if a `?.` link
+// found null: the rest of the chain is skipped, e.g. `a?.["x"]["y"]`
+fn eval_chain(expr: ast::Expression, env: &mut Environment) -> Option<Object> {
+    match expr {
+        ast::Expression::Index { left, index } => {
+            let left = eval_chain(*left, env)?;
+            Some(eval_index_expression(left, *index, env))
+        }
+        ast::Expression::Member { left, name } => {
+            let left = eval_chain(*left, env)?;
+            let index = ast::Expression::String(name.0);
+            Some(eval_index_expression(left, index, env))
+        }
+        ast::Expression::Call {
+            function: ast::CallExpressionFunction::Expression(f),
+            args,
+        } => {
+            let f = eval_chain(*f, env)?;
+            Some(call_object(f, args, env))
+        }
+        // <left>?.[<index>]
+        // <index> is not evaluated if <left> is null
+        ast::Expression::OptionalIndex { left, index } => match eval_chain(*left, env)? {
+            Object::Null => None,
+            left => Some(eval_index_expression(left, *index, env)),
+        },
+        // <f>?.(<args>)
+        // <args> are not evaluated if <f> is null
+        ast::Expression::OptionalCall { function, args } => match eval_chain(*function, env)? {
+            Object::Null => None,
+            f => Some(call_object(f, args, env)),
+        },
+        expr => Some(eval_expression(expr, env)),
+    }
+}
+
+fn apply_function(f: Object, args: Vec<Object>) -> Object {
+    match f {
+        Object::Function { body, params, env } => match extend_function_env(env, params, args) {
+            Ok(mut env) => unwrap_return_value(eval_statement(body.into(), &mut env)),
+            Err(e) => *e,
         },
-        Err(v) => v,
+        Object::LetFunction {
+            id,
+            body,
+            params,
+            mut env,
+        } => {
+            // let f = fn(n) { if (n == 0) { 1 } else { n * f(n - 1) } };
+            //                                              ^^^^^^^^
+            // set <id> in `env` for recursive calls
+            env.set(
+                &id,
+                Object::LetFunction {
+                    id: id.clone(),
+                    body: body.clone(),
+                    params: params.clone(),
+                    env: env.clone(),
+                },
+            );
+            match extend_function_env(env, params, args) {
+                Ok(mut env) => unwrap_return_value(eval_statement(body.into(), &mut env)),
+                Err(e) => *e,
+            }
+        }
+        Object::Builtin(f) => f(args),
+        _ => new_error_object(ErrorKind::Type, format!("not a function: `{}`", f)),
     }
 }
 
 fn extend_function_env(
     env: Environment,
-    params: Vec<ast::Identifier>,
+    params: Vec<ast::Parameter>,
     args: Vec<Object>,
-) -> Environment {
+) -> Result<Environment, Box<Object>> {
     let mut env = Environment::new_with_outer(env);
-    for (param, arg) in params.into_iter().zip(args) {
-        env.set(&param, arg);
-    }
-    env
+    // default values see the preceding parameters
+    bind_params(params, args, &mut env, |expr, env| {
+        eval_expression(expr, env)
+    })?;
+    Ok(env)
 }
 
-fn eval_index_expression(
-    left: ast::Expression,
-    index: ast::Expression,
+// binds `args` to `params` in `env`, `default` evaluates the default value of
+// a parameter without an argument
+pub(crate) fn bind_params<F>(
+    params: Vec<ast::Parameter>,
+    args: Vec<Object>,
     env: &mut Environment,
-) -> Object {
-    let left = eval_expression(left, env);
+    mut default: F,
+) -> Result<(), Box<Object>>
+where
+    F: FnMut(ast::Expression, &mut Environment) -> Object,
+{
+    check_arity(&params, args.len())
+        .map_err(|e| Box::new(new_error_object(ErrorKind::Argument, e)))?;
+    let mut args = args.into_iter();
+    for param in params {
+        match param {
+            ast::Parameter::Identifier(id) => {
+                let arg = args.next().unwrap_or(Object::Null);
+                env.set(&id, arg);
+            }
+            ast::Parameter::Default(id, expr) => {
+                let arg = match args.next() {
+                    Some(arg) => arg,
+                    None => default(expr, env),
+                };
+                if arg.is_error() {
+                    return Err(arg.into());
+                }
+                env.set(&id, arg);
+            }
+            ast::Parameter::Rest(id) => env.set(&id, Object::Array(args.by_ref().collect())),
+            ast::Parameter::Pattern(p) => {
+                let arg = args.next().unwrap_or(Object::Null);
+                bind_pattern(p, arg, env)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_arity(params: &[ast::Parameter], n: usize) -> Result<(), String> {
+    let required = params
+        .iter()
+        .filter(|p| {
+            matches!(
+                p,
+                ast::Parameter::Identifier(_) | ast::Parameter::Pattern(_)
+            )
+        })
+        .count();
+    let has_rest = params.iter().any(|p| matches!(p, ast::Parameter::Rest(_)));
+    let max = params.len() - has_rest as usize;
+    if n >= required && (has_rest || n <= max) {
+        return Ok(());
+    }
+    let want = if has_rest {
+        format!("{} or more", required)
+    } else if required < max {
+        format!("{} to {}", required, max)
+    } else {
+        required.to_string()
+    };
+    Err(format!(
+        "wrong number of arguments. got={}, want={}",
+        n, want
+    ))
+}
+
+fn eval_index_expression(left: Object, index: ast::Expression, env: &mut Environment) -> Object {
     if left.is_error() {
         return left;
     }
@@ -397,10 +826,24 @@ fn eval_index_expression(
     if index.is_error() {
         return index;
     }
+    index_object(left, index)
+}
+
+fn index_object(left: Object, index: Object) -> Object {
     match (left, index) {
         (Object::Array(array), Object::Integer(idx)) => eval_array_index_expression(array, idx),
         (Object::Hash(hash), idx) => eval_hash_index_expression(hash, idx),
-        (l, _) => new_error_object(format!("index operator not supported: `{}`", l)),
+        (Object::Module { path, exports }, Object::String(name)) => match exports.get(&name) {
+            Some(value) => value.clone(),
+            None => new_error_object(
+                ErrorKind::Import,
+                format!("module \"{}\" has no export `{}`", path, name),
+            ),
+        },
+        (l, _) => new_error_object(
+            ErrorKind::Type,
+            format!("index operator not supported: `{}`", l),
+        ),
     }
 }
 
@@ -412,10 +855,12 @@ fn eval_array_index_expression(array: Vec<Object>, idx: i64) -> Object {
     array[idx].clone()
 }
 
-fn eval_hash_index_expression(hash: HashMap<HashKey, Object>, idx: Object) -> Object {
+fn eval_hash_index_expression(hash: IndexMap<HashKey, Object>, idx: Object) -> Object {
     let idx = match HashKey::try_from(idx) {
         Ok(it) => it,
-        Err((_, o)) => return new_error_object(format!("unusable as hash key: `{}`", o)),
+        Err((_, o)) => {
+            return new_error_object(ErrorKind::Type, format!("unusable as hash key: `{}`", o))
+        }
     };
     hash.get(&idx).cloned().unwrap_or(null_object())
 }
@@ -423,7 +868,7 @@ fn eval_hash_index_expression(hash: HashMap<HashKey, Object>, idx: Object) -> Ob
 fn eval_quote_expression(expr: ast::Expression, env: &mut Environment) -> Object {
     match quote(expr.into(), env) {
         Ok(quoted) => quoted,
-        Err(e) => new_error_object(&e),
+        Err(e) => new_error_object(ErrorKind::Runtime, e),
     }
 }
 
@@ -454,17 +899,17 @@ fn null_object() -> Object {
     Object::Null
 }
 
-fn new_error_object(s: impl Into<String>) -> Object {
-    Object::Error(s.into())
+fn new_error_object(kind: ErrorKind, s: impl Into<String>) -> Object {
+    Object::Error(kind, s.into())
 }
 
 #[cfg(test)]
 mod tests {
     use super::{eval, null_object, Environment};
     use crate::lexer::Lexer;
-    use crate::object::{HashKey, Object};
+    use crate::object::{ErrorKind, HashKey, Object};
     use crate::parser::parse;
-    use std::collections::HashMap;
+    use indexmap::IndexMap;
 
     #[test]
     fn eval_integer_expression() {
@@ -517,6 +962,19 @@ mod tests {
             (r#""foobar" == "foo""#, false),
             (r#""foo" == "foobar""#, false),
             (r#""" == """#, true),
+            ("[1, 2] == [1, 2]", true),
+            ("[1, 2] == [2, 1]", false),
+            ("[1, [2, 3]] != [1, [2, 3]]", false),
+            ("[] == []", true),
+            (r#"{"a": 1, "b": 2} == {"b": 2, "a": 1}"#, true),
+            (r#"{"a": [1]} == {"a": [2]}"#, false),
+            ("[][0] == {}[1]", true),
+            ("[][0] != [][0]", false),
+            ("[][0] == 0", false),
+            ("1 == true", false),
+            (r#"1 == "1""#, false),
+            (r#"1 != "1""#, true),
+            ("[1] == 1", false),
         ];
         for (input, expected) in tests {
             let v = test_eval(input);
@@ -612,11 +1070,29 @@ mod tests {
             (HashKey::Boolean(false), new_int(6)),
         ]
         .into_iter()
-        .collect::<HashMap<_, _>>();
+        .collect::<IndexMap<_, _>>();
         let v = test_eval(input);
         assert_eq!(v, Object::Hash(expected));
     }
 
+    #[test]
+    fn eval_hash_insertion_order() {
+        let tests = vec![
+            (r#"{"b": 1, "a": 2, "c": 3}"#, r#"{"b": 1, "a": 2, "c": 3}"#),
+            (r#"{3: 1, 1: 2, 2: 3}"#, r#"{3: 1, 1: 2, 2: 3}"#),
+            (r#"{"b": 1, "a": 2, "b": 3}"#, r#"{"b": 3, "a": 2}"#),
+            (
+                r#"let h = {"z": 1, true: 2, 0: 3}; keys(h)"#,
+                r#"["z", true, 0]"#,
+            ),
+            (r#"values({"z": 1, "a": 2})"#, "[1, 2]"),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v.inspect(), expected);
+        }
+    }
+
     #[test]
     fn eval_hash_index_expressio() {
         let tests = vec![
@@ -627,6 +1103,80 @@ mod tests {
             (r#"{5: 5}[5]"#, new_int(5)),
             (r#"{true: 5}[true]"#, new_int(5)),
             (r#"{false: 5}[false]"#, new_int(5)),
+            (r#"{[1, "a"]: 5}[[1, "a"]]"#, new_int(5)),
+            (r#"{[1, [true]]: 5}[[1, [true]]]"#, new_int(5)),
+            (r#"{[1, 2]: 5}[[2, 1]]"#, Object::Null),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v, expected);
+        }
+    }
+
+    #[test]
+    fn eval_null_coalescing_expression() {
+        let tests = vec![
+            ("1 ?? 2", new_int(1)),
+            ("[][0] ?? 2", new_int(2)),
+            ("false ?? 2", Object::Boolean(false)),
+            (r#"{"a": 1}["b"] ?? {"a": 1}["a"]"#, new_int(1)),
+            ("[][0] ?? [][1] ?? 3", new_int(3)),
+            ("1 ?? foobar", new_int(1)),
+            ("[][0] ?? 1 + 2", new_int(3)),
+            (
+                "[][0] ?? foobar",
+                Object::Error(ErrorKind::Name, "identifier not found: `foobar`".into()),
+            ),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v, expected);
+        }
+    }
+
+    #[test]
+    fn eval_optional_chaining_expression() {
+        let tests = vec![
+            (
+                r#"let cfg = {"db": {"host": "localhost"}}; cfg["db"]?.["host"]"#,
+                Object::String("localhost".into()),
+            ),
+            (r#"let cfg = {}; cfg["db"]?.["host"]"#, Object::Null),
+            (
+                r#"let cfg = {}; cfg["db"]?.["host"]?.["name"] ?? "default""#,
+                Object::String("default".into()),
+            ),
+            ("[1, 2]?.[1]", new_int(2)),
+            ("[][0]?.[foobar]", Object::Null),
+            (
+                r#"let f = {"double": fn(x) { x * 2 }}; f["double"]?.(2)"#,
+                new_int(4),
+            ),
+            (r#"let f = {}; f["double"]?.(foobar)"#, Object::Null),
+            (r#"len?.("abc")"#, new_int(3)),
+            (
+                "1?.[0]",
+                Object::Error(ErrorKind::Type, "index operator not supported: `1`".into()),
+            ),
+            (
+                "1?.(0)",
+                Object::Error(ErrorKind::Type, "not a function: `1`".into()),
+            ),
+            // the rest of the chain is skipped after a null
+            (r#"let a = {}; a["b"]?.["x"]["y"]"#, Object::Null),
+            (r#"let a = {}; a["b"]?.["x"].y(foobar)[0]"#, Object::Null),
+            (r#"let a = {}; a["b"]?.(1)["x"](2)"#, Object::Null),
+            (
+                r#"let a = {"b": {"x": {"y": 1}}}; a["b"]?.["x"]["y"]"#,
+                new_int(1),
+            ),
+            (
+                r#"let a = {"b": {}}; a["b"]?.["x"]["y"]"#,
+                Object::Error(
+                    ErrorKind::Type,
+                    "index operator not supported: `null`".into(),
+                ),
+            ),
         ];
         for (input, expected) in tests {
             let v = test_eval(input);
@@ -717,10 +1267,120 @@ mod tests {
                 "unknown operator: `true + false`",
             ),
             ("foobar", "identifier not found: `foobar`"),
+            ("[1] < [2]", "unknown operator: `[1] < [2]`"),
+            (
+                r#"{[1, fn(x) { x }]: 1}"#,
+                "unusable as hash key: `[1, fn(x) {\nx\n}]`",
+            ),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            match v {
+                Object::Error(_, message) => assert_eq!(message, expected),
+                v => panic!("expected error, got {:?}", v),
+            }
+        }
+    }
+
+    #[test]
+    fn eval_try_expression() {
+        let tests = vec![
+            ("try { 1 } catch (e) { 2 }", new_int(1)),
+            (
+                r#"try { foobar } catch (e) { e["message"] }"#,
+                new_string("identifier not found: `foobar`"),
+            ),
+            (
+                r#"try { foobar } catch (e) { e["kind"] }"#,
+                new_string("NameError"),
+            ),
+            (
+                r#"try { 1 + true } catch (e) { e["kind"] }"#,
+                new_string("TypeError"),
+            ),
+            (
+                r#"try { len(1, 2) } catch (e) { e["kind"] }"#,
+                new_string("ArgumentError"),
+            ),
+            (
+                r#"try { const a = 1; let a = 2; } catch (e) { e["kind"] }"#,
+                new_string("NameError"),
+            ),
+            (
+                r#"try { match (1) { 2 => 3 } } catch (e) { e["kind"] }"#,
+                new_string("MatchError"),
+            ),
+            (
+                r#"try { [...1] } catch (e) { e["kind"] }"#,
+                new_string("TypeError"),
+            ),
+            (
+                r#"try { throw("oops") } catch (e) { e["message"] }"#,
+                new_string("oops"),
+            ),
+            (
+                r#"try { throw("oops") } catch (e) { e["kind"] }"#,
+                new_string("Error"),
+            ),
+            (
+                r#"try { throw({"kind": "ConfigError", "message": "no db", "key": "db"}) } catch (e) { e["kind"] + ": " + e["key"] }"#,
+                new_string("ConfigError: db"),
+            ),
+            (r#"try { throw(42) } catch (e) { e["value"] }"#, new_int(42)),
+            (
+                r#"
+                let f = fn() { throw("deep"); 1 };
+                let g = fn() { f() + 1 };
+                try { g() } catch (e) { e["message"] }
+                "#,
+                new_string("deep"),
+            ),
+            (
+                r#"let a = 1; try { foobar } catch (e) { let a = 2; } a"#,
+                new_int(1),
+            ),
+            (
+                r#"try { try { foobar } catch (e) { throw("again") } } catch (e) { e["message"] }"#,
+                new_string("again"),
+            ),
+            (r#"try { 1 } finally { 2 }"#, new_int(1)),
+            (
+                r#"try { foobar } catch (e) { 1 } finally { 2 }"#,
+                new_int(1),
+            ),
+            (
+                r#"let f = fn() { try { return 1; } finally { 2 } 3 }; f()"#,
+                new_int(1),
+            ),
+            (
+                r#"let f = fn() { try { 1 } finally { return 2; } }; f()"#,
+                new_int(2),
+            ),
+            (
+                "try { foobar } finally { 1 }",
+                Object::Error(ErrorKind::Name, "identifier not found: `foobar`".into()),
+            ),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v, expected, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn eval_uncaught_throw() {
+        let tests = vec![
+            (r#"throw("oops"); 1"#, "Error: oops"),
+            (
+                r#"throw({"kind": "ConfigError", "message": "no db"})"#,
+                "ConfigError: no db",
+            ),
+            (r#"if (true) { throw("in block"); } 1"#, "Error: in block"),
+            (r#"try { 1 } finally { throw("fin") }"#, "Error: fin"),
         ];
         for (input, expected) in tests {
             let v = test_eval(input);
-            assert_eq!(v, Object::Error(expected.into()));
+            assert_eq!(v.inspect(), expected);
         }
     }
 
@@ -741,6 +1401,131 @@ mod tests {
         }
     }
 
+    #[test]
+    fn eval_destructure_statement() {
+        let tests = vec![
+            ("let [a, b] = [1, 2]; a + b", "3"),
+            (
+                "let [a, [b, c], ...d] = [1, [2, 3], 4, 5]; [a, b, c, d]",
+                "[1, 2, 3, [4, 5]]",
+            ),
+            ("let [a, ...b] = [1]; b", "[]"),
+            (
+                r#"let {name, age: years} = {"name": "x", "age": 2}; [name, years]"#,
+                r#"["x", 2]"#,
+            ),
+            (r#"let {a: [b]} = {"a": [1], "c": 2}; b"#, "1"),
+            (
+                "let f = fn([a, b], {c}) { a + b + c }; f([1, 2], {\"c\": 3})",
+                "6",
+            ),
+            (
+                "let [a, b] = [1, 2, 3];",
+                "cannot destructure `[1, 2, 3]` with `[a, b]`: expected 2 elements, got 3",
+            ),
+            (
+                "let [a, b, ...c] = [1];",
+                "cannot destructure `[1]` with `[a, b, ...c]`: expected 2 elements, got 1",
+            ),
+            (
+                "let [a] = 1;",
+                "cannot destructure `1` with `[a]`: not an array",
+            ),
+            (
+                r#"let {a, b} = {"a": 1};"#,
+                r#"cannot destructure `{"a": 1}` with `{a, b}`: missing key "b""#,
+            ),
+            (
+                "let {a} = [1];",
+                "cannot destructure `[1]` with `{a}`: not a hash",
+            ),
+            (
+                "fn([a]) { a }(2)",
+                "cannot destructure `2` with `[a]`: not an array",
+            ),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v.inspect(), expected, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn eval_const_statement() {
+        let tests = vec![
+            ("const a = 5; a", "5"),
+            (
+                "const fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(5)",
+                "120",
+            ),
+            ("const a = 1; let a = 2;", "cannot reassign constant `a`"),
+            ("const a = 1; const a = 2;", "cannot reassign constant `a`"),
+            (
+                "const a = 1; let [a] = [2];",
+                "cannot reassign constant `a`",
+            ),
+            (
+                "const a = 1; let [b, ...a] = [2];",
+                "cannot reassign constant `a`",
+            ),
+            (
+                "const f = 1; let f = fn() { 2 };",
+                "cannot reassign constant `f`",
+            ),
+            // inner scopes and parameters may shadow constants
+            ("const a = 1; if (true) { let a = 2; a }", "2"),
+            ("const a = 1; let f = fn(a) { a }; f(2) + a", "3"),
+            ("const a = 1; match (2) { a => a }", "2"),
+            ("let a = 1; const a = 2; a", "2"),
+            // arrays and hashes are values
+            ("const a = [1]; let b = push(a, 2); [a, b]", "[[1], [1, 2]]"),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v.inspect(), expected, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn eval_match_expression() {
+        let tests = vec![
+            (
+                r#"match (2) { 1 => "one", 2 => "two", _ => "many" }"#,
+                r#""two""#,
+            ),
+            (
+                r#"match (-1) { -1 => "minus one", n => n }"#,
+                r#""minus one""#,
+            ),
+            (r#"match ("b") { "a" => 1, "b" => 2 }"#, "2"),
+            ("match (false) { true => 1, false => 0 }", "0"),
+            ("match (5) { n => n * 2 }", "10"),
+            ("match ([1, [2, 3]]) { [a, [_, b]] => a + b }", "4"),
+            ("match ([1, 2, 3]) { [a] => a, [a, ...b] => b }", "[2, 3]"),
+            (
+                r#"match ({"kind": "circle", "r": 2}) { {kind: "square", side} => side, {kind: "circle", r} => r * r }"#,
+                "4",
+            ),
+            (
+                "match (3) { n if n > 5 => \"big\", n if n > 1 => \"small\", _ => \"tiny\" }",
+                r#""small""#,
+            ),
+            // arms bind in their own scope
+            ("let a = 1; match (2) { a => a }; a", "1"),
+            (
+                "let f = fn(x) { match (x) { [] => 0, [h, ...t] => h + f(t) } }; f([1, 2, 3])",
+                "6",
+            ),
+            ("match (3) { 1 => 1, [a] => a }", "no match arm matched `3`"),
+            ("match (x) { _ => 1 }", "identifier not found: `x`"),
+            ("match (1) { n if m => n }", "identifier not found: `m`"),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v.inspect(), expected, "input={}", input);
+        }
+    }
+
     #[test]
     fn create_env_in_if_block_statement() {
         let tests: Vec<(&str, Object)> = vec![
@@ -768,7 +1553,7 @@ mod tests {
             Object::Function { params, body, .. } => {
                 // params
                 assert_eq!(params.len(), 1);
-                assert_eq!(params[0].0, "x");
+                assert_eq!(params[0].identifiers()[0].0, "x");
                 // body
                 assert_eq!(body.to_string(), "(x + 2)");
             }
@@ -818,6 +1603,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn eval_function_parameters() {
+        let tests = vec![
+            (
+                "fn(x, y) { x }(1)",
+                Object::Error(ErrorKind::Argument, "wrong number of arguments. got=1, want=2".into()),
+            ),
+            (
+                "fn(x) { x }(1, 2)",
+                Object::Error(ErrorKind::Argument, "wrong number of arguments. got=2, want=1".into()),
+            ),
+            ("fn(x, y = 2) { x + y }(1)", Object::Integer(3)),
+            ("fn(x, y = 2) { x + y }(1, 1)", Object::Integer(2)),
+            ("fn(x, y = x * 10) { y }(3)", Object::Integer(30)),
+            (
+                "fn(x, y = 2) { x }()",
+                Object::Error(ErrorKind::Argument, "wrong number of arguments. got=0, want=1 to 2".into()),
+            ),
+            (
+                "fn(x = undefined) { x }()",
+                Object::Error(ErrorKind::Name, "identifier not found: `undefined`".into()),
+            ),
+            (
+                "fn(x, ...rest) { rest }(1, 2, 3)",
+                Object::Array(vec![Object::Integer(2), Object::Integer(3)]),
+            ),
+            ("fn(x, ...rest) { rest }(1)", Object::Array(vec![])),
+            (
+                "fn(x, ...rest) { rest }()",
+                Object::Error(ErrorKind::Argument, "wrong number of arguments. got=0, want=1 or more".into()),
+            ),
+            (
+                "let count = fn(xs, n = 0) { if (len(xs) == 0) { n } else { count(rest(xs), n + 1) } }; count([1, 2, 3])",
+                Object::Integer(3),
+            ),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v, expected, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn eval_spread_expression() {
+        let tests = vec![
+            ("let a = [1, 2]; [...a, 3, ...a]", "[1, 2, 3, 1, 2]"),
+            ("[...[]]", "[]"),
+            (
+                r#"let h = {"a": 1, "b": 2}; {...h, "a": 3, "c": 4}"#,
+                r#"{"a": 3, "b": 2, "c": 4}"#,
+            ),
+            (r#"{"a": 0, ...{"a": 1}}"#, r#"{"a": 1}"#),
+            (
+                "let add = fn(a, b, c) { a + b + c }; add(...[1, 2], 3)",
+                "6",
+            ),
+            ("fn(...xs) { xs }(...[1], ...[2])", "[1, 2]"),
+            ("[...1]", "cannot spread `1`, not an array"),
+            (r#"{...[1]}"#, "cannot spread `[1]` into a hash"),
+            ("len(...[1, 2])", "wrong number of arguments. got=2, want=1"),
+            ("[...x]", "identifier not found: `x`"),
+        ];
+        for (input, expected) in tests {
+            let v = test_eval(input);
+            assert_eq!(v.inspect(), expected, "input={}", input);
+        }
+    }
+
     #[test]
     fn eval_builtin_function_expression() {
         let tests = vec![
@@ -825,11 +1678,17 @@ mod tests {
             (r#"len("four")"#, Object::Integer(4)),
             (
                 r#"len(1)"#,
-                Object::Error("argument to `len` not supported, got `1`".into()),
+                Object::Error(
+                    ErrorKind::Type,
+                    "argument to `len` not supported, got `1`".into(),
+                ),
             ),
             (
                 r#"len("one", "two")"#,
-                Object::Error("wrong number of arguments. got=2, want=1".into()),
+                Object::Error(
+                    ErrorKind::Argument,
+                    "wrong number of arguments. got=2, want=1".into(),
+                ),
             ),
         ];
         for (input, expected) in tests {
@@ -994,4 +1853,8 @@ mod tests {
     fn new_int(n: i64) -> Object {
         Object::Integer(n)
     }
+
+    fn new_string(s: &str) -> Object {
+        Object::String(s.into())
+    }
 }
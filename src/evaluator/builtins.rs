@@ -1,26 +1,148 @@
 use crate::ast::Identifier;
-use crate::object::Object;
+use crate::evaluator::json;
+use crate::object::{ErrorKind, HashKey, Object};
+use indexmap::IndexMap;
+use itertools::Itertools;
 use lazy_static::lazy_static;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
 
 pub type BuiltinFunction = fn(Vec<Object>) -> Object;
 
 lazy_static! {
-    static ref FUNCTION_MAP: HashMap<&'static str, BuiltinFunction> = {
+    // name -> (function, number of arguments), variadic functions (e.g. `puts`)
+    // have no fixed number
+    static ref FUNCTION_MAP: HashMap<&'static str, (BuiltinFunction, Option<usize>)> = {
         let mut map = HashMap::new();
-        map.insert("len", len as BuiltinFunction);
-        map.insert("first", first as BuiltinFunction);
-        map.insert("last", last as BuiltinFunction);
-        map.insert("rest", rest as BuiltinFunction);
-        map.insert("push", push as BuiltinFunction);
-        map.insert("puts", puts as BuiltinFunction);
+        map.insert("len", (len as BuiltinFunction, Some(1)));
+        map.insert("first", (first as BuiltinFunction, Some(1)));
+        map.insert("last", (last as BuiltinFunction, Some(1)));
+        map.insert("rest", (rest as BuiltinFunction, Some(1)));
+        map.insert("push", (push as BuiltinFunction, Some(2)));
+        map.insert("puts", (puts as BuiltinFunction, None));
+        map.insert("keys", (keys as BuiltinFunction, Some(1)));
+        map.insert("values", (values as BuiltinFunction, Some(1)));
+        map.insert("throw", (throw as BuiltinFunction, Some(1)));
+        map.insert("macroexpand", (macroexpand as BuiltinFunction, Some(1)));
+        map.insert("macroexpand1", (macroexpand1 as BuiltinFunction, Some(1)));
+        map.insert("read_file", (read_file as BuiltinFunction, Some(1)));
+        map.insert("write_file", (write_file as BuiltinFunction, Some(2)));
+        map.insert("append_file", (append_file as BuiltinFunction, Some(2)));
+        map.insert("read_lines", (read_lines as BuiltinFunction, Some(1)));
+        map.insert("list_dir", (list_dir as BuiltinFunction, Some(1)));
+        map.insert("file_exists", (file_exists as BuiltinFunction, Some(1)));
+        map.insert("read_stdin", (read_stdin as BuiltinFunction, Some(0)));
+        map.insert("read_line", (read_line as BuiltinFunction, Some(0)));
+        map.insert("eprint", (eprint as BuiltinFunction, None));
+        map.insert("json_parse", (json_parse as BuiltinFunction, Some(1)));
+        map.insert("json_stringify", (json_stringify as BuiltinFunction, None));
         map
     };
 }
 
+// what the builtins may do, sandboxed embeddings disable I/O
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Capabilities {
+    // the file system, stdin and stderr builtins
+    pub io: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self { io: true }
+    }
+}
+
+// what the builtins may do, where `puts` writes, stdout by default, and
+// whether files are evaluated after the prelude
+pub struct Context {
+    capabilities: Capabilities,
+    output: Box<dyn Write>,
+    prelude: bool,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            capabilities: Capabilities::default(),
+            output: Box::new(io::stdout()),
+            prelude: true,
+        }
+    }
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn with_output(mut self, output: Box<dyn Write>) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn with_prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude;
+        self
+    }
+
+    // for the builtins called on the current thread until the guard is
+    // dropped, which restores the previous context (e.g. on a panic)
+    pub fn enter(self) -> ContextGuard {
+        let previous = CONTEXT.with(|c| c.replace(self));
+        ContextGuard {
+            previous: Some(previous),
+        }
+    }
+}
+
+#[must_use = "the context is left when the guard is dropped"]
+pub struct ContextGuard {
+    previous: Option<Context>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            // the thread may be exiting
+            let _ = CONTEXT.try_with(|c| c.replace(previous));
+        }
+    }
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Context> = RefCell::new(Context::default());
+}
+
+pub(crate) fn io_enabled() -> bool {
+    CONTEXT.with(|c| c.borrow().capabilities.io)
+}
+
+pub(crate) fn prelude_enabled() -> bool {
+    CONTEXT.with(|c| c.borrow().prelude)
+}
+
 pub fn get(id: &Identifier) -> Option<BuiltinFunction> {
     let id: &str = &id.0;
-    FUNCTION_MAP.get(id).copied()
+    FUNCTION_MAP.get(id).map(|&(f, _)| f)
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    FUNCTION_MAP.contains_key(name)
+}
+
+pub fn arity(name: &str) -> Option<usize> {
+    FUNCTION_MAP.get(name).and_then(|&(_, n)| n)
+}
+
+pub fn names() -> Vec<&'static str> {
+    FUNCTION_MAP.keys().copied().sorted().collect()
 }
 
 // functions
@@ -98,34 +220,302 @@ fn push(args: Vec<Object>) -> Object {
 }
 
 fn puts(args: Vec<Object>) -> Object {
+    let res = CONTEXT.with(|c| {
+        let output = &mut c.borrow_mut().output;
+        for a in args {
+            writeln!(output, "{}", a)?;
+        }
+        output.flush()
+    });
+    match res {
+        Ok(()) => Object::Null,
+        Err(e) => Object::Error(ErrorKind::IO, format!("cannot write output: {}", e)),
+    }
+}
+
+fn keys(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return new_wrong_number_arguments_error(args.len(), 1);
+    }
+
+    match &args[0] {
+        Object::Hash(hash) => Object::Array(hash.keys().cloned().map(Object::from).collect()),
+        o => new_not_supported_error("keys", o),
+    }
+}
+
+fn values(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return new_wrong_number_arguments_error(args.len(), 1);
+    }
+
+    match &args[0] {
+        Object::Hash(hash) => Object::Array(hash.values().cloned().collect()),
+        o => new_not_supported_error("values", o),
+    }
+}
+
+fn throw(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return new_wrong_number_arguments_error(args.len(), 1);
+    }
+
+    let kind_key = HashKey::String("kind".into());
+    let message_key = HashKey::String("message".into());
+    let value = match args.into_iter().next().unwrap() {
+        Object::Hash(mut hash) => {
+            if !hash.contains_key(&message_key) {
+                return new_not_supported_error("throw", &Object::Hash(hash));
+            }
+            if !hash.contains_key(&kind_key) {
+                hash.insert(kind_key, Object::String("Error".into()));
+            }
+            hash
+        }
+        Object::String(message) => {
+            let mut hash = IndexMap::new();
+            hash.insert(kind_key, Object::String("Error".into()));
+            hash.insert(message_key, Object::String(message));
+            hash
+        }
+        o => {
+            let mut hash = IndexMap::new();
+            hash.insert(kind_key, Object::String("Error".into()));
+            hash.insert(message_key, Object::String(o.to_string()));
+            hash.insert(HashKey::String("value".into()), o);
+            hash
+        }
+    };
+    Object::Throw(Box::new(Object::Hash(value)))
+}
+
+fn json_parse(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return new_wrong_number_arguments_error(args.len(), 1);
+    }
+
+    match &args[0] {
+        Object::String(s) => {
+            json::parse(s).unwrap_or_else(|e| Object::Error(ErrorKind::Runtime, e))
+        }
+        o => new_not_supported_error("json_parse", o),
+    }
+}
+
+// json_stringify(value, indent?)
+fn json_stringify(args: Vec<Object>) -> Object {
+    if args.is_empty() || args.len() > 2 {
+        return Object::Error(
+            ErrorKind::Argument,
+            format!("wrong number of arguments. got={}, want=1 or 2", args.len()),
+        );
+    }
+    // compact unless positive
+    let indent = match args.get(1) {
+        None => None,
+        Some(Object::Integer(n)) if *n > 0 => Some(*n as usize),
+        Some(Object::Integer(_)) => None,
+        Some(o) => return new_not_supported_error("json_stringify", o),
+    };
+
+    match json::stringify(&args[0], indent) {
+        Ok(s) => Object::String(s),
+        Err(e) => Object::Error(ErrorKind::Type, e),
+    }
+}
+
+// I/O, paths are relative to the working directory
+
+fn read_file(args: Vec<Object>) -> Object {
+    let path = match io_path_arg("read_file", &args) {
+        Ok(path) => path,
+        Err(e) => return *e,
+    };
+    match std::fs::read_to_string(path) {
+        Ok(s) => Object::String(s),
+        Err(e) => new_io_error("read", path, e),
+    }
+}
+
+fn write_file(args: Vec<Object>) -> Object {
+    write_to_file("write_file", args, false)
+}
+
+fn append_file(args: Vec<Object>) -> Object {
+    write_to_file("append_file", args, true)
+}
+
+fn write_to_file(fname: &str, args: Vec<Object>, append: bool) -> Object {
+    if let Err(e) = check_io(fname, &args, 2) {
+        return *e;
+    }
+    let (path, content) = match (&args[0], &args[1]) {
+        (Object::String(path), Object::String(content)) => (path, content),
+        (Object::String(_), o) | (o, _) => return new_not_supported_error(fname, o),
+    };
+    let res = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()));
+    match res {
+        Ok(()) => Object::Null,
+        Err(e) => new_io_error("write", path, e),
+    }
+}
+
+fn read_lines(args: Vec<Object>) -> Object {
+    let path = match io_path_arg("read_lines", &args) {
+        Ok(path) => path,
+        Err(e) => return *e,
+    };
+    match std::fs::read_to_string(path) {
+        Ok(s) => Object::Array(s.lines().map(|l| Object::String(l.into())).collect()),
+        Err(e) => new_io_error("read", path, e),
+    }
+}
+
+// the entry names, sorted
+fn list_dir(args: Vec<Object>) -> Object {
+    let path = match io_path_arg("list_dir", &args) {
+        Ok(path) => path,
+        Err(e) => return *e,
+    };
+    let names = std::fs::read_dir(path).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<_>>>()
+    });
+    match names {
+        Ok(names) => Object::Array(names.into_iter().sorted().map(Object::String).collect()),
+        Err(e) => new_io_error("list", path, e),
+    }
+}
+
+fn file_exists(args: Vec<Object>) -> Object {
+    match io_path_arg("file_exists", &args) {
+        Ok(path) => Object::Boolean(std::path::Path::new(path).exists()),
+        Err(e) => *e,
+    }
+}
+
+// the rest of stdin
+fn read_stdin(args: Vec<Object>) -> Object {
+    if let Err(e) = check_io("read_stdin", &args, 0) {
+        return *e;
+    }
+    let mut s = String::new();
+    match io::stdin().read_to_string(&mut s) {
+        Ok(_) => Object::String(s),
+        Err(e) => new_io_error("read", "stdin", e),
+    }
+}
+
+// the next line of stdin without the line break, null at the end
+fn read_line(args: Vec<Object>) -> Object {
+    if let Err(e) = check_io("read_line", &args, 0) {
+        return *e;
+    }
+    let mut s = String::new();
+    match io::stdin().lock().read_line(&mut s) {
+        Ok(0) => Object::Null,
+        Ok(_) => Object::String(s.trim_end_matches(&['\n', '\r'][..]).into()),
+        Err(e) => new_io_error("read", "stdin", e),
+    }
+}
+
+// `puts` to stderr
+fn eprint(args: Vec<Object>) -> Object {
+    if !io_enabled() {
+        return new_io_disabled_error("eprint");
+    }
     for a in args {
-        println!("{}", a);
+        eprintln!("{}", a);
     }
     Object::Null
 }
 
+fn check_io(fname: &str, args: &[Object], expected: usize) -> Result<(), Box<Object>> {
+    if !io_enabled() {
+        return Err(new_io_disabled_error(fname).into());
+    }
+    if args.len() != expected {
+        return Err(new_wrong_number_arguments_error(args.len(), expected).into());
+    }
+    Ok(())
+}
+
+fn io_path_arg<'a>(fname: &str, args: &'a [Object]) -> Result<&'a str, Box<Object>> {
+    check_io(fname, args, 1)?;
+    match &args[0] {
+        Object::String(path) => Ok(path),
+        o => Err(new_not_supported_error(fname, o).into()),
+    }
+}
+
+fn new_io_disabled_error(fname: &str) -> Object {
+    Object::Error(
+        ErrorKind::IO,
+        format!("`{}` is not allowed, I/O is disabled", fname),
+    )
+}
+
+fn new_io_error(action: &str, path: &str, e: io::Error) -> Object {
+    Object::Error(
+        ErrorKind::IO,
+        format!("cannot {} \"{}\": {}", action, path, e),
+    )
+}
+
 // helpers
 
-fn new_wrong_number_arguments_error(n: usize, expected: usize) -> Object {
-    Object::Error(format!(
-        "wrong number of arguments. got={}, want={}",
-        n, expected
-    ))
+// `macroexpand(quote(...))` is replaced during macro expansion, so these are
+// only called with other arguments
+fn macroexpand(args: Vec<Object>) -> Object {
+    not_expanded("macroexpand", args)
+}
+
+fn macroexpand1(args: Vec<Object>) -> Object {
+    not_expanded("macroexpand1", args)
 }
 
-fn new_not_supported_error(fname: impl Into<String>, o: &Object) -> Object {
-    Object::Error(format!(
-        "argument to `{}` not supported, got `{}`",
-        fname.into(),
-        o
-    ))
+fn not_expanded(fname: &str, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return new_wrong_number_arguments_error(args.len(), 1);
+    }
+    Object::Error(
+        ErrorKind::Type,
+        format!(
+            "argument to `{}` must be a `quote(...)` expression, got `{}`",
+            fname, args[0]
+        ),
+    )
+}
+
+pub(super) fn new_wrong_number_arguments_error(n: usize, expected: usize) -> Object {
+    Object::Error(
+        ErrorKind::Argument,
+        format!("wrong number of arguments. got={}, want={}", n, expected),
+    )
+}
+
+pub(super) fn new_not_supported_error(fname: impl Into<String>, o: &Object) -> Object {
+    Object::Error(
+        ErrorKind::Type,
+        format!("argument to `{}` not supported, got `{}`", fname.into(), o),
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use crate::ast::Identifier;
-    use crate::evaluator::builtins::{get, BuiltinFunction};
-    use crate::object::Object;
+    use crate::evaluator::builtins::{get, BuiltinFunction, Capabilities, Context};
+    use crate::object::{ErrorKind, HashKey, Object};
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
 
     #[test]
     fn len() {
@@ -239,8 +629,259 @@ mod tests {
         }
     }
 
+    #[test]
+    fn keys() {
+        let keys = test_get("keys");
+        // args, expected
+        let tests = vec![
+            (vec![new_hash(vec![])], new_array(vec![])),
+            (
+                vec![new_hash(vec![
+                    (HashKey::String("b".into()), new_integer(1)),
+                    (HashKey::Integer(1), new_integer(2)),
+                    (HashKey::Boolean(true), new_integer(3)),
+                ])],
+                new_array(vec![new_string("b"), new_integer(1), Object::Boolean(true)]),
+            ),
+            (
+                vec![new_integer(1)],
+                Object::Error(
+                    ErrorKind::Type,
+                    "argument to `keys` not supported, got `1`".into(),
+                ),
+            ),
+        ];
+        for (args, expected) in tests {
+            assert_eq!(keys(args), expected);
+        }
+    }
+
+    #[test]
+    fn values() {
+        let values = test_get("values");
+        // args, expected
+        let tests = vec![
+            (vec![new_hash(vec![])], new_array(vec![])),
+            (
+                vec![new_hash(vec![
+                    (HashKey::String("b".into()), new_integer(1)),
+                    (HashKey::String("a".into()), new_integer(2)),
+                ])],
+                new_array(vec![new_integer(1), new_integer(2)]),
+            ),
+        ];
+        for (args, expected) in tests {
+            assert_eq!(values(args), expected);
+        }
+    }
+
+    #[test]
+    fn throw() {
+        let throw = test_get("throw");
+        // args, expected
+        let tests = vec![
+            (
+                vec![new_string("oops")],
+                new_throw(vec![
+                    (new_key("kind"), new_string("Error")),
+                    (new_key("message"), new_string("oops")),
+                ]),
+            ),
+            (
+                vec![new_integer(1)],
+                new_throw(vec![
+                    (new_key("kind"), new_string("Error")),
+                    (new_key("message"), new_string("1")),
+                    (new_key("value"), new_integer(1)),
+                ]),
+            ),
+            (
+                vec![new_hash(vec![(new_key("message"), new_string("oops"))])],
+                new_throw(vec![
+                    (new_key("message"), new_string("oops")),
+                    (new_key("kind"), new_string("Error")),
+                ]),
+            ),
+            (
+                vec![new_hash(vec![
+                    (new_key("kind"), new_string("IOError")),
+                    (new_key("message"), new_string("oops")),
+                ])],
+                new_throw(vec![
+                    (new_key("kind"), new_string("IOError")),
+                    (new_key("message"), new_string("oops")),
+                ]),
+            ),
+            (
+                vec![new_hash(vec![])],
+                Object::Error(
+                    ErrorKind::Type,
+                    "argument to `throw` not supported, got `{}`".into(),
+                ),
+            ),
+            (
+                vec![],
+                Object::Error(
+                    ErrorKind::Argument,
+                    "wrong number of arguments. got=0, want=1".into(),
+                ),
+            ),
+        ];
+        for (args, expected) in tests {
+            assert_eq!(throw(args), expected);
+        }
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir().join(format!("monkey-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| new_string(&dir.join(name).display().to_string());
+        let missing = dir.join("missing.txt").display().to_string();
+        // (function, args, expected)
+        let tests = vec![
+            ("file_exists", vec![path("a.txt")], Object::Boolean(false)),
+            (
+                "write_file",
+                vec![path("a.txt"), new_string("1\n")],
+                new_null(),
+            ),
+            (
+                "append_file",
+                vec![path("a.txt"), new_string("2")],
+                new_null(),
+            ),
+            (
+                "append_file",
+                vec![path("b.txt"), new_string("")],
+                new_null(),
+            ),
+            ("file_exists", vec![path("a.txt")], Object::Boolean(true)),
+            ("read_file", vec![path("a.txt")], new_string("1\n2")),
+            (
+                "read_lines",
+                vec![path("a.txt")],
+                new_array(vec![new_string("1"), new_string("2")]),
+            ),
+            (
+                "write_file",
+                vec![path("a.txt"), new_string("3")],
+                new_null(),
+            ),
+            ("read_file", vec![path("a.txt")], new_string("3")),
+            (
+                "list_dir",
+                vec![new_string(&dir.display().to_string())],
+                new_array(vec![new_string("a.txt"), new_string("b.txt")]),
+            ),
+            (
+                "read_file",
+                vec![new_string(&missing)],
+                Object::Error(
+                    ErrorKind::IO,
+                    format!(
+                        "cannot read \"{}\": No such file or directory (os error 2)",
+                        missing
+                    ),
+                ),
+            ),
+            (
+                "write_file",
+                vec![path("a.txt"), new_integer(1)],
+                Object::Error(
+                    ErrorKind::Type,
+                    "argument to `write_file` not supported, got `1`".into(),
+                ),
+            ),
+            (
+                "read_file",
+                vec![],
+                Object::Error(
+                    ErrorKind::Argument,
+                    "wrong number of arguments. got=0, want=1".into(),
+                ),
+            ),
+        ];
+        for (name, args, expected) in tests {
+            assert_eq!(test_get(name)(args), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn io_disabled() {
+        let _context = Context::new()
+            .with_capabilities(Capabilities { io: false })
+            .enter();
+        for name in &[
+            "read_file",
+            "write_file",
+            "append_file",
+            "read_lines",
+            "list_dir",
+            "file_exists",
+            "read_stdin",
+            "read_line",
+            "eprint",
+        ] {
+            let expected = format!("`{}` is not allowed, I/O is disabled", name);
+            assert_eq!(
+                test_get(name)(vec![]),
+                Object::Error(ErrorKind::IO, expected)
+            );
+        }
+        // other builtins are not affected
+        assert_eq!(test_get("puts")(vec![]), new_null());
+    }
+
+    #[test]
+    fn context() {
+        let outer = Output::default();
+        let inner = Output::default();
+        let puts = test_get("puts");
+        let file_exists = test_get("file_exists");
+        {
+            let _outer = Context::new().with_output(Box::new(outer.clone())).enter();
+            puts(vec![new_integer(1)]);
+            {
+                let _inner = Context::new()
+                    .with_capabilities(Capabilities { io: false })
+                    .with_output(Box::new(inner.clone()))
+                    .enter();
+                puts(vec![new_integer(2)]);
+                assert!(file_exists(vec![new_string(".")]).is_error());
+            }
+            puts(vec![new_integer(3)]);
+            assert_eq!(file_exists(vec![new_string(".")]), Object::Boolean(true));
+        }
+        assert_eq!(outer.0.borrow().as_slice(), b"1\n3\n");
+        assert_eq!(inner.0.borrow().as_slice(), b"2\n");
+        // restored when unwinding
+        let res = std::panic::catch_unwind(|| {
+            let _context = Context::new()
+                .with_capabilities(Capabilities { io: false })
+                .enter();
+            panic!("in context");
+        });
+        assert!(res.is_err());
+        assert_eq!(file_exists(vec![new_string(".")]), Object::Boolean(true));
+    }
+
     // helpers
 
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
     fn new_id(s: &str) -> Identifier {
         Identifier(s.into())
     }
@@ -257,6 +898,18 @@ mod tests {
         Object::Array(a)
     }
 
+    fn new_hash(pairs: Vec<(HashKey, Object)>) -> Object {
+        Object::Hash(pairs.into_iter().collect())
+    }
+
+    fn new_key(s: &str) -> HashKey {
+        HashKey::String(s.into())
+    }
+
+    fn new_throw(pairs: Vec<(HashKey, Object)>) -> Object {
+        Object::Throw(Box::new(new_hash(pairs)))
+    }
+
     fn new_null() -> Object {
         Object::Null
     }
@@ -0,0 +1,361 @@
+use crate::ast::{self};
+use crate::object::{ErrorKind, Object};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Identifiers bound inside `quote(...)` of a macro body (by `let`, parameters,
+// `catch` and `match` arms) are renamed to fresh symbols, so that they neither capture nor
+// are captured by identifiers at the call site. Code inside `unquote(...)` is
+// left as is, and so are references outside the scope of a renamed binding.
+// Identifiers prefixed with `__` are captured on purpose: `__it` becomes `it`.
+
+const CAPTURE_PREFIX: &str = "__";
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// `<name>__<n>` cannot conflict with user identifiers, the lexer rejects them
+pub fn gensym(name: &str) -> ast::Identifier {
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst) + 1;
+    ast::Identifier(format!("{}__{}", name, n))
+}
+
+// `gensym()`, `gensym("tmp")`, only available during macro evaluation
+pub fn gensym_builtin(args: Vec<Object>) -> Object {
+    let name = match args.as_slice() {
+        [] => "gensym".into(),
+        // `__<n>` would be captured
+        [Object::String(s)] if s.is_empty() => {
+            return Object::Error(
+                ErrorKind::Argument,
+                "argument to `gensym` must not be empty".into(),
+            )
+        }
+        [Object::String(s)] => s.clone(),
+        [o] => {
+            return Object::Error(
+                ErrorKind::Type,
+                format!("argument to `gensym` not supported, got `{}`", o),
+            )
+        }
+        _ => {
+            return Object::Error(
+                ErrorKind::Argument,
+                format!("wrong number of arguments. got={}, want=0 or 1", args.len()),
+            )
+        }
+    };
+    Object::Quote(ast::Expression::Identifier(gensym(&name)).into())
+}
+
+// renames the templates of all `quote(...)` in a macro body, called once per expansion
+pub fn rename_quoted(body: ast::BlockStatement) -> Result<ast::BlockStatement, String> {
+    let node = ast::modify(ast::Statement::from(body).into(), |node| match node {
+        ast::Node::Expression(ast::Expression::Quote(template)) => {
+            let mut renamer = Renamer {
+                scopes: vec![HashMap::new()],
+            };
+            let template = renamer.expression(*template);
+            ast::Expression::Quote(template.into()).into()
+        }
+        _ => node,
+    })?;
+    match node.statement()? {
+        ast::Statement::Block(block) => Ok(block),
+        _ => Err("node is not BlockStatement".into()),
+    }
+}
+
+// renames the identifiers bound in quoted code and the references in their
+// scope, free identifiers are left as is; nothing inside `unquote(...)` is renamed
+struct Renamer {
+    // original name -> fresh name, innermost scope last
+    scopes: Vec<HashMap<String, ast::Identifier>>,
+}
+
+impl Renamer {
+    fn bind(&mut self, id: ast::Identifier) -> ast::Identifier {
+        if id.0.starts_with(CAPTURE_PREFIX) {
+            return self.reference(id);
+        }
+        let renamed = gensym(&id.0);
+        let scope = self.scopes.last_mut().expect("no scope");
+        scope.insert(id.0, renamed.clone());
+        renamed
+    }
+
+    fn reference(&mut self, id: ast::Identifier) -> ast::Identifier {
+        match self.scopes.iter().rev().find_map(|s| s.get(&id.0)) {
+            Some(renamed) => renamed.clone(),
+            None if id.0.starts_with(CAPTURE_PREFIX) => {
+                ast::Identifier(id.0[CAPTURE_PREFIX.len()..].into())
+            }
+            None => id,
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, stmt: ast::Statement) -> ast::Statement {
+        match stmt {
+            ast::Statement::Let {
+                identifier,
+                expression,
+            } => {
+                let (identifier, expression) = self.binding(identifier, expression);
+                ast::Statement::Let {
+                    identifier,
+                    expression,
+                }
+            }
+            ast::Statement::Const {
+                identifier,
+                expression,
+            } => {
+                let (identifier, expression) = self.binding(identifier, expression);
+                ast::Statement::Const {
+                    identifier,
+                    expression,
+                }
+            }
+            ast::Statement::Destructure {
+                pattern,
+                expression,
+            } => {
+                let expression = self.expression(expression);
+                ast::Statement::Destructure {
+                    pattern: self.pattern(pattern),
+                    expression,
+                }
+            }
+            ast::Statement::Import { path, binding } => {
+                let binding = match binding {
+                    ast::ImportBinding::Module(id) => ast::ImportBinding::Module(self.bind(id)),
+                    ast::ImportBinding::Names(names) => ast::ImportBinding::Names(
+                        names.into_iter().map(|id| self.bind(id)).collect(),
+                    ),
+                };
+                ast::Statement::Import { path, binding }
+            }
+            ast::Statement::Export(stmt) => ast::Statement::Export(self.statement(*stmt).into()),
+            ast::Statement::Return(expr) => ast::Statement::Return(self.expression(expr)),
+            ast::Statement::Expression(expr) => ast::Statement::Expression(self.expression(expr)),
+            ast::Statement::Block(block) => ast::Statement::Block(self.block(block)),
+            ast::Statement::Commented(comments, stmt) => {
+                ast::Statement::Commented(comments, self.statement(*stmt).into())
+            }
+            ast::Statement::Comment(it) => ast::Statement::Comment(it),
+        }
+    }
+
+    // functions can refer to themselves, other values are bound after
+    fn binding(
+        &mut self,
+        identifier: ast::Identifier,
+        expression: ast::Expression,
+    ) -> (ast::Identifier, ast::Expression) {
+        if let ast::Expression::Function(_) = expression {
+            let identifier = self.bind(identifier);
+            (identifier, self.expression(expression))
+        } else {
+            let expression = self.expression(expression);
+            (self.bind(identifier), expression)
+        }
+    }
+
+    fn block(&mut self, block: ast::BlockStatement) -> ast::BlockStatement {
+        self.push_scope();
+        let statements = block
+            .statements
+            .into_iter()
+            .map(|s| self.statement(s))
+            .collect();
+        self.pop_scope();
+        ast::BlockStatement { statements }
+    }
+
+    fn params(&mut self, params: Vec<ast::Parameter>) -> Vec<ast::Parameter> {
+        params
+            .into_iter()
+            .map(|p| match p {
+                ast::Parameter::Identifier(id) => ast::Parameter::Identifier(self.bind(id)),
+                ast::Parameter::Default(id, default) => {
+                    let default = self.expression(default);
+                    ast::Parameter::Default(self.bind(id), default)
+                }
+                ast::Parameter::Rest(id) => ast::Parameter::Rest(self.bind(id)),
+                ast::Parameter::Pattern(p) => ast::Parameter::Pattern(self.pattern(p)),
+            })
+            .collect()
+    }
+
+    // keys of hash patterns are not identifiers
+    fn pattern(&mut self, pattern: ast::Pattern) -> ast::Pattern {
+        match pattern {
+            ast::Pattern::Identifier(id) => ast::Pattern::Identifier(self.bind(id)),
+            ast::Pattern::Wildcard | ast::Pattern::Literal(_) => pattern,
+            ast::Pattern::Array { elements, rest } => ast::Pattern::Array {
+                elements: elements.into_iter().map(|p| self.pattern(p)).collect(),
+                rest: rest.map(|id| self.bind(id)),
+            },
+            ast::Pattern::Hash(fields) => ast::Pattern::Hash(
+                fields
+                    .into_iter()
+                    .map(|(key, p)| (key, self.pattern(p)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn expressions(&mut self, exprs: Vec<ast::Expression>) -> Vec<ast::Expression> {
+        exprs.into_iter().map(|e| self.expression(e)).collect()
+    }
+
+    fn boxed(&mut self, expr: ast::Expression) -> Box<ast::Expression> {
+        self.expression(expr).into()
+    }
+
+    // the parameters are in a scope of their own, around the body
+    fn function(&mut self, f: ast::FunctionExpression) -> ast::FunctionExpression {
+        self.push_scope();
+        let f = ast::FunctionExpression {
+            params: self.params(f.params),
+            body: self.block(f.body),
+        };
+        self.pop_scope();
+        f
+    }
+
+    fn expression(&mut self, expr: ast::Expression) -> ast::Expression {
+        match expr {
+            ast::Expression::Identifier(id) => ast::Expression::Identifier(self.reference(id)),
+            ast::Expression::Array(elements) => ast::Expression::Array(self.expressions(elements)),
+            ast::Expression::Hash(entries) => {
+                let entries = entries
+                    .into_iter()
+                    .map(|entry| match entry {
+                        ast::HashEntry::Pair(k, v) => {
+                            ast::HashEntry::Pair(self.expression(k), self.expression(v))
+                        }
+                        ast::HashEntry::Spread(h) => ast::HashEntry::Spread(self.expression(h)),
+                    })
+                    .collect();
+                ast::Expression::Hash(entries)
+            }
+            ast::Expression::Spread(expr) => ast::Expression::Spread(self.boxed(*expr)),
+            ast::Expression::Prefix { operator, right } => ast::Expression::Prefix {
+                operator,
+                right: self.boxed(*right),
+            },
+            ast::Expression::Infix {
+                left,
+                operator,
+                right,
+            } => ast::Expression::Infix {
+                left: self.boxed(*left),
+                operator,
+                right: self.boxed(*right),
+            },
+            ast::Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => ast::Expression::If {
+                condition: self.boxed(*condition),
+                consequence: self.block(consequence),
+                alternative: alternative.map(|alt| self.block(alt)),
+            },
+            ast::Expression::Try {
+                block,
+                catch,
+                finally,
+            } => ast::Expression::Try {
+                block: self.block(block),
+                catch: catch.map(|c| {
+                    self.push_scope();
+                    let c = ast::CatchClause {
+                        identifier: self.bind(c.identifier),
+                        body: self.block(c.body),
+                    };
+                    self.pop_scope();
+                    c
+                }),
+                finally: finally.map(|fin| self.block(fin)),
+            },
+            ast::Expression::Match { value, arms } => ast::Expression::Match {
+                value: self.boxed(*value),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| {
+                        self.push_scope();
+                        let arm = ast::MatchArm {
+                            pattern: self.pattern(arm.pattern),
+                            guard: arm.guard.map(|g| self.expression(g)),
+                            body: self.expression(arm.body),
+                        };
+                        self.pop_scope();
+                        arm
+                    })
+                    .collect(),
+            },
+            ast::Expression::Function(f) => ast::Expression::Function(self.function(f)),
+            ast::Expression::Call { function, args } => {
+                let function = match function {
+                    ast::CallExpressionFunction::Identifier(id) => {
+                        ast::CallExpressionFunction::Identifier(self.reference(id))
+                    }
+                    ast::CallExpressionFunction::Function(f) => {
+                        ast::CallExpressionFunction::Function(self.function(f))
+                    }
+                    ast::CallExpressionFunction::Expression(expr) => {
+                        ast::CallExpressionFunction::Expression(self.boxed(*expr))
+                    }
+                };
+                ast::Expression::Call {
+                    function,
+                    args: self.expressions(args),
+                }
+            }
+            ast::Expression::Index { left, index } => ast::Expression::Index {
+                left: self.boxed(*left),
+                index: self.boxed(*index),
+            },
+            ast::Expression::OptionalIndex { left, index } => ast::Expression::OptionalIndex {
+                left: self.boxed(*left),
+                index: self.boxed(*index),
+            },
+            // the name is not an identifier
+            ast::Expression::Member { left, name } => ast::Expression::Member {
+                left: self.boxed(*left),
+                name,
+            },
+            ast::Expression::OptionalCall { function, args } => ast::Expression::OptionalCall {
+                function: self.boxed(*function),
+                args: self.expressions(args),
+            },
+            ast::Expression::Quote(expr) => ast::Expression::Quote(self.boxed(*expr)),
+            ast::Expression::Macro(m) => {
+                self.push_scope();
+                let m = ast::MacroExpression {
+                    params: self.params(m.params),
+                    body: self.block(m.body),
+                };
+                self.pop_scope();
+                ast::Expression::Macro(m)
+            }
+            ast::Expression::Commented(comments, expr) => {
+                ast::Expression::Commented(comments, self.boxed(*expr))
+            }
+            // code from the call site
+            other @ ast::Expression::Unquote(_) => other,
+            other @ ast::Expression::Integer(_)
+            | other @ ast::Expression::Boolean(_)
+            | other @ ast::Expression::String(_) => other,
+        }
+    }
+}
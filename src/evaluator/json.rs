@@ -0,0 +1,234 @@
+use crate::object::{HashKey, Object};
+use serde_json::Value;
+
+// `json_parse` and `json_stringify`. Objects are hashes with string keys,
+// numbers are integers.
+
+pub(crate) fn parse(s: &str) -> Result<Object, String> {
+    let value = serde_json::from_str::<Value>(s).map_err(|e| format!("invalid JSON: {}", e))?;
+    from_value(value)
+}
+
+// compact without `indent`, one item per line otherwise; keys are sorted
+pub(crate) fn stringify(o: &Object, indent: Option<usize>) -> Result<String, String> {
+    let mut res = String::new();
+    write(o, indent, 0, &mut res)?;
+    Ok(res)
+}
+
+fn from_value(value: Value) -> Result<Object, String> {
+    let o = match value {
+        Value::Null => Object::Null,
+        Value::Bool(b) => Object::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(n) => Object::Integer(n),
+            None => return Err(format!("invalid JSON: number `{}` is not an integer", n)),
+        },
+        Value::String(s) => Object::String(s),
+        Value::Array(values) => Object::Array(
+            values
+                .into_iter()
+                .map(from_value)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => Object::Hash(
+            map.into_iter()
+                .map(|(k, v)| Ok((HashKey::String(k), from_value(v)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+    };
+    Ok(o)
+}
+
+fn write(o: &Object, indent: Option<usize>, depth: usize, res: &mut String) -> Result<(), String> {
+    match o {
+        Object::Null => res.push_str("null"),
+        Object::Boolean(b) => res.push_str(&b.to_string()),
+        Object::Integer(n) => res.push_str(&n.to_string()),
+        Object::String(s) => res.push_str(&Value::String(s.clone()).to_string()),
+        Object::Array(values) => {
+            let items = values
+                .iter()
+                .map(|v| {
+                    let mut item = String::new();
+                    write(v, indent, depth + 1, &mut item)?;
+                    Ok(item)
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            write_items(('[', ']'), items, indent, depth, res);
+        }
+        Object::Hash(hash) => {
+            let mut fields = Vec::new();
+            for (k, v) in hash {
+                match k {
+                    HashKey::String(k) => fields.push((k, v)),
+                    k => {
+                        return Err(format!(
+                            "cannot convert hash key `{}` to JSON, keys must be strings",
+                            k
+                        ))
+                    }
+                }
+            }
+            fields.sort_by_key(|(k, _)| *k);
+            let mut items = Vec::new();
+            for (k, v) in fields {
+                let mut item = Value::String(k.clone()).to_string();
+                item.push_str(if indent.is_some() { ": " } else { ":" });
+                write(v, indent, depth + 1, &mut item)?;
+                items.push(item);
+            }
+            write_items(('{', '}'), items, indent, depth, res);
+        }
+        o => {
+            let kind = match o {
+                Object::Function { .. } | Object::LetFunction { .. } => "a function",
+                Object::Builtin(_) => "a builtin function",
+                Object::Macro { .. } => "a macro",
+                Object::Quote(_) => "a quote",
+                Object::Module { .. } => "a module",
+                _ => "an error",
+            };
+            return Err(format!("cannot convert {} to JSON", kind));
+        }
+    }
+    Ok(())
+}
+
+fn write_items(
+    (open, close): (char, char),
+    items: Vec<String>,
+    indent: Option<usize>,
+    depth: usize,
+    res: &mut String,
+) {
+    res.push(open);
+    match indent {
+        Some(n) if !items.is_empty() => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    res.push(',');
+                }
+                res.push('\n');
+                res.push_str(&" ".repeat(n * (depth + 1)));
+                res.push_str(item);
+            }
+            res.push('\n');
+            res.push_str(&" ".repeat(n * depth));
+        }
+        _ => res.push_str(&items.join(",")),
+    }
+    res.push(close);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Identifier;
+    use crate::evaluator::eval;
+    use crate::evaluator::json::stringify;
+    use crate::lexer::Lexer;
+    use crate::object::{Environment, Object};
+    use crate::parser::parse;
+
+    #[test]
+    fn json_parse() {
+        // (`text`, program, expected)
+        let tests = vec![
+            (
+                r#"{"b": [1, -2, true, null], "a": "x\n\"y\""}"#,
+                "json_parse(text)",
+                r#"{"b": [1, -2, true, null], "a": "x
+"y""}"#,
+            ),
+            (" 1 ", "json_parse(text)", "1"),
+            // the keys stay in the source order
+            (
+                r#"{"b": 1, "a": 2}"#,
+                "json_parse(text)",
+                r#"{"b": 1, "a": 2}"#,
+            ),
+            ("{}", r#"json_parse(text)["a"]"#, "null"),
+            (
+                r#"{"a": [1, {"b": false}]}"#,
+                "json_stringify(json_parse(text))",
+                r#""{"a":[1,{"b":false}]}""#,
+            ),
+            (
+                "1.5",
+                "json_parse(text)",
+                "invalid JSON: number `1.5` is not an integer",
+            ),
+            (
+                "[1,",
+                "json_parse(text)",
+                "invalid JSON: EOF while parsing a value at line 1 column 3",
+            ),
+            (
+                "",
+                "json_parse(1)",
+                "argument to `json_parse` not supported, got `1`",
+            ),
+        ];
+        for (text, input, expected) in tests {
+            assert_eq!(test_eval(text, input), expected, "text={}", text);
+        }
+    }
+
+    #[test]
+    fn json_stringify() {
+        let tests = vec![
+            (
+                r#"json_stringify({"b": [1, "x", if (false) { 1 }], "a": {}, "c": []})"#,
+                r#""{"a":{},"b":[1,"x",null],"c":[]}""#,
+            ),
+            (
+                r#"json_stringify({"b": [1, 2], "a": {"c": true}}, 2)"#,
+                r#""{
+  "a": {
+    "c": true
+  },
+  "b": [
+    1,
+    2
+  ]
+}""#,
+            ),
+            ("json_stringify([1], 0)", r#""[1]""#),
+            (
+                "json_stringify([fn(x) { x }])",
+                "cannot convert a function to JSON",
+            ),
+            ("json_stringify(quote(1))", "cannot convert a quote to JSON"),
+            (
+                "json_stringify({1: 2})",
+                "cannot convert hash key `1` to JSON, keys must be strings",
+            ),
+            (
+                r#"json_stringify(1, "2")"#,
+                "argument to `json_stringify` not supported, got `2`",
+            ),
+            (
+                "json_stringify()",
+                "wrong number of arguments. got=0, want=1 or 2",
+            ),
+            (
+                "json_stringify(1, 2, 3)",
+                "wrong number of arguments. got=3, want=1 or 2",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval("", input), expected, "input={}", input);
+        }
+        assert_eq!(
+            stringify(&Object::String("\"\n".into()), None),
+            Ok(r#""\"\n""#.into())
+        );
+    }
+
+    fn test_eval(text: &str, input: &str) -> String {
+        let mut env = Environment::new();
+        env.set(&Identifier("text".into()), Object::String(text.into()));
+        let prog = parse(Lexer::new(input)).unwrap();
+        eval(prog.into(), &mut env).inspect()
+    }
+}
@@ -1,6 +1,11 @@
 use crate::ast::{self};
-use crate::evaluator::eval;
+use crate::evaluator::hygiene::{self};
+use crate::evaluator::modules;
+use crate::evaluator::{bind_params, eval};
 use crate::object::{Environment, Object};
+use itertools::Itertools;
+use std::cell::RefCell;
+use std::fmt;
 
 pub type Error = String;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -10,14 +15,31 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub fn define_macros(prog: ast::Program, env: &mut Environment) -> ast::Program {
     let mut macro_excluded_statements = Vec::new();
     for stmt in prog.statements {
-        if let ast::Statement::Let {
-            identifier,
-            expression: ast::Expression::Macro(m),
-        } = stmt
-        {
-            add_macro(identifier, m, env);
-        } else {
-            macro_excluded_statements.push(stmt);
+        match stmt {
+            ast::Statement::Let {
+                identifier,
+                expression: ast::Expression::Macro(m),
+            } => add_macro(identifier, m, env),
+            // exported from `env` by `modules`
+            ast::Statement::Export(stmt) => match *stmt {
+                ast::Statement::Let {
+                    identifier,
+                    expression: ast::Expression::Macro(m),
+                } => add_macro(identifier, m, env),
+                stmt => macro_excluded_statements.push(ast::Statement::Export(stmt.into())),
+            },
+            // the other names are bound when the import is evaluated
+            ast::Statement::Import {
+                path,
+                binding: ast::ImportBinding::Names(names),
+            } => {
+                modules::import_macros(&path, &names, env);
+                macro_excluded_statements.push(ast::Statement::Import {
+                    path,
+                    binding: ast::ImportBinding::Names(names),
+                });
+            }
+            stmt => macro_excluded_statements.push(stmt),
         }
     }
     ast::Program {
@@ -37,36 +59,435 @@ fn add_macro(id: ast::Identifier, m: ast::MacroExpression, env: &mut Environment
 
 // expand_macros
 
+// the number of nested expansions before expansion is considered runaway
+pub const DEFAULT_DEPTH_LIMIT: usize = 100;
+
 pub fn expand_macros(prog: ast::Program, env: &Environment) -> Result<ast::Program> {
-    let node = ast::modify(prog.into(), |node| match &node {
-        ast::Node::Expression(ast::Expression::Call {
-            function: ast::CallExpressionFunction::Identifier(id),
-            args,
-        }) => {
-            if let Some(Object::Macro {
-                params,
-                body,
-                env: macro_env,
-            }) = env.get(&id)
-            {
-                let args = quote_args(args.clone());
-                let mut eval_env =
-                    extend_macro_env(macro_env.clone(), params.clone(), args.clone());
-                let body = ast::Statement::from(body);
-                let evaluated = eval(body.into(), &mut eval_env);
-                if let Object::Quote(node) = evaluated {
-                    node
-                } else {
-                    // TODO
-                    panic!("we only support returning AST-nodes from macros");
+    expand_macros_with_depth_limit(prog, env, DEFAULT_DEPTH_LIMIT)
+}
+
+// expands macro calls until none remain, macros defined in blocks are visible in the block
+pub fn expand_macros_with_depth_limit(
+    prog: ast::Program,
+    env: &Environment,
+    depth_limit: usize,
+) -> Result<ast::Program> {
+    let expander = Expander {
+        depth_limit,
+        trace: None,
+    };
+    expander.program(prog, env)
+}
+
+// like `expand_macros`, also returns each expansion step in order
+pub fn expand_macros_traced(
+    prog: ast::Program,
+    env: &Environment,
+) -> Result<(ast::Program, Vec<Expansion>)> {
+    let expander = Expander {
+        depth_limit: DEFAULT_DEPTH_LIMIT,
+        trace: Some(RefCell::new(Vec::new())),
+    };
+    let prog = expander.program(prog, env)?;
+    let trace = expander.trace.map(RefCell::into_inner).unwrap_or_default();
+    Ok((prog, trace))
+}
+
+// a macro call and the code it expanded to
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Expansion {
+    // the number of enclosing expansions
+    pub depth: usize,
+    pub name: ast::Identifier,
+    pub args: Vec<ast::Expression>,
+    pub expanded: ast::Expression,
+}
+
+impl fmt::Display for Expansion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}({}) => {}",
+            "  ".repeat(self.depth),
+            self.name,
+            self.args.iter().join(", "),
+            self.expanded
+        )
+    }
+}
+
+struct Expander {
+    depth_limit: usize,
+    // `None` unless tracing
+    trace: Option<RefCell<Vec<Expansion>>>,
+}
+
+impl Expander {
+    fn program(&self, prog: ast::Program, env: &Environment) -> Result<ast::Program> {
+        let mut env = Environment::new_with_outer(env.clone());
+        let statements = self.statements(prog.statements, &mut env, 0)?;
+        Ok(ast::Program { statements })
+    }
+
+    fn statements(
+        &self,
+        stmts: Vec<ast::Statement>,
+        env: &mut Environment,
+        depth: usize,
+    ) -> Result<Vec<ast::Statement>> {
+        let mut res = Vec::new();
+        for stmt in stmts {
+            match stmt {
+                ast::Statement::Let {
+                    identifier,
+                    expression: ast::Expression::Macro(m),
+                } => add_macro(identifier, m, env),
+                stmt => res.push(self.statement(stmt, env, depth)?),
+            }
+        }
+        Ok(res)
+    }
+
+    fn statement(
+        &self,
+        stmt: ast::Statement,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<ast::Statement> {
+        let stmt = match stmt {
+            ast::Statement::Let {
+                identifier,
+                expression,
+            } => ast::Statement::Let {
+                identifier,
+                expression: self.expression(expression, env, depth)?,
+            },
+            ast::Statement::Const {
+                identifier,
+                expression,
+            } => ast::Statement::Const {
+                identifier,
+                expression: self.expression(expression, env, depth)?,
+            },
+            ast::Statement::Destructure {
+                pattern,
+                expression,
+            } => ast::Statement::Destructure {
+                pattern,
+                expression: self.expression(expression, env, depth)?,
+            },
+            ast::Statement::Import { .. } => stmt,
+            ast::Statement::Export(stmt) => {
+                ast::Statement::Export(self.statement(*stmt, env, depth)?.into())
+            }
+            ast::Statement::Return(expr) => {
+                ast::Statement::Return(self.expression(expr, env, depth)?)
+            }
+            ast::Statement::Expression(expr) => {
+                ast::Statement::Expression(self.expression(expr, env, depth)?)
+            }
+            ast::Statement::Block(block) => ast::Statement::Block(self.block(block, env, depth)?),
+            ast::Statement::Commented(comments, stmt) => {
+                ast::Statement::Commented(comments, self.statement(*stmt, env, depth)?.into())
+            }
+            ast::Statement::Comment(it) => ast::Statement::Comment(it),
+        };
+        Ok(stmt)
+    }
+
+    fn block(
+        &self,
+        block: ast::BlockStatement,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<ast::BlockStatement> {
+        let mut env = Environment::new_with_outer(env.clone());
+        let statements = self.statements(block.statements, &mut env, depth)?;
+        Ok(ast::BlockStatement { statements })
+    }
+
+    fn function(
+        &self,
+        f: ast::FunctionExpression,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<ast::FunctionExpression> {
+        Ok(ast::FunctionExpression {
+            params: self.params(f.params, env, depth)?,
+            body: self.block(f.body, env, depth)?,
+        })
+    }
+
+    fn params(
+        &self,
+        params: Vec<ast::Parameter>,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<Vec<ast::Parameter>> {
+        params
+            .into_iter()
+            .map(|p| match p {
+                ast::Parameter::Default(id, default) => Ok(ast::Parameter::Default(
+                    id,
+                    self.expression(default, env, depth)?,
+                )),
+                p => Ok(p),
+            })
+            .collect()
+    }
+
+    fn expressions(
+        &self,
+        exprs: Vec<ast::Expression>,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<Vec<ast::Expression>> {
+        exprs
+            .into_iter()
+            .map(|e| self.expression(e, env, depth))
+            .collect()
+    }
+
+    fn boxed(
+        &self,
+        expr: ast::Expression,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<Box<ast::Expression>> {
+        Ok(self.expression(expr, env, depth)?.into())
+    }
+
+    fn expression(
+        &self,
+        expr: ast::Expression,
+        env: &Environment,
+        depth: usize,
+    ) -> Result<ast::Expression> {
+        let expr = match expr {
+            ast::Expression::Call {
+                function: ast::CallExpressionFunction::Identifier(id),
+                args,
+            } => {
+                if let Some(m @ Object::Macro { .. }) = env.get(&id) {
+                    // the expanded code may contain macro calls
+                    let expanded = self.expand_call(id, m, args, depth)?;
+                    return self.expression(expanded, env, depth + 1);
+                }
+                if let Some(expanded) = self.macroexpand(&id, &args, env, depth)? {
+                    return Ok(expanded);
                 }
-            } else {
-                node
+                ast::Expression::Call {
+                    function: ast::CallExpressionFunction::Identifier(id),
+                    args: self.expressions(args, env, depth)?,
+                }
+            }
+            ast::Expression::Call {
+                function: ast::CallExpressionFunction::Function(f),
+                args,
+            } => ast::Expression::Call {
+                function: ast::CallExpressionFunction::Function(self.function(f, env, depth)?),
+                args: self.expressions(args, env, depth)?,
+            },
+            ast::Expression::Call {
+                function: ast::CallExpressionFunction::Expression(f),
+                args,
+            } => ast::Expression::Call {
+                function: ast::CallExpressionFunction::Expression(self.boxed(*f, env, depth)?),
+                args: self.expressions(args, env, depth)?,
+            },
+            ast::Expression::Array(elements) => {
+                ast::Expression::Array(self.expressions(elements, env, depth)?)
+            }
+            ast::Expression::Hash(entries) => {
+                let mut new_entries = Vec::new();
+                for entry in entries {
+                    let entry = match entry {
+                        ast::HashEntry::Pair(k, v) => {
+                            let k = self.expression(k, env, depth)?;
+                            let v = self.expression(v, env, depth)?;
+                            ast::HashEntry::Pair(k, v)
+                        }
+                        ast::HashEntry::Spread(h) => {
+                            ast::HashEntry::Spread(self.expression(h, env, depth)?)
+                        }
+                    };
+                    new_entries.push(entry);
+                }
+                ast::Expression::Hash(new_entries)
             }
+            ast::Expression::Spread(expr) => {
+                ast::Expression::Spread(self.boxed(*expr, env, depth)?)
+            }
+            ast::Expression::Prefix { operator, right } => ast::Expression::Prefix {
+                operator,
+                right: self.boxed(*right, env, depth)?,
+            },
+            ast::Expression::Infix {
+                left,
+                operator,
+                right,
+            } => ast::Expression::Infix {
+                left: self.boxed(*left, env, depth)?,
+                operator,
+                right: self.boxed(*right, env, depth)?,
+            },
+            ast::Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => ast::Expression::If {
+                condition: self.boxed(*condition, env, depth)?,
+                consequence: self.block(consequence, env, depth)?,
+                alternative: match alternative {
+                    Some(alt) => Some(self.block(alt, env, depth)?),
+                    None => None,
+                },
+            },
+            ast::Expression::Try {
+                block,
+                catch,
+                finally,
+            } => ast::Expression::Try {
+                block: self.block(block, env, depth)?,
+                catch: match catch {
+                    Some(c) => Some(ast::CatchClause {
+                        identifier: c.identifier,
+                        body: self.block(c.body, env, depth)?,
+                    }),
+                    None => None,
+                },
+                finally: match finally {
+                    Some(fin) => Some(self.block(fin, env, depth)?),
+                    None => None,
+                },
+            },
+            ast::Expression::Match { value, arms } => {
+                let value = self.boxed(*value, env, depth)?;
+                let mut expanded = Vec::new();
+                for arm in arms {
+                    expanded.push(ast::MatchArm {
+                        pattern: arm.pattern,
+                        guard: match arm.guard {
+                            Some(g) => Some(self.expression(g, env, depth)?),
+                            None => None,
+                        },
+                        body: self.expression(arm.body, env, depth)?,
+                    });
+                }
+                ast::Expression::Match {
+                    value,
+                    arms: expanded,
+                }
+            }
+            ast::Expression::Function(f) => {
+                ast::Expression::Function(self.function(f, env, depth)?)
+            }
+            ast::Expression::Index { left, index } => ast::Expression::Index {
+                left: self.boxed(*left, env, depth)?,
+                index: self.boxed(*index, env, depth)?,
+            },
+            ast::Expression::OptionalIndex { left, index } => ast::Expression::OptionalIndex {
+                left: self.boxed(*left, env, depth)?,
+                index: self.boxed(*index, env, depth)?,
+            },
+            ast::Expression::Member { left, name } => ast::Expression::Member {
+                left: self.boxed(*left, env, depth)?,
+                name,
+            },
+            ast::Expression::OptionalCall { function, args } => ast::Expression::OptionalCall {
+                function: self.boxed(*function, env, depth)?,
+                args: self.expressions(args, env, depth)?,
+            },
+            // quoted code is expanded when it is spliced by a macro
+            other => other,
+        };
+        Ok(expr)
+    }
+
+    fn expand_call(
+        &self,
+        id: ast::Identifier,
+        m: Object,
+        args: Vec<ast::Expression>,
+        depth: usize,
+    ) -> Result<ast::Expression> {
+        if depth >= self.depth_limit {
+            return Err(format!(
+                "expanding `{}` exceeded the macro expansion depth limit of {}",
+                id, self.depth_limit
+            ));
         }
-        _ => node,
-    })?;
-    node.program()
+        let expanded = apply_macro(m, args.clone())?;
+        if let Some(trace) = &self.trace {
+            trace.borrow_mut().push(Expansion {
+                depth,
+                name: id,
+                args,
+                expanded: expanded.clone(),
+            });
+        }
+        Ok(expanded)
+    }
+
+    // `macroexpand(quote(...))` and `macroexpand1(quote(...))` are replaced by
+    // the quoted code expanded fully or by one step, as macros are only known here;
+    // other arguments are left to the builtins, which report them as errors
+    fn macroexpand(
+        &self,
+        id: &ast::Identifier,
+        args: &[ast::Expression],
+        env: &Environment,
+        depth: usize,
+    ) -> Result<Option<ast::Expression>> {
+        let quoted = match args {
+            [ast::Expression::Quote(quoted)] => (**quoted).clone(),
+            _ => return Ok(None),
+        };
+        let expanded = match id.0.as_str() {
+            "macroexpand" => self.expression(quoted, env, depth)?,
+            "macroexpand1" => match quoted {
+                ast::Expression::Call {
+                    function: ast::CallExpressionFunction::Identifier(id),
+                    args,
+                } => match env.get(&id) {
+                    Some(m @ Object::Macro { .. }) => self.expand_call(id, m, args, depth)?,
+                    _ => ast::Expression::Call {
+                        function: ast::CallExpressionFunction::Identifier(id),
+                        args,
+                    },
+                },
+                other => other,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(ast::Expression::Quote(expanded.into())))
+    }
+}
+
+fn apply_macro(m: Object, args: Vec<ast::Expression>) -> Result<ast::Expression> {
+    if let Object::Macro {
+        params,
+        body,
+        env: macro_env,
+    } = m
+    {
+        let args = quote_args(args);
+        let mut eval_env = extend_macro_env(macro_env, params, args)?;
+        let body = hygiene::rename_quoted(body)?;
+        let body = ast::Statement::from(body);
+        match eval(body.into(), &mut eval_env) {
+            Object::Quote(node) => node.expression(),
+            Object::Error(_, e) => Err(e),
+            // uncaught `throw(...)`, reported like at the top level
+            o @ Object::Throw(_) => Err(o.inspect()),
+            o => Err(format!(
+                "macro must return a quoted expression, got `{}`",
+                o
+            )),
+        }
+    } else {
+        Err(format!("`{}` is not a macro", m))
+    }
 }
 
 fn quote_args(args: Vec<ast::Expression>) -> Vec<Object> {
@@ -77,22 +498,33 @@ fn quote_args(args: Vec<ast::Expression>) -> Vec<Object> {
 
 fn extend_macro_env(
     env: Environment,
-    params: Vec<ast::Identifier>,
+    params: Vec<ast::Parameter>,
     args: Vec<Object>,
-) -> Environment {
+) -> Result<Environment> {
     let mut env = Environment::new_with_outer(env);
-    for (param, arg) in params.into_iter().zip(args) {
-        env.set(&param, arg);
-    }
-    env
+    env.set(
+        &ast::Identifier("gensym".into()),
+        Object::Builtin(hygiene::gensym_builtin),
+    );
+    // default values are code like arguments, rest arguments are an array of quotes
+    let quote_default = |expr: ast::Expression, _: &mut Environment| Object::Quote(expr.into());
+    bind_params(params, args, &mut env, quote_default).map_err(|e| match *e {
+        Object::Error(_, e) => e,
+        e => e.to_string(),
+    })?;
+    Ok(env)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{define_macros, expand_macros};
+    use super::{
+        define_macros, expand_macros, expand_macros_traced, expand_macros_with_depth_limit,
+        DEFAULT_DEPTH_LIMIT,
+    };
     use crate::ast;
+    use crate::evaluator::eval;
     use crate::lexer::Lexer;
-    use crate::object::{Environment, Object};
+    use crate::object::{Environment, ErrorKind, Object};
     use crate::parser::parse;
 
     #[test]
@@ -168,6 +600,392 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_expand_macros_recursively() -> Result<(), String> {
+        let unless = r#"
+        let unless = macro(c, a, b) {
+            quote(if (!(unquote(c))) { unquote(a) } else { unquote(b) })
+        };
+        "#;
+        let tests = vec![
+            // macros defined in blocks
+            (
+                r#"
+                let f = fn(x) {
+                    let twice = macro(e) { quote(unquote(e) * 2) };
+                    twice(x)
+                };
+                f(3);
+                "#,
+                Object::Integer(6),
+            ),
+            (
+                r#"
+                if (true) { let one = macro() { quote(1) }; };
+                one();
+                "#,
+                Object::Error(ErrorKind::Name, "identifier not found: `one`".into()),
+            ),
+            // an expansion using another macro
+            (
+                r#"
+                let when = macro(c, a) { quote(unless(!(unquote(c)), unquote(a), 0)) };
+                when(true, 1);
+                "#,
+                Object::Integer(1),
+            ),
+            // macro calls in arguments
+            ("first([unless(false, 1, 2)]);", Object::Integer(1)),
+            ("unless(false, unless(true, 1, 2), 3);", Object::Integer(2)),
+            // unquote in call arguments
+            (
+                r#"
+                let wrap = macro(x) { quote(first([unquote(x)])) };
+                wrap(5);
+                "#,
+                Object::Integer(5),
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = test_parse(format!("{}{}", unless, input));
+            let mut env = Environment::new();
+            let program = define_macros(program, &mut env);
+            let expanded = expand_macros(program, &env)?;
+            let evaluated = eval(expanded.into(), &mut Environment::new());
+            assert_eq!(evaluated, expected, "input={}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_macros_depth_limit() {
+        let input = r#"
+        let forever = macro() { quote(forever()) };
+        forever();
+        "#;
+        let mut env = Environment::new();
+        let program = define_macros(test_parse(input), &mut env);
+        assert_eq!(
+            expand_macros(program, &env),
+            Err(format!(
+                "expanding `forever` exceeded the macro expansion depth limit of {}",
+                DEFAULT_DEPTH_LIMIT
+            ))
+        );
+
+        let input = r#"
+        let three = macro() { quote(two() + 1) };
+        let two = macro() { quote(one() + 1) };
+        let one = macro() { quote(1) };
+        three();
+        "#;
+        let mut env = Environment::new();
+        let program = define_macros(test_parse(input), &mut env);
+        let expanded = expand_macros_with_depth_limit(program.clone(), &env, 3);
+        assert_eq!(expanded.map(|p| p.to_string()), Ok("((1 + 1) + 1)".into()));
+        let expanded = expand_macros_with_depth_limit(program, &env, 2);
+        assert_eq!(
+            expanded,
+            Err("expanding `one` exceeded the macro expansion depth limit of 2".into())
+        );
+    }
+
+    #[test]
+    fn test_macro_parameters() -> Result<(), String> {
+        let tests = vec![
+            (
+                r#"
+                let inc = macro(x, by = 1) { quote(unquote(x) + unquote(by)) };
+                [inc(1), inc(1, 10)];
+                "#,
+                Object::Array(vec![Object::Integer(2), Object::Integer(11)]),
+            ),
+            // `cond(c1, v1, c2, v2, ..., default)`
+            (
+                r#"
+                let cond = macro(...clauses) {
+                    let build = fn(cs) {
+                        if (len(cs) == 1) {
+                            first(cs)
+                        } else {
+                            let others = build(rest(rest(cs)));
+                            quote(if (unquote(cs[0])) { unquote(cs[1]) } else { unquote(others) })
+                        }
+                    };
+                    build(clauses)
+                };
+                let x = 2;
+                cond(x == 1, "one", x == 2, "two", "many");
+                "#,
+                Object::String("two".into()),
+            ),
+            // unquote in a default value of a quoted function
+            (
+                "let m = macro(e) { quote(fn(q = unquote(e)) { q }()) }; m(5);",
+                Object::Integer(5),
+            ),
+            (
+                "let m = macro(e) { quote(fn(q = unquote(e)) { q }) }; let f = m(5); f();",
+                Object::Integer(5),
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut env = Environment::new();
+            let program = define_macros(test_parse(input), &mut env);
+            let expanded = expand_macros(program, &env)?;
+            let evaluated = eval(expanded.into(), &mut Environment::new());
+            assert_eq!(evaluated, expected, "input={}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_macros_errors() {
+        let tests = vec![
+            (
+                "let m = macro() { 1 }; m();",
+                "macro must return a quoted expression, got `1`",
+            ),
+            (
+                "let m = macro(x) { quote(unquote(y)) }; m(1);",
+                "identifier not found: `y`",
+            ),
+            (
+                "let m = macro() { quote(unquote(fn() { 1 })) }; m();",
+                "could not convert `fn() {\n1\n}` to AST node",
+            ),
+            (
+                "let m = macro() { undefined }; m();",
+                "identifier not found: `undefined`",
+            ),
+            (
+                "let m = macro(a, b) { a }; m(1);",
+                "wrong number of arguments. got=1, want=2",
+            ),
+            (
+                r#"let m = macro() { if (true) { throw("boom") } }; m();"#,
+                "Error: boom",
+            ),
+            (
+                r#"let m = macro() { gensym("") }; m();"#,
+                "argument to `gensym` must not be empty",
+            ),
+            (
+                "let m = macro() { len(1, 2) }; m();",
+                "wrong number of arguments. got=2, want=1",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut env = Environment::new();
+            let program = define_macros(test_parse(input), &mut env);
+            assert_eq!(
+                expand_macros(program, &env),
+                Err(expected.into()),
+                "input={}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_macroexpand() -> Result<(), String> {
+        let macros = r#"
+        let double = macro(e) { quote(unquote(e) * 2) };
+        let quadruple = macro(e) { quote(double(double(unquote(e)))) };
+        "#;
+        let tests = vec![
+            ("macroexpand(quote(quadruple(x)))", "quote(((x * 2) * 2))"),
+            (
+                "macroexpand1(quote(quadruple(x)))",
+                "quote(double(double(x)))",
+            ),
+            (
+                "macroexpand1(quote(1 + double(x)))",
+                "quote((1 + double(x)))",
+            ),
+            ("macroexpand(quote(1 + double(x)))", "quote((1 + (x * 2)))"),
+            ("macroexpand(q)", "macroexpand(q)"),
+        ];
+        for (input, expected) in tests {
+            let mut env = Environment::new();
+            let program = define_macros(test_parse(format!("{}{}", macros, input)), &mut env);
+            let expanded = expand_macros(program, &env)?;
+            assert_eq!(expanded.to_string(), expected, "input={}", input);
+        }
+
+        let tests = vec![
+            (
+                "macroexpand(quote(double(2 + 3)))",
+                Object::Quote(test_expression("(2 + 3) * 2").into()),
+            ),
+            (
+                "let q = quote(double(1)); macroexpand(q)",
+                Object::Error(
+                    ErrorKind::Type,
+                    "argument to `macroexpand` must be a `quote(...)` expression, got `quote(double(1))`"
+                        .into(),
+                ),
+            ),
+            (
+                "macroexpand1(double(1))",
+                Object::Error(
+                    ErrorKind::Type,
+                    "argument to `macroexpand1` must be a `quote(...)` expression, got `2`"
+                        .into(),
+                ),
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut env = Environment::new();
+            let program = define_macros(test_parse(format!("{}{}", macros, input)), &mut env);
+            let expanded = expand_macros(program, &env)?;
+            let evaluated = eval(expanded.into(), &mut Environment::new());
+            assert_eq!(evaluated, expected, "input={}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_macros_traced() -> Result<(), String> {
+        let input = r#"
+        let double = macro(e) { quote(unquote(e) * 2) };
+        let quadruple = macro(e) { quote(double(double(unquote(e)))) };
+        quadruple(1 + 2);
+        double(3);
+        "#;
+        let mut env = Environment::new();
+        let program = define_macros(test_parse(input), &mut env);
+        let (expanded, trace) = expand_macros_traced(program.clone(), &env)?;
+        assert_eq!(expanded, expand_macros(program, &env)?);
+        let trace = trace.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            trace,
+            vec![
+                "quadruple((1 + 2)) => double(double((1 + 2)))",
+                "  double(double((1 + 2))) => (double((1 + 2)) * 2)",
+                "    double((1 + 2)) => ((1 + 2) * 2)",
+                "double(3) => (3 * 2)",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hygienic_expansion() -> Result<(), String> {
+        let tests = vec![
+            // bindings of patterns are renamed, keys of hash patterns are not
+            (
+                r#"
+                let sumPair = macro(p) {
+                    quote(if (true) { let {a: [x, y]} = {"a": unquote(p)}; x + y })
+                };
+                let x = 10;
+                sumPair([x, 1]);
+                "#,
+                Object::Integer(11),
+            ),
+            // `tmp` of the template does not capture `tmp` of the call site
+            (
+                r#"
+                let addTen = macro(x) { quote(fn(tmp) { tmp + unquote(x) }(10)) };
+                let tmp = 1;
+                addTen(tmp);
+                "#,
+                Object::Integer(11),
+            ),
+            (
+                r#"
+                let addTen = macro(x) {
+                    quote(if (true) { let tmp = 10; tmp + unquote(x) })
+                };
+                let tmp = 1;
+                addTen(tmp);
+                "#,
+                Object::Integer(11),
+            ),
+            (
+                r#"
+                let double = macro(x) { quote(if (true) { let y = unquote(x); y * 2 }) };
+                let y = 3;
+                double(y + 1);
+                "#,
+                Object::Integer(8),
+            ),
+            // only references in the scope of a binding are renamed
+            (
+                r#"
+                let x = 1;
+                let m = macro(a) { quote(x + fn(x) { x * unquote(a) }(2)) };
+                m(3);
+                "#,
+                Object::Integer(7),
+            ),
+            (
+                r#"
+                let m = macro(a) {
+                    quote(if (true) { let f = fn(n) { if (n > 0) { f(n - 1) } else { y } }; f(unquote(a)) })
+                };
+                let y = 5;
+                m(2);
+                "#,
+                Object::Integer(5),
+            ),
+            // `__it` is captured as `it`
+            (
+                r#"
+                let aif = macro(c, body) {
+                    quote(if (true) { let __it = unquote(c); if (__it) { unquote(body) } })
+                };
+                aif(5, it * 2);
+                "#,
+                Object::Integer(10),
+            ),
+            (
+                r#"
+                let same = macro() { let a = gensym(); quote(unquote(a)) };
+                gensym;
+                "#,
+                Object::Error(ErrorKind::Name, "identifier not found: `gensym`".into()),
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = test_parse(input);
+            let mut env = Environment::new();
+            let program = define_macros(program, &mut env);
+            let expanded = expand_macros(program, &env)?;
+            let evaluated = eval(expanded.into(), &mut Environment::new());
+            assert_eq!(evaluated, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_gensym() -> Result<(), String> {
+        let input = r#"
+        let pair = macro() {
+            let a = gensym();
+            let b = gensym("tmp");
+            quote([unquote(a), unquote(b)])
+        };
+        pair();
+        "#;
+        let program = test_parse(input);
+        let mut env = Environment::new();
+        let program = define_macros(program, &mut env);
+        let expanded = expand_macros(program, &env)?;
+        match &expanded.statements[..] {
+            [ast::Statement::Expression(ast::Expression::Array(ids))] => match &ids[..] {
+                [ast::Expression::Identifier(a), ast::Expression::Identifier(b)] => {
+                    assert!(a.0.starts_with("gensym__"), "{}", a);
+                    assert!(b.0.starts_with("tmp__"), "{}", b);
+                }
+                _ => panic!("unexpected expansion: {}", expanded),
+            },
+            _ => panic!("unexpected expansion: {}", expanded),
+        }
+        Ok(())
+    }
+
     // helpers
 
     fn test_parse(input: impl Into<String>) -> ast::Program {
@@ -175,6 +993,13 @@ mod tests {
         parse(lexer).unwrap()
     }
 
+    fn test_expression(input: &str) -> ast::Expression {
+        match test_parse(input).statements.remove(0) {
+            ast::Statement::Expression(expr) => expr,
+            s => panic!("statement not `<expr>`. got={:?}", s),
+        }
+    }
+
     fn new_id(id: impl Into<String>) -> ast::Identifier {
         id.into().into()
     }
@@ -0,0 +1,339 @@
+use crate::ast::{self};
+use crate::evaluator::{builtins, define_macros, eval, expand_macros, prelude};
+use crate::lexer::Lexer;
+use crate::object::{Environment, ErrorKind, Object};
+use crate::parser::{self, parse};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// `import "<path>"` evaluates the file once in its own environment and caches
+// the module object, which holds the bindings declared with `export`. Paths
+// are relative to the importing file, or to the working directory outside of
+// files (e.g. in the REPL).
+
+#[derive(Default)]
+struct Modules {
+    // (canonical path, evaluated after the prelude) -> module
+    cache: HashMap<(PathBuf, bool), Object>,
+    // the files being evaluated, the innermost last
+    loading: Vec<PathBuf>,
+}
+
+thread_local! {
+    static MODULES: RefCell<Modules> = RefCell::new(Modules::default());
+}
+
+// `monkey run [--no-prelude] <file>`, after the prelude unless the context
+// disables it
+pub fn run_file(path: impl AsRef<Path>) -> Result<(), String> {
+    let res = eval_file(path);
+    if res.is_error() {
+        return Err(res.inspect());
+    }
+    Ok(())
+}
+
+// evaluates a program file, the result of its last statement
+pub(crate) fn eval_file(path: impl AsRef<Path>) -> Object {
+    let path = path.as_ref();
+    let res = path
+        .canonicalize()
+        .map_err(|e| format!("cannot read \"{}\": {}", path.display(), e))
+        .and_then(|path| load(&path));
+    match res {
+        Ok((_, res)) => res,
+        Err(e) => Object::Error(ErrorKind::Runtime, e),
+    }
+}
+
+pub(crate) fn import(path: &str) -> Object {
+    match import_module(path) {
+        Ok(m) => m,
+        Err(e) => Object::Error(
+            ErrorKind::Import,
+            format!("cannot import \"{}\": {}", path, e),
+        ),
+    }
+}
+
+// binds the macros among `names`, before the program is expanded; errors are
+// reported when the import itself is evaluated
+pub(crate) fn import_macros(path: &str, names: &[ast::Identifier], env: &mut Environment) {
+    if let Object::Module { exports, .. } = import(path) {
+        for id in names {
+            if let Some(m @ Object::Macro { .. }) = exports.get(&id.0) {
+                env.set(id, m.clone());
+            }
+        }
+    }
+}
+
+fn import_module(path: &str) -> Result<Object, String> {
+    // the file system is off limits, even to tell whether the file exists
+    if !builtins::io_enabled() {
+        return Err("I/O is disabled".into());
+    }
+    let dir = MODULES.with(|modules| {
+        modules
+            .borrow()
+            .loading
+            .last()
+            .and_then(|file| file.parent())
+            .map(Path::to_path_buf)
+    });
+    let path = match dir {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+    let path = path.canonicalize().map_err(|e| e.to_string())?;
+    let key = (path, builtins::prelude_enabled());
+    if let Some(m) = MODULES.with(|modules| modules.borrow().cache.get(&key).cloned()) {
+        return Ok(m);
+    }
+    let (exports, _) = load(&key.0)?;
+    let m = Object::Module {
+        path: key.0.display().to_string(),
+        exports,
+    };
+    MODULES.with(|modules| modules.borrow_mut().cache.insert(key, m.clone()));
+    Ok(m)
+}
+
+// the exported bindings and the result
+fn load(path: &Path) -> Result<(IndexMap<String, Object>, Object), String> {
+    let cycle = MODULES.with(|modules| {
+        let loading = &modules.borrow().loading;
+        let start = loading.iter().position(|p| p == path)?;
+        let files = loading[start..].iter().map(|p| file_name(p));
+        Some(
+            files
+                .chain(Some(file_name(path)))
+                .collect::<Vec<_>>()
+                .join(" -> "),
+        )
+    });
+    if let Some(cycle) = cycle {
+        return Err(format!("import cycle: {}", cycle));
+    }
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let prog = parse(Lexer::new(source)).map_err(|parser::Errors(e)| e.join("\n"))?;
+    MODULES.with(|modules| modules.borrow_mut().loading.push(path.into()));
+    let res = eval_module(prog);
+    MODULES.with(|modules| modules.borrow_mut().loading.pop());
+    res
+}
+
+fn eval_module(prog: ast::Program) -> Result<(IndexMap<String, Object>, Object), String> {
+    let exported = prog
+        .statements
+        .iter()
+        .flat_map(ast::Statement::exported_identifiers)
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut env = Environment::new();
+    let mut macro_env = Environment::new();
+    if builtins::prelude_enabled() {
+        prelude::load(&mut env, &mut macro_env);
+    }
+    // the program may redefine the prelude bindings, even with `const`
+    let mut env = Environment::new_with_outer(env);
+    let mut macro_env = Environment::new_with_outer(macro_env);
+    let prog = define_macros(prog, &mut macro_env);
+    let prog = expand_macros(prog, &macro_env)?;
+    let res = eval(prog.into(), &mut env);
+    if res.is_error() {
+        return Err(res.inspect());
+    }
+    let exports = exported
+        .into_iter()
+        .filter_map(|id| {
+            let value = env.get(&id).or_else(|| macro_env.get(&id))?;
+            Some((id.0, value))
+        })
+        .collect();
+    Ok((exports, res))
+}
+
+fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into(),
+        None => path.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::builtins::{Capabilities, Context};
+    use crate::evaluator::modules::eval_file;
+    use std::path::PathBuf;
+
+    #[test]
+    fn import_modules() {
+        let dir = write_files(
+            "import_modules",
+            vec![
+                (
+                    "lib/math.mk",
+                    r#"
+                    import {inc} from "util.mk";
+                    export let double = fn(x) { x * 2 };
+                    export const one = inc(0);
+                    export let [two, three] = [2, 3];
+                    export let twice = macro(e) { quote(unquote(e) + unquote(e)) };
+                    let hidden = 4;
+                    "#,
+                ),
+                ("lib/util.mk", "export let inc = fn(x) { x + 1 };"),
+                (
+                    "counter.mk",
+                    r#"import "lib/util.mk" as u; export let n = u.inc(1);"#,
+                ),
+                ("a.mk", r#"import "b.mk" as b;"#),
+                ("b.mk", r#"import "a.mk" as a;"#),
+                ("bad.mk", "let x = ;"),
+                ("fail.mk", "export let x = 1 + true;"),
+            ],
+        );
+        // (main file content, expected)
+        let tests = vec![
+            (
+                r#"import "lib/math.mk" as math; [math.double(2), math["one"], math.three]"#,
+                "[4, 1, 3]",
+            ),
+            (
+                r#"import {double, twice} from "lib/math.mk"; twice(double(1))"#,
+                "4",
+            ),
+            // relative to the importing file
+            (
+                r#"import "lib/math.mk" as m; import "counter.mk" as c; [m.one, c.n]"#,
+                "[1, 2]",
+            ),
+            (
+                r#"import "lib/math.mk" as math; math.hidden"#,
+                "module \"{dir}/lib/math.mk\" has no export `hidden`",
+            ),
+            (
+                r#"import "lib/math.mk" as math; try { math.hidden } catch (e) { e["kind"] }"#,
+                r#""ImportError""#,
+            ),
+            (
+                r#"try { import "missing.mk" as m; } catch (e) { e["kind"] }"#,
+                r#""ImportError""#,
+            ),
+            (
+                r#"import {hidden} from "lib/math.mk";"#,
+                "module \"{dir}/lib/math.mk\" has no export `hidden`",
+            ),
+            (
+                r#"import "a.mk" as a;"#,
+                r#"cannot import "a.mk": cannot import "b.mk": cannot import "a.mk": import cycle: a.mk -> b.mk -> a.mk"#,
+            ),
+            (
+                r#"import "missing.mk" as m;"#,
+                r#"cannot import "missing.mk": No such file or directory (os error 2)"#,
+            ),
+            (
+                r#"import "bad.mk" as m;"#,
+                r#"cannot import "bad.mk": 1:9: could not parse Semicolon as prefix expression"#,
+            ),
+            (
+                r#"import "fail.mk" as m;"#,
+                r#"cannot import "fail.mk": unknown operator: `1 + true`"#,
+            ),
+            (
+                r#"const m = 1; import "counter.mk" as m;"#,
+                "cannot reassign constant `m`",
+            ),
+        ];
+        let _context = Context::new().with_prelude(false).enter();
+        for (input, expected) in tests {
+            let path = dir.join("main.mk");
+            std::fs::write(&path, input).unwrap();
+            let expected = expected.replace("{dir}", &dir.display().to_string());
+            assert_eq!(eval_file(&path).inspect(), expected, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn prelude() {
+        let dir = write_files(
+            "prelude",
+            vec![(
+                "lib.mk",
+                "export let doubles = fn(arr) { map(arr, fn(x) { x * 2 }) };",
+            )],
+        );
+        // (main file content, prelude, expected)
+        let tests = vec![
+            (
+                r#"import {doubles} from "lib.mk"; sum(doubles([1, 2]))"#,
+                true,
+                "6",
+            ),
+            ("sum([1])", false, "identifier not found: `sum`"),
+            // the module is cached with the setting it was evaluated with
+            (
+                r#"import {doubles} from "lib.mk"; doubles([1])"#,
+                false,
+                "identifier not found: `map`",
+            ),
+            (
+                r#"import {doubles} from "lib.mk"; doubles([1])"#,
+                true,
+                "[2]",
+            ),
+        ];
+        for (input, prelude, expected) in tests {
+            let path = dir.join("main.mk");
+            std::fs::write(&path, input).unwrap();
+            let _context = Context::new().with_prelude(prelude).enter();
+            assert_eq!(eval_file(&path).inspect(), expected, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn import_without_io() {
+        let dir = write_files("import_without_io", vec![("lib.mk", "export let x = 1;")]);
+        let tests = vec![
+            (
+                r#"import "lib.mk" as lib; lib.x"#,
+                r#"cannot import "lib.mk": I/O is disabled"#,
+            ),
+            (
+                r#"import {x} from "lib.mk"; x"#,
+                r#"cannot import "lib.mk": I/O is disabled"#,
+            ),
+            (
+                r#"import "/etc/passwd" as p;"#,
+                r#"cannot import "/etc/passwd": I/O is disabled"#,
+            ),
+            (
+                r#"import "missing.mk" as m;"#,
+                r#"cannot import "missing.mk": I/O is disabled"#,
+            ),
+        ];
+        let _context = Context::new()
+            .with_capabilities(Capabilities { io: false })
+            .with_prelude(false)
+            .enter();
+        for (input, expected) in tests {
+            let path = dir.join("main.mk");
+            std::fs::write(&path, input).unwrap();
+            assert_eq!(eval_file(&path).inspect(), expected, "input={}", input);
+        }
+    }
+
+    // files in a new directory under the temporary directory
+    fn write_files(name: &str, files: Vec<(&str, &str)>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("monkey-{}-{}", name, std::process::id()));
+        for (file, content) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+}
@@ -0,0 +1,9 @@
+// The prelude, evaluated before every program unless disabled, after the
+// list functions written in Rust (see prelude.rs).
+// Each definition is evaluated on its own: it sees the builtins and itself,
+// not the other definitions.
+
+// unless(x > 1, "small", "big")
+let unless = macro(condition, consequence, alternative) {
+    quote(if (unquote(condition)) { unquote(alternative) } else { unquote(consequence) })
+};
@@ -0,0 +1,383 @@
+use crate::ast::{self};
+use crate::evaluator::builtins::{
+    new_not_supported_error, new_wrong_number_arguments_error, BuiltinFunction,
+};
+use crate::evaluator::{
+    apply_function, define_macros, eval, eval_infix_objects, expand_macros, is_truthy,
+};
+use crate::lexer::Lexer;
+use crate::object::{Environment, Object};
+use crate::parser::parse;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+
+// helpers written in Monkey, bundled into the binary
+const SOURCE: &str = include_str!("prelude.mk");
+
+// the list functions, written in Rust: in Monkey they recurse once per
+// element, which overflows the stack on lists of a few hundred elements
+const FUNCTIONS: &[(&str, &str, BuiltinFunction)] = &[
+    ("map", "fn(arr, f)", map),
+    ("filter", "fn(arr, f)", filter),
+    ("reduce", "fn(arr, initial, f)", reduce),
+    ("sum", "fn(arr)", sum),
+    ("find", "fn(arr, f)", find),
+    ("any", "fn(arr, f)", any),
+    ("all", "fn(arr, f)", all),
+    ("contains", "fn(arr, value)", contains),
+    ("reverse", "fn(arr)", reverse),
+    ("range", "fn(start, end)", range),
+];
+
+lazy_static! {
+    // name -> signature, e.g. `let map = fn(arr, f)`
+    static ref DEFINITIONS: IndexMap<String, String> = program()
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            ast::Statement::Let {
+                identifier,
+                expression,
+            } => {
+                let value = match expression {
+                    ast::Expression::Function(f) => format!("fn({})", f.params.iter().join(", ")),
+                    ast::Expression::Macro(m) => format!("macro({})", m.params.iter().join(", ")),
+                    e => e.to_string(),
+                };
+                let signature = format!("let {} = {}", identifier, value);
+                Some((identifier.0.clone(), signature))
+            }
+            _ => None,
+        })
+        .chain(FUNCTIONS.iter().map(|&(name, params, _)| {
+            (name.to_string(), format!("let {} = {}", name, params))
+        }))
+        .collect();
+}
+
+// the functions and macros defined by the prelude
+pub fn is_defined(name: &str) -> bool {
+    DEFINITIONS.contains_key(name)
+}
+
+// (name, signature) of the prelude definitions, sorted by name
+pub fn signatures() -> Vec<(&'static str, &'static str)> {
+    DEFINITIONS
+        .iter()
+        .map(|(name, signature)| (name.as_str(), signature.as_str()))
+        .sorted()
+        .collect()
+}
+
+// binds the prelude functions in `env` and its macros in `macro_env`
+pub fn load(env: &mut Environment, macro_env: &mut Environment) {
+    for &(name, _, f) in FUNCTIONS {
+        env.set(&ast::Identifier(name.into()), Object::Builtin(f));
+    }
+    let prog = define_macros(program(), macro_env);
+    let prog = expand_macros(prog, macro_env).expect("prelude expands");
+    for stmt in prog.statements {
+        let id = match &stmt {
+            ast::Statement::Let { identifier, .. } => identifier.clone(),
+            _ => continue,
+        };
+        // in an environment of its own, closures copy their environment and
+        // would otherwise copy every previous definition
+        let mut scope = Environment::new();
+        let res = eval(ast::Node::Statement(stmt), &mut scope);
+        assert!(!res.is_error(), "prelude `{}`: {}", id, res.inspect());
+        if let Some(value) = scope.get(&id) {
+            env.set(&id, value);
+        }
+    }
+}
+
+fn program() -> ast::Program {
+    parse(Lexer::new(SOURCE)).expect("prelude parses")
+}
+
+// functions
+
+// map([1, 2], fn(x) { x * 2 }) => [2, 4]
+fn map(args: Vec<Object>) -> Object {
+    let (arr, f) = match array_and_function("map", args) {
+        Ok(it) => it,
+        Err(e) => return *e,
+    };
+    let mut res = Vec::new();
+    for x in arr {
+        let y = apply_function(f.clone(), vec![x]);
+        if y.is_error() {
+            return y;
+        }
+        res.push(y);
+    }
+    Object::Array(res)
+}
+
+// filter([1, 2, 3], fn(x) { x > 1 }) => [2, 3]
+fn filter(args: Vec<Object>) -> Object {
+    let (arr, f) = match array_and_function("filter", args) {
+        Ok(it) => it,
+        Err(e) => return *e,
+    };
+    let mut res = Vec::new();
+    for x in arr {
+        let keep = apply_function(f.clone(), vec![x.clone()]);
+        if keep.is_error() {
+            return keep;
+        }
+        if is_truthy(keep) {
+            res.push(x);
+        }
+    }
+    Object::Array(res)
+}
+
+// reduce([1, 2, 3], 0, fn(acc, x) { acc + x }) => 6
+fn reduce(mut args: Vec<Object>) -> Object {
+    if args.len() != 3 {
+        return new_wrong_number_arguments_error(args.len(), 3);
+    }
+    let f = args.remove(2);
+    let mut acc = args.remove(1);
+    let arr = match args.remove(0) {
+        Object::Array(arr) => arr,
+        o => return new_not_supported_error("reduce", &o),
+    };
+    for x in arr {
+        acc = apply_function(f.clone(), vec![acc, x]);
+        if acc.is_error() {
+            return acc;
+        }
+    }
+    acc
+}
+
+// sum([1, 2, 3]) => 6
+fn sum(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return new_wrong_number_arguments_error(args.len(), 1);
+    }
+    let arr = match &args[0] {
+        Object::Array(arr) => arr,
+        o => return new_not_supported_error("sum", o),
+    };
+    let mut acc = Object::Integer(0);
+    for x in arr {
+        acc = eval_infix_objects(ast::InfixOperator::Add, acc, x.clone());
+        if acc.is_error() {
+            return acc;
+        }
+    }
+    acc
+}
+
+// find([1, 2, 3], fn(x) { x > 1 }) => 2, null if there is none
+fn find(args: Vec<Object>) -> Object {
+    let (arr, f) = match array_and_function("find", args) {
+        Ok(it) => it,
+        Err(e) => return *e,
+    };
+    for x in arr {
+        let found = apply_function(f.clone(), vec![x.clone()]);
+        if found.is_error() {
+            return found;
+        }
+        if is_truthy(found) {
+            return x;
+        }
+    }
+    Object::Null
+}
+
+// any([1, 2], fn(x) { x > 1 }) => true
+fn any(args: Vec<Object>) -> Object {
+    let (arr, f) = match array_and_function("any", args) {
+        Ok(it) => it,
+        Err(e) => return *e,
+    };
+    for x in arr {
+        let res = apply_function(f.clone(), vec![x]);
+        if res.is_error() {
+            return res;
+        }
+        if is_truthy(res) {
+            return Object::Boolean(true);
+        }
+    }
+    Object::Boolean(false)
+}
+
+// all([1, 2], fn(x) { x > 1 }) => false
+fn all(args: Vec<Object>) -> Object {
+    let (arr, f) = match array_and_function("all", args) {
+        Ok(it) => it,
+        Err(e) => return *e,
+    };
+    for x in arr {
+        let res = apply_function(f.clone(), vec![x]);
+        if res.is_error() {
+            return res;
+        }
+        if !is_truthy(res) {
+            return Object::Boolean(false);
+        }
+    }
+    Object::Boolean(true)
+}
+
+// contains([1, 2], 2) => true
+fn contains(args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return new_wrong_number_arguments_error(args.len(), 2);
+    }
+    match &args[0] {
+        Object::Array(arr) => Object::Boolean(arr.contains(&args[1])),
+        o => new_not_supported_error("contains", o),
+    }
+}
+
+// reverse([1, 2, 3]) => [3, 2, 1]
+fn reverse(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return new_wrong_number_arguments_error(args.len(), 1);
+    }
+    match &args[0] {
+        Object::Array(arr) => Object::Array(arr.iter().rev().cloned().collect()),
+        o => new_not_supported_error("reverse", o),
+    }
+}
+
+// range(1, 4) => [1, 2, 3]
+fn range(args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return new_wrong_number_arguments_error(args.len(), 2);
+    }
+    match (&args[0], &args[1]) {
+        (Object::Integer(start), Object::Integer(end)) => {
+            Object::Array((*start..*end).map(Object::Integer).collect())
+        }
+        (Object::Integer(_), o) | (o, _) => new_not_supported_error("range", o),
+    }
+}
+
+// (arr, f) of `<name>(arr, f)`
+fn array_and_function(
+    name: &str,
+    mut args: Vec<Object>,
+) -> Result<(Vec<Object>, Object), Box<Object>> {
+    if args.len() != 2 {
+        return Err(new_wrong_number_arguments_error(args.len(), 2).into());
+    }
+    let f = args.remove(1);
+    match args.remove(0) {
+        Object::Array(arr) => Ok((arr, f)),
+        o => Err(new_not_supported_error(name, &o).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::prelude::{is_defined, load, signatures};
+    use crate::evaluator::{define_macros, eval, expand_macros};
+    use crate::lexer::Lexer;
+    use crate::object::Environment;
+    use crate::parser::parse;
+
+    #[test]
+    fn prelude_functions() {
+        let tests = vec![
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("map([], fn(x) { x * 2 })", "[]"),
+            ("filter([1, 2, 3, 4], fn(x) { x > 2 })", "[3, 4]"),
+            ("filter([1, 2], fn(x) { false })", "[]"),
+            ("reduce([1, 2, 3], 10, fn(acc, x) { acc + x })", "16"),
+            (
+                r#"reduce(["a", "b"], "", fn(acc, x) { x + acc })"#,
+                r#""ba""#,
+            ),
+            ("reduce([], 1, fn(acc, x) { acc * x })", "1"),
+            ("sum([1, 2, 3])", "6"),
+            ("sum([])", "0"),
+            ("find([1, 2, 3], fn(x) { x > 1 })", "2"),
+            ("find([1, 2, 3], fn(x) { x > 3 })", "null"),
+            ("any([1, 2], fn(x) { x > 1 })", "true"),
+            ("any([], fn(x) { true })", "false"),
+            ("all([1, 2], fn(x) { x > 0 })", "true"),
+            ("all([1, 2], fn(x) { x > 1 })", "false"),
+            ("contains([1, [2], 3], [2])", "true"),
+            (r#"contains(["a"], "b")"#, "false"),
+            ("reverse([1, 2, 3])", "[3, 2, 1]"),
+            ("reverse([])", "[]"),
+            ("range(1, 4)", "[1, 2, 3]"),
+            ("range(2, 2)", "[]"),
+            // no recursion per element
+            ("sum(range(0, 10000))", "49995000"),
+            ("len(map(range(0, 10000), fn(x) { x * 2 }))", "10000"),
+            ("len(filter(range(0, 10000), fn(x) { x > 9000 }))", "999"),
+            (
+                "reduce(range(0, 10000), 0, fn(acc, x) { acc + 1 })",
+                "10000",
+            ),
+            ("find(range(0, 10000), fn(x) { x == 9999 })", "9999"),
+            ("all(range(0, 10000), fn(x) { x > -1 })", "true"),
+            ("first(reverse(range(0, 10000)))", "9999"),
+            // errors
+            (
+                "map(1, fn(x) { x })",
+                "argument to `map` not supported, got `1`",
+            ),
+            ("sum([1, true])", "unknown operator: `1 + true`"),
+            ("range(1)", "wrong number of arguments. got=1, want=2"),
+            (
+                r#"range(1, "a")"#,
+                "argument to `range` not supported, got `a`",
+            ),
+            ("map([1], fn(x) { throw(x) })", "Error: 1"),
+            (r#"unless(1 > 2, "small", "big")"#, r#""small""#),
+            (r#"unless(true, throw("evaluated"), "big")"#, r#""big""#),
+            // prelude functions can be redefined
+            ("let map = fn(x) { x }; map(1)", "1"),
+            ("const sum = 1; sum", "1"),
+            // the prelude doesn't see the program's bindings
+            (
+                "let first = fn(arr) { 0 }; map([1, 2], fn(x) { x })",
+                "[1, 2]",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut env = Environment::new();
+            let mut macro_env = Environment::new();
+            load(&mut env, &mut macro_env);
+            let mut env = Environment::new_with_outer(env);
+            let prog = parse(Lexer::new(input)).unwrap();
+            let prog = define_macros(prog, &mut macro_env);
+            let prog = expand_macros(prog, &macro_env).unwrap();
+            assert_eq!(
+                eval(prog.into(), &mut env).inspect(),
+                expected,
+                "input={}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn prelude_names() {
+        for name in &[
+            "map", "filter", "reduce", "sum", "find", "any", "all", "contains", "reverse", "range",
+            "unless",
+        ] {
+            assert!(is_defined(name), "{}", name);
+        }
+        assert!(!is_defined("iter"));
+        assert!(!is_defined("len"));
+        assert!(signatures().contains(&("map", "let map = fn(arr, f)")));
+        assert!(signatures().contains(&(
+            "unless",
+            "let unless = macro(condition, consequence, alternative)"
+        )));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::ast::{self};
 use crate::evaluator::eval;
 use crate::object::{Environment, Object};
+use std::convert::TryFrom;
 
 pub type Error = String;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -11,26 +12,115 @@ pub fn quote(node: ast::Node, env: &mut Environment) -> Result<Object> {
 }
 
 fn eval_unquote_calls(quoted: ast::Node, env: &mut Environment) -> Result<ast::Node> {
-    ast::modify(quoted, |node| match node {
-        ast::Node::Expression(expr) => match expr {
-            ast::Expression::Unquote(expr) => {
-                let expr = *expr;
-                eval(expr.into(), env).into()
+    // the modifier cannot fail, the first error is kept and returned afterwards
+    let mut error = None;
+    let node = ast::modify(quoted, |node| match node {
+        ast::Node::Expression(ast::Expression::Unquote(expr)) if error.is_none() => {
+            let expr = *expr;
+            match ast::Node::try_from(eval(expr.clone().into(), env)) {
+                Ok(node) => node,
+                Err(e) => {
+                    error = Some(e);
+                    ast::Expression::Unquote(expr.into()).into()
+                }
             }
-            other => other.into(),
-        },
+        }
         _ => node,
-    })
+    })?;
+    match error {
+        Some(e) => Err(e),
+        None => Ok(node),
+    }
 }
 
 // Object -> AST Node
-impl std::convert::From<Object> for ast::Node {
-    fn from(obj: Object) -> ast::Node {
+impl TryFrom<Object> for ast::Node {
+    type Error = Error;
+
+    fn try_from(obj: Object) -> Result<ast::Node> {
+        Ok(ast::Expression::try_from(obj)?.into())
+    }
+}
+
+impl TryFrom<Object> for ast::Expression {
+    type Error = Error;
+
+    fn try_from(obj: Object) -> Result<ast::Expression> {
         match obj {
-            Object::Integer(it) => ast::Expression::Integer(it).into(),
-            Object::Boolean(it) => ast::Expression::Boolean(it).into(),
-            Object::Quote(node) => node,
-            _ => panic!("not implemented"),
+            Object::Integer(it) => Ok(ast::Expression::Integer(it)),
+            Object::Boolean(it) => Ok(ast::Expression::Boolean(it)),
+            Object::String(it) => Ok(ast::Expression::String(it)),
+            Object::Array(elements) => {
+                let elements = elements
+                    .into_iter()
+                    .map(ast::Expression::try_from)
+                    .collect::<Result<_>>()?;
+                Ok(ast::Expression::Array(elements))
+            }
+            Object::Hash(pairs) => {
+                let pairs = pairs
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let k = ast::Expression::try_from(Object::from(k))?;
+                        Ok(ast::HashEntry::Pair(k, ast::Expression::try_from(v)?))
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(ast::Expression::Hash(pairs))
+            }
+            // `if (false) {}` evaluates to null
+            Object::Null => Ok(ast::Expression::If {
+                condition: ast::Expression::Boolean(false).into(),
+                consequence: ast::BlockStatement { statements: vec![] },
+                alternative: None,
+            }),
+            Object::Quote(node) => node.expression(),
+            Object::Error(_, e) => Err(e),
+            o => Err(format!("could not convert `{}` to AST node", o)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast;
+    use crate::evaluator::quote_unquote::quote;
+    use crate::lexer::Lexer;
+    use crate::object::{Environment, Object};
+    use crate::parser::parse;
+
+    #[test]
+    fn test_quote_unquote_objects() {
+        let tests = vec![
+            (r#"quote(unquote("a" + "b"))"#, Ok(r#""ab""#)),
+            ("quote(unquote([1, [true]]))", Ok("[1, [true]]")),
+            (
+                r#"quote(unquote({"a": 1, 2: [3]}))"#,
+                Ok(r#"{"a": 1, 2: [3]}"#),
+            ),
+            ("quote(unquote(if (false) { 1 }))", Ok("if false {  }")),
+            ("quote(unquote([quote(a + b)]))", Ok("[(a + b)]")),
+            ("quote(fn(a = unquote(1 + 2)) { a })", Ok("fn(a = 3) a")),
+            (
+                "quote(unquote(fn(x) { x }))",
+                Err("could not convert `fn(x) {\nx\n}` to AST node"),
+            ),
+            ("quote(unquote(foo))", Err("identifier not found: `foo`")),
+            (
+                "quote(unquote(1 + true) + unquote(foo))",
+                Err("unknown operator: `1 + true`"),
+            ),
+        ];
+        for (input, expected) in tests {
+            let node = parse(Lexer::new(input)).unwrap().statements[0].clone();
+            let node = match node {
+                ast::Statement::Expression(ast::Expression::Quote(expr)) => *expr,
+                s => panic!("statement is not quote. got={:?}", s),
+            };
+            let quoted = quote(node.into(), &mut Environment::new()).map(|q| match q {
+                Object::Quote(node) => node.to_string(),
+                o => panic!("object is not quote. got={:?}", o),
+            });
+            assert_eq!(quoted, expected.map(String::from).map_err(String::from));
         }
     }
 }
@@ -0,0 +1,710 @@
+use crate::ast::{self};
+use crate::lexer::Lexer;
+use crate::parser::{self, Precedence};
+use itertools::Itertools;
+
+const INDENT: &str = "    ";
+
+// source -> formatted source
+pub fn format(input: impl Into<String>) -> Result<String, Box<dyn std::error::Error>> {
+    let lexer = Lexer::new_with_comments(input);
+    let program = parser::parse(lexer)?;
+    Ok(format_program(&program))
+}
+
+pub(crate) fn format_program(program: &ast::Program) -> String {
+    let mut formatter = Formatter { indent: 0 };
+    formatter.program(program)
+}
+
+// Formatter
+
+struct Formatter {
+    indent: usize,
+}
+
+impl Formatter {
+    fn program(&mut self, program: &ast::Program) -> String {
+        let stmts = self.statements(&program.statements, false);
+        let mut res = String::new();
+        for (i, (leading, s)) in stmts.iter().enumerate() {
+            if i > 0 {
+                // separate multi-line statements (e.g. functions) with a blank line
+                let prev = &stmts[i - 1].1;
+                if prev.contains('\n') || s.contains('\n') {
+                    res.push('\n');
+                }
+            }
+            res.push_str(leading);
+            res.push_str(s);
+            res.push('\n');
+        }
+        res
+    }
+
+    // (leading comments, statement with its trailing comments)
+    fn statements(&mut self, stmts: &[ast::Statement], in_block: bool) -> Vec<(String, String)> {
+        let indent = INDENT.repeat(self.indent);
+        let mut res = Vec::new();
+        for (i, stmt) in stmts.iter().enumerate() {
+            let (comments, stmt) = match stmt {
+                ast::Statement::Commented(comments, stmt) => (Some(comments), stmt.as_ref()),
+                _ => (None, stmt),
+            };
+            let mut s = self.statement(stmt);
+            if let ast::Statement::Expression(expr) = stmt {
+                let next = stmts[i + 1..]
+                    .iter()
+                    .find(|s| !matches!(s, ast::Statement::Comment(_)));
+                if Self::needs_semicolon(expr, next, in_block) {
+                    s.push(';');
+                }
+            }
+            let mut leading = String::new();
+            if let Some(comments) = comments {
+                for c in comments.leading.iter() {
+                    leading.push_str(&format!("{}\n{}", c, indent));
+                }
+                for c in comments.trailing.iter() {
+                    s.push(' ');
+                    s.push_str(c);
+                }
+            }
+            res.push((leading, s));
+        }
+        res
+    }
+
+    fn needs_semicolon(
+        expr: &ast::Expression,
+        next: Option<&ast::Statement>,
+        in_block: bool,
+    ) -> bool {
+        let next = next.map(|stmt| match stmt {
+            ast::Statement::Commented(_, stmt) => stmt.as_ref(),
+            _ => stmt,
+        });
+        match (expr, next) {
+            // `if (x) { a } -1` would be parsed as an infix expression
+            (ast::Expression::If { .. }, Some(ast::Statement::Expression(next)))
+            | (ast::Expression::Try { .. }, Some(ast::Statement::Expression(next)))
+            | (ast::Expression::Match { .. }, Some(ast::Statement::Expression(next))) => {
+                Self::starts_like_infix(next)
+            }
+            (ast::Expression::If { .. }, _)
+            | (ast::Expression::Try { .. }, _)
+            | (ast::Expression::Match { .. }, _) => false,
+            // the last expression of a block is its value
+            (_, None) => !in_block,
+            _ => true,
+        }
+    }
+
+    fn starts_like_infix(expr: &ast::Expression) -> bool {
+        match expr {
+            ast::Expression::Commented(_, expr) => Self::starts_like_infix(expr),
+            ast::Expression::Prefix {
+                operator: ast::PrefixOperator::Minus,
+                ..
+            } => true,
+            ast::Expression::Array(_) => true,
+            ast::Expression::Infix { left, .. } => {
+                Self::needs_parens_as_left_operand(left, expr) || Self::starts_like_infix(left)
+            }
+            ast::Expression::Index { left, .. }
+            | ast::Expression::OptionalIndex { left, .. }
+            | ast::Expression::Member { left, .. }
+            | ast::Expression::OptionalCall { function: left, .. } => {
+                Self::is_operator(left) || Self::starts_like_infix(left)
+            }
+            _ => false,
+        }
+    }
+
+    fn statement(&mut self, stmt: &ast::Statement) -> String {
+        match stmt {
+            ast::Statement::Let {
+                identifier,
+                expression,
+            } => format!("let {} = {};", identifier, self.expression(expression)),
+            ast::Statement::Const {
+                identifier,
+                expression,
+            } => format!("const {} = {};", identifier, self.expression(expression)),
+            ast::Statement::Destructure {
+                pattern,
+                expression,
+            } => format!("let {} = {};", pattern, self.expression(expression)),
+            ast::Statement::Import { .. } => stmt.to_string(),
+            ast::Statement::Export(stmt) => format!("export {}", self.statement(stmt)),
+            ast::Statement::Return(expr) => format!("return {};", self.expression(expr)),
+            ast::Statement::Expression(expr) => self.expression(expr),
+            ast::Statement::Block(block) => self.block(block),
+            ast::Statement::Comment(it) => it.clone(),
+            // formatted with the comments by `statements`
+            ast::Statement::Commented(_, stmt) => self.statement(stmt),
+        }
+    }
+
+    fn block(&mut self, block: &ast::BlockStatement) -> String {
+        if block.statements.is_empty() {
+            return "{}".into();
+        }
+        self.indent += 1;
+        let indent = INDENT.repeat(self.indent);
+        let stmts = self
+            .statements(&block.statements, true)
+            .into_iter()
+            .map(|(leading, s)| Self::indent_lines(&(leading + &s), &indent))
+            .join("\n");
+        self.indent -= 1;
+        format!("{{\n{}\n{}}}", stmts, INDENT.repeat(self.indent))
+    }
+
+    fn indent_lines(s: &str, indent: &str) -> String {
+        // nested blocks are already indented relative to this statement
+        let mut lines = s.lines();
+        let first = lines.next().unwrap_or_default();
+        let mut res = format!("{}{}", indent, first);
+        for l in lines {
+            res.push('\n');
+            res.push_str(l);
+        }
+        res
+    }
+
+    fn expression(&mut self, expr: &ast::Expression) -> String {
+        match expr {
+            ast::Expression::Identifier(id) => id.to_string(),
+            ast::Expression::Integer(n) => n.to_string(),
+            ast::Expression::Boolean(b) => b.to_string(),
+            ast::Expression::String(s) => format!(r#""{}""#, s),
+            ast::Expression::Array(elements) => {
+                format!("[{}]", self.expression_list(elements))
+            }
+            ast::Expression::Hash(entries) => {
+                let entries = self.list(entries, Self::entry_breaks_line, Self::entry);
+                format!("{{{}}}", entries)
+            }
+            ast::Expression::Spread(expr) => format!("...{}", self.expression(expr)),
+            ast::Expression::Prefix { operator, right } => {
+                let right_s = self.expression(right);
+                if Self::is_operator(right) {
+                    format!("{}({})", operator, right_s)
+                } else {
+                    format!("{}{}", operator, right_s)
+                }
+            }
+            ast::Expression::Infix {
+                left,
+                operator,
+                right,
+            } => {
+                let mut left_s = self.expression(left);
+                if Self::needs_parens_as_left_operand(left, expr) {
+                    left_s = format!("({})", left_s);
+                }
+                let mut right_s = self.expression(right);
+                if Self::needs_parens_as_right_operand(right, expr) {
+                    right_s = format!("({})", right_s);
+                }
+                format!("{} {} {}", left_s, operator, right_s)
+            }
+            ast::Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let mut s = format!(
+                    "if ({}) {}",
+                    self.expression(condition),
+                    self.block(consequence)
+                );
+                if let Some(alt) = alternative {
+                    s.push_str(&format!(" else {}", self.block(alt)));
+                }
+                s
+            }
+            ast::Expression::Try {
+                block,
+                catch,
+                finally,
+            } => {
+                let mut s = format!("try {}", self.block(block));
+                if let Some(c) = catch {
+                    s.push_str(&format!(
+                        " catch ({}) {}",
+                        c.identifier,
+                        self.block(&c.body)
+                    ));
+                }
+                if let Some(fin) = finally {
+                    s.push_str(&format!(" finally {}", self.block(fin)));
+                }
+                s
+            }
+            ast::Expression::Match { value, arms } => {
+                let value = self.expression(value);
+                if arms.is_empty() {
+                    return format!("match ({}) {{}}", value);
+                }
+                self.indent += 1;
+                let indent = INDENT.repeat(self.indent);
+                let n = arms.len();
+                let arms = arms
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arm)| {
+                        // the comments before the body lead the arm
+                        let (comments, body) = match &arm.body {
+                            ast::Expression::Commented(comments, body) => (Some(comments), &**body),
+                            body => (None, body),
+                        };
+                        let mut s = String::new();
+                        for c in comments.iter().flat_map(|c| c.leading.iter()) {
+                            s.push_str(&format!("{}\n{}", c, indent));
+                        }
+                        s.push_str(&arm.pattern.to_string());
+                        if let Some(guard) = &arm.guard {
+                            s.push_str(&format!(" if {}", self.expression(guard)));
+                        }
+                        s.push_str(&format!(" => {}", self.expression(body)));
+                        if i + 1 < n {
+                            s.push(',');
+                        }
+                        for c in comments.iter().flat_map(|c| c.trailing.iter()) {
+                            s.push(' ');
+                            s.push_str(c);
+                        }
+                        Self::indent_lines(&s, &indent)
+                    })
+                    .join("\n");
+                self.indent -= 1;
+                format!(
+                    "match ({}) {{\n{}\n{}}}",
+                    value,
+                    arms,
+                    INDENT.repeat(self.indent)
+                )
+            }
+            ast::Expression::Function(f) => self.function(f),
+            ast::Expression::Call { function, args } => {
+                let function = match function {
+                    ast::CallExpressionFunction::Identifier(id) => id.to_string(),
+                    ast::CallExpressionFunction::Function(f) => self.function(f),
+                    ast::CallExpressionFunction::Expression(f) => self.operand(f),
+                };
+                format!("{}({})", function, self.expression_list(args))
+            }
+            ast::Expression::Index { left, index } => {
+                format!("{}[{}]", self.operand(left), self.expression(index))
+            }
+            ast::Expression::OptionalIndex { left, index } => {
+                format!("{}?.[{}]", self.operand(left), self.expression(index))
+            }
+            ast::Expression::Member { left, name } => format!("{}.{}", self.operand(left), name),
+            ast::Expression::OptionalCall { function, args } => {
+                format!(
+                    "{}?.({})",
+                    self.operand(function),
+                    self.expression_list(args)
+                )
+            }
+            ast::Expression::Quote(expr) => format!("quote({})", self.expression(expr)),
+            ast::Expression::Unquote(expr) => format!("unquote({})", self.expression(expr)),
+            ast::Expression::Macro(m) => {
+                let params = self.params(&m.params);
+                format!("macro({}) {}", params, self.block(&m.body))
+            }
+            ast::Expression::Commented(comments, expr) => {
+                let mut s = self.leading_comments(&comments.leading);
+                s.push_str(&self.expression(expr));
+                for c in comments.trailing.iter() {
+                    s.push(' ');
+                    s.push_str(c);
+                }
+                if comments.trailing.iter().any(|c| c.starts_with("//")) {
+                    s.push_str(&format!("\n{}", INDENT.repeat(self.indent)));
+                }
+                s
+            }
+        }
+    }
+
+    // a line comment ends the line
+    fn leading_comments(&self, comments: &[String]) -> String {
+        let indent = INDENT.repeat(self.indent);
+        comments
+            .iter()
+            .map(|c| {
+                if c.starts_with("//") {
+                    format!("{}\n{}", c, indent)
+                } else {
+                    format!("{} ", c)
+                }
+            })
+            .collect()
+    }
+
+    fn function(&mut self, f: &ast::FunctionExpression) -> String {
+        let params = self.params(&f.params);
+        format!("fn({}) {}", params, self.block(&f.body))
+    }
+
+    fn params(&mut self, params: &[ast::Parameter]) -> String {
+        params
+            .iter()
+            .map(|p| match p {
+                ast::Parameter::Identifier(id) => id.to_string(),
+                ast::Parameter::Default(id, default) => {
+                    format!("{} = {}", id, self.expression(default))
+                }
+                ast::Parameter::Rest(id) => format!("...{}", id),
+                ast::Parameter::Pattern(p) => p.to_string(),
+            })
+            .join(", ")
+    }
+
+    fn expression_list(&mut self, exprs: &[ast::Expression]) -> String {
+        self.list(exprs, Self::breaks_line, Self::item)
+    }
+
+    // on one line, or one item per line if a comment would end the line
+    fn list<T>(
+        &mut self,
+        items: &[T],
+        breaks_line: fn(&T) -> bool,
+        item: fn(&mut Self, &T) -> (String, Vec<String>),
+    ) -> String {
+        if !items.iter().any(breaks_line) {
+            return items
+                .iter()
+                .map(|it| {
+                    let (s, trailing) = item(self, it);
+                    std::iter::once(s).chain(trailing).join(" ")
+                })
+                .join(", ");
+        }
+        self.indent += 1;
+        let indent = INDENT.repeat(self.indent);
+        let mut lines = Vec::new();
+        for (i, it) in items.iter().enumerate() {
+            let (mut s, trailing) = item(self, it);
+            if i + 1 < items.len() {
+                s.push(',');
+            }
+            for c in trailing {
+                s.push(' ');
+                s.push_str(&c);
+            }
+            lines.push(Self::indent_lines(&s, &indent));
+        }
+        self.indent -= 1;
+        format!("\n{}\n{}", lines.join("\n"), INDENT.repeat(self.indent))
+    }
+
+    // (item with its leading comments, trailing comments)
+    fn item(&mut self, expr: &ast::Expression) -> (String, Vec<String>) {
+        match expr {
+            ast::Expression::Commented(comments, expr) => {
+                let s = self.leading_comments(&comments.leading) + &self.expression(expr);
+                (s, comments.trailing.clone())
+            }
+            _ => (self.expression(expr), Vec::new()),
+        }
+    }
+
+    fn entry(&mut self, entry: &ast::HashEntry) -> (String, Vec<String>) {
+        match entry {
+            ast::HashEntry::Pair(k, v) => {
+                let (v, trailing) = self.item(v);
+                (format!("{}: {}", self.expression(k), v), trailing)
+            }
+            ast::HashEntry::Spread(h) => {
+                let (h, trailing) = self.item(h);
+                (format!("...{}", h), trailing)
+            }
+        }
+    }
+
+    // a line comment, or a block comment over several lines
+    fn breaks_line(expr: &ast::Expression) -> bool {
+        match expr {
+            ast::Expression::Commented(comments, _) => comments
+                .leading
+                .iter()
+                .chain(comments.trailing.iter())
+                .any(|c| c.starts_with("//") || c.contains('\n')),
+            _ => false,
+        }
+    }
+
+    fn entry_breaks_line(entry: &ast::HashEntry) -> bool {
+        match entry {
+            ast::HashEntry::Pair(k, v) => Self::breaks_line(k) || Self::breaks_line(v),
+            ast::HashEntry::Spread(h) => Self::breaks_line(h),
+        }
+    }
+
+    fn operand(&mut self, expr: &ast::Expression) -> String {
+        // the left side of `[...]`, `?.[...]` and `?.(...)`
+        let s = self.expression(expr);
+        if Self::is_operator(expr) {
+            format!("({})", s)
+        } else {
+            s
+        }
+    }
+
+    fn is_operator(expr: &ast::Expression) -> bool {
+        matches!(
+            Self::uncommented(expr),
+            ast::Expression::Prefix { .. } | ast::Expression::Infix { .. }
+        )
+    }
+
+    // comments don't change how an expression is parsed
+    fn uncommented(expr: &ast::Expression) -> &ast::Expression {
+        match expr {
+            ast::Expression::Commented(_, expr) => Self::uncommented(expr),
+            _ => expr,
+        }
+    }
+
+    fn needs_parens_as_left_operand(left: &ast::Expression, parent: &ast::Expression) -> bool {
+        match (Self::uncommented(left), parent) {
+            (
+                ast::Expression::Infix { operator: l, .. },
+                ast::Expression::Infix { operator: p, .. },
+            ) => Precedence::of_infix(*l) < Precedence::of_infix(*p),
+            _ => false,
+        }
+    }
+
+    fn needs_parens_as_right_operand(right: &ast::Expression, parent: &ast::Expression) -> bool {
+        // infix operators are left-associative
+        match (Self::uncommented(right), parent) {
+            (
+                ast::Expression::Infix { operator: r, .. },
+                ast::Expression::Infix { operator: p, .. },
+            ) => Precedence::of_infix(*r) <= Precedence::of_infix(*p),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::lexer::Lexer;
+    use crate::parser::parse;
+
+    #[test]
+    fn format_expressions() -> Result<(), Box<dyn std::error::Error>> {
+        // (input, expected)
+        let tests = vec![
+            ("1+2", "1 + 2;\n"),
+            ("(a + b) * c", "(a + b) * c;\n"),
+            ("a + (b * c)", "a + b * c;\n"),
+            ("a - (b - c)", "a - (b - c);\n"),
+            ("(a - b) - c", "a - b - c;\n"),
+            ("-(a + b)", "-(a + b);\n"),
+            ("-(-a)", "-(-a);\n"),
+            ("!(a == b)", "!(a == b);\n"),
+            ("(-a)[0]", "(-a)[0];\n"),
+            ("-(a[0])", "-a[0];\n"),
+            ("(a + b)[0]", "(a + b)[0];\n"),
+            ("(a ?? b) == c", "(a ?? b) == c;\n"),
+            ("a ?? (b == c)", "a ?? b == c;\n"),
+            ("x?.[ 1 ]?.( 2,3 )", "x?.[1]?.(2, 3);\n"),
+            (r#"{ "a" :1,"b":[ 1,2 ] }"#, "{\"a\": 1, \"b\": [1, 2]};\n"),
+            ("add( 1 , 2 * 3 )", "add(1, 2 * 3);\n"),
+            ("quote(unquote(a) + b)", "quote(unquote(a) + b);\n"),
+            (
+                "fn( a,b=(1+2),...c ) { a }",
+                "fn(a, b = 1 + 2, ...c) {\n    a\n};\n",
+            ),
+            (
+                "let [ a,...b ]=x;let { c , d:[e] } = y",
+                "let [a, ...b] = x;\nlet {c, d: [e]} = y;\n",
+            ),
+            (
+                r#"[ ...a,1 ];f( ...(a) );{ ...h,"k":1 }"#,
+                "[...a, 1];\nf(...a);\n{...h, \"k\": 1};\n",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(format(input)?, expected, "input={}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn format_statements() -> Result<(), Box<dyn std::error::Error>> {
+        // (input, expected)
+        let tests = vec![
+            (
+                "let x=1;let y = x;return x+y",
+                "let x = 1;\nlet y = x;\nreturn x + y;\n",
+            ),
+            (
+                "let add = fn(x,y){x+y};add(1,2)",
+                r#"let add = fn(x, y) {
+    x + y
+};
+
+add(1, 2);
+"#,
+            ),
+            (
+                "if(x>1){let y=x;y*2}else{0}",
+                r#"if (x > 1) {
+    let y = x;
+    y * 2
+} else {
+    0
+}
+"#,
+            ),
+            (
+                "let f = fn() { fn(x) { if (x) { 1 } } }",
+                r#"let f = fn() {
+    fn(x) {
+        if (x) {
+            1
+        }
+    }
+};
+"#,
+            ),
+            ("fn() {}()", "fn() {}();\n"),
+            ("const  x=1;x", "const x = 1;\nx;\n"),
+            (
+                r#"import "a.mk" as a;import {b,c} from "b.mk";export let d=a.f(b)+(1+c).x"#,
+                "import \"a.mk\" as a;\nimport {b, c} from \"b.mk\";\nexport let d = a.f(b) + (1 + c).x;\n",
+            ),
+            (
+                "try{f()}catch(e){e}finally{g()}",
+                r#"try {
+    f()
+} catch (e) {
+    e
+} finally {
+    g()
+}
+"#,
+            ),
+            (
+                "let unless = macro(c, x) { quote(if (!(unquote(c))) { unquote(x) }) };",
+                r#"let unless = macro(c, x) {
+    quote(if (!unquote(c)) {
+        unquote(x)
+    })
+};
+"#,
+            ),
+            ("if (x) { 1 }; -1", "if (x) {\n    1\n};\n\n-1;\n"),
+            ("if (x) { 1 }; [1][0]", "if (x) {\n    1\n};\n\n[1][0];\n"),
+            ("if (x) { 1 } y", "if (x) {\n    1\n}\n\ny;\n"),
+            (
+                "match(x){[a,_] if a>0=>a,{k:-1}=>fn(){1},_=>\"other\"}",
+                r#"match (x) {
+    [a, _] if a > 0 => a,
+    {k: -1} => fn() {
+        1
+    },
+    _ => "other"
+}
+"#,
+            ),
+            ("match (x) {}", "match (x) {}\n"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(format(input)?, expected, "input={}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn format_comments() -> Result<(), Box<dyn std::error::Error>> {
+        let tests = vec![
+            (
+                "// one\nlet a = 1; // two\n/* three */ a",
+                "// one\nlet a = 1; // two\n/* three */\na;\n",
+            ),
+            (
+                "let a = 1;\n// doc\nlet f = fn(x) { /* inner */ x // last\n};",
+                "let a = 1;\n\n// doc\nlet f = fn(x) {\n    /* inner */\n    x // last\n};\n",
+            ),
+            (
+                "if (a) { 1 }; // c\n-1",
+                "if (a) {\n    1\n}; // c\n\n-1;\n",
+            ),
+            ("1 // end", "1; // end\n"),
+        ];
+        for (input, expected) in tests {
+            let formatted = format(input)?;
+            assert_eq!(formatted, expected);
+            assert_eq!(format(formatted.clone())?, formatted);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn format_inner_comments() -> Result<(), Box<dyn std::error::Error>> {
+        // comments inside a statement are not moved out of it
+        let tests = vec![
+            (
+                "let a = [1, // one\n 2];",
+                "let a = [\n    1, // one\n    2\n];\n",
+            ),
+            ("add(1 /* x */, 2);", "add(1 /* x */, 2);\n"),
+            ("let s = 1 + /* two */ 2;", "let s = 1 + /* two */ 2;\n"),
+            (
+                "f({\"a\": 1, // a\n \"b\": /* b */ 2})",
+                "f({\n    \"a\": 1, // a\n    \"b\": /* b */ 2\n});\n",
+            ),
+            (
+                "match (x) {\n// first\n1 => a, // one\n_ => b}",
+                "match (x) {\n    // first\n    1 => a, // one\n    _ => b\n}\n",
+            ),
+        ];
+        for (input, expected) in tests {
+            let formatted = format(input)?;
+            assert_eq!(formatted, expected, "input={}", input);
+            assert_eq!(format(formatted.clone())?, formatted);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn format_is_idempotent_and_preserves_ast() -> Result<(), Box<dyn std::error::Error>> {
+        let inputs = vec![
+            r#"
+            let map = fn(arr, f) {
+                let iter = fn(arr, accumulated) {
+                    if (len(arr) == 0) { accumulated }
+                    else { iter(rest(arr), push(accumulated, f(first(arr)))); }
+                };
+                iter(arr, []);
+            };
+            map([1, 2, 3], fn(x) { x * 2 });
+            "#,
+            "a * [1, 2, 3, 4][b * c] * d; -a * b; !-a; a + b / c - d",
+            "(5 + 10 * 2 + 15 / 3) * 2 + -10; 2 * (5 + 10); 1 - (2 - (3 - 4))",
+            r#"let cfg = {"db": {"host": "x"}}; cfg["db"]?.["host"] ?? "localhost""#,
+            "if (a) { 1 }; -1; if (b) { 2 } else { 3 }; (a + b)[0]; (-a)?.(1)",
+            "try { throw(1) } catch (e) { e } -1",
+            "let m = macro(a, b = -1, ...c) { quote(unquote(a) + unquote(b)) }",
+            r#"match (f(x)) { 0 => "zero", [h, ...t] if h => t, {a, b: true} => a }; -1"#,
+        ];
+        for input in inputs {
+            let expected = parse(Lexer::new(input))?;
+            let formatted = format(input)?;
+            let reparsed = parse(Lexer::new(formatted.clone()))?;
+            assert_eq!(reparsed, expected, "formatted={}", formatted);
+            assert_eq!(format(formatted.clone())?, formatted);
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,52 @@
 use crate::token::Token;
+use std::fmt::{self};
 
 pub struct Lexer {
     input: Vec<char>,
     pos: usize,
+    emit_comments: bool,
+    // for positions
+    line: usize,
+    line_start: usize,
+    token_start: Position,
+}
+
+// zero-based, columns are counted in chars
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
 }
 
 impl Lexer {
     pub fn new(input: impl Into<String>) -> Self {
         let input = input.into().chars().collect::<Vec<char>>();
-        Self { input, pos: 0 }
+        Self {
+            input,
+            pos: 0,
+            emit_comments: false,
+            line: 0,
+            line_start: 0,
+            token_start: Position::default(),
+        }
+    }
+
+    // yields `(token, start position)`
+    pub fn positioned(self) -> PositionedLexer {
+        PositionedLexer(self)
+    }
+
+    // emits comments as `Token::Comment` instead of skipping them
+    pub fn new_with_comments(input: impl Into<String>) -> Self {
+        let mut lexer = Self::new(input);
+        lexer.emit_comments = true;
+        lexer
     }
 
     fn current_char(&self) -> Option<&char> {
@@ -21,17 +59,29 @@ impl Lexer {
 
     // `next` is used in an Iterator
     fn advance(&mut self) {
+        if self.current_char() == Some(&'\n') {
+            self.line += 1;
+            self.line_start = self.pos + 1;
+        }
         self.pos = std::cmp::min(self.pos + 1, self.input.len());
     }
 
     fn next_token(&mut self) -> Option<Token> {
         self.skip_whitespace();
+        self.token_start = Position {
+            line: self.line,
+            column: self.pos - self.line_start,
+        };
         let token = match self.current_char() {
             Some('=') => match self.peek_char() {
                 Some('=') => {
                     self.advance();
                     Token::Eq
                 }
+                Some('>') => {
+                    self.advance();
+                    Token::FatArrow
+                }
                 _ => Token::Assign,
             },
             Some(':') => Token::Colon,
@@ -48,7 +98,41 @@ impl Lexer {
                 }
                 _ => Token::Bang,
             },
-            Some('/') => Token::Slash,
+            Some('?') => match self.peek_char() {
+                Some('?') => {
+                    self.advance();
+                    Token::QuestionQuestion
+                }
+                Some('.') => {
+                    self.advance();
+                    Token::QuestionDot
+                }
+                _ => Token::Illegal("?".into()),
+            },
+            Some('/') => match self.peek_char() {
+                Some('/') | Some('*') => {
+                    let comment = match self.read_comment() {
+                        Ok(s) => Token::Comment(s),
+                        Err(s) => Token::Illegal(s),
+                    };
+                    if let Token::Comment(_) = comment {
+                        if !self.emit_comments {
+                            self.advance();
+                            return self.next_token();
+                        }
+                    }
+                    comment
+                }
+                _ => Token::Slash,
+            },
+            Some('.') => match (self.peek_char(), self.input.get(self.pos + 2)) {
+                (Some('.'), Some('.')) => {
+                    self.advance();
+                    self.advance();
+                    Token::Ellipsis
+                }
+                _ => Token::Dot,
+            },
             Some('*') => Token::Asterisk,
             Some('<') => Token::LT,
             Some('>') => Token::GT,
@@ -75,12 +159,17 @@ impl Lexer {
         Some(token)
     }
 
+    // a letter followed by letters and digits
     fn read_identifier(&mut self) -> String {
         let is_latter =
             |ch: Option<&char>| -> bool { ch.filter(|&ch| Self::is_letter(ch)).is_some() };
+        let is_latter_or_digit = |ch: Option<&char>| -> bool {
+            ch.filter(|&ch| Self::is_letter(ch) || Self::is_digit(ch))
+                .is_some()
+        };
         assert!(is_latter(self.current_char()));
         let l = self.pos;
-        while is_latter(self.peek_char()) {
+        while is_latter_or_digit(self.peek_char()) {
             self.advance();
         }
         let r = self.pos + 1;
@@ -93,14 +182,22 @@ impl Lexer {
         match s {
             "fn" => Token::Function,
             "let" => Token::Let,
+            "const" => Token::Const,
             "true" => Token::True,
             "false" => Token::False,
             "if" => Token::If,
             "else" => Token::Else,
             "return" => Token::Return,
+            "try" => Token::Try,
+            "catch" => Token::Catch,
+            "finally" => Token::Finally,
+            "match" => Token::Match,
+            "import" => Token::Import,
+            "export" => Token::Export,
             "quote" => Token::Quote,
             "unquote" => Token::Unquote,
             "macro" => Token::Macro,
+            _ if is_generated_identifier(s) => Token::Illegal(ident),
             _ => Token::Identifier(ident),
         }
     }
@@ -141,6 +238,40 @@ impl Lexer {
         Ok(s)
     }
 
+    fn read_comment(&mut self) -> Result<String, String> {
+        // `// ...` until the end of line, `/* ... */` can be nested
+        assert!(self.current_char() == Some(&'/'));
+        let l = self.pos;
+        if self.peek_char() == Some(&'/') {
+            while self.peek_char().filter(|&ch| ch != &'\n').is_some() {
+                self.advance();
+            }
+        } else {
+            self.advance();
+            let mut depth = 1;
+            while depth > 0 {
+                self.advance();
+                match (self.current_char(), self.peek_char()) {
+                    (Some('/'), Some('*')) => {
+                        self.advance();
+                        depth += 1;
+                    }
+                    (Some('*'), Some('/')) => {
+                        self.advance();
+                        depth -= 1;
+                    }
+                    (None, _) => {
+                        let s = self.input[l..].iter().collect::<String>();
+                        return Err(s);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let r = self.pos + 1;
+        Ok(self.input[l..r].iter().collect::<String>())
+    }
+
     fn skip_whitespace(&mut self) {
         while self
             .current_char()
@@ -168,9 +299,58 @@ impl Iterator for Lexer {
     }
 }
 
+// the diagnostic for an `Illegal` token
+// `<name>__<n>`, see `hygiene::gensym`
+fn is_generated_identifier(s: &str) -> bool {
+    match s.rfind("__") {
+        Some(i) => {
+            let n = &s[i + 2..];
+            !n.is_empty() && n.chars().all(|ch| ch.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+pub fn illegal_token_message(s: &str) -> String {
+    if s.starts_with('"') {
+        "unterminated string".into()
+    } else if s.starts_with("/*") {
+        "unterminated comment".into()
+    } else if is_generated_identifier(s) {
+        format!("`{}` is reserved for identifiers generated by macros", s)
+    } else {
+        format!("illegal character `{}`", s)
+    }
+}
+
+// `monkey tokens <file>`: the tokens with their positions, one per line, and
+// the diagnostics for the illegal ones
+pub fn dump_tokens(input: impl Into<String>) -> (String, Vec<String>) {
+    let mut lines = Vec::new();
+    let mut diagnostics = Vec::new();
+    for (token, position) in Lexer::new_with_comments(input).positioned() {
+        if let Token::Illegal(s) = &token {
+            diagnostics.push(format!("{}: {}", position, illegal_token_message(s)));
+        }
+        lines.push(format!("{:<8}{:?}", position.to_string(), token));
+    }
+    (lines.join("\n"), diagnostics)
+}
+
+pub struct PositionedLexer(Lexer);
+
+impl Iterator for PositionedLexer {
+    type Item = (Token, Position);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.0.next_token()?;
+        Some((token, self.0.token_start))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::lexer::Lexer;
+    use crate::lexer::{dump_tokens, Lexer, Position};
     use crate::token::Token;
 
     #[test]
@@ -224,7 +404,7 @@ mod tests {
         assert_eq!(iter.next(), None);
 
         let input = r#"
-        !-/*5;
+        !-/ *5;
         5 < 10 > 5;
         "#;
         let lexer = Lexer::new(input);
@@ -301,6 +481,21 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn ident_digits() {
+        let input = "x1 macroexpand1 a__b1 a__1 1x";
+        let lexer = Lexer::new(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Identifier("x1".into())));
+        assert_eq!(iter.next(), Some(Token::Identifier("macroexpand1".into())));
+        assert_eq!(iter.next(), Some(Token::Identifier("a__b1".into())));
+        // reserved for `gensym`
+        assert_eq!(iter.next(), Some(Token::Illegal("a__1".into())));
+        assert_eq!(iter.next(), Some(Token::Int("1".into())));
+        assert_eq!(iter.next(), Some(Token::Identifier("x".into())));
+        assert_eq!(iter.next(), None);
+    }
+
     #[test]
     fn int_eof() {
         let input = r#"
@@ -314,6 +509,44 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn ellipsis() {
+        let input = "fn(...rest) ..";
+        let lexer = Lexer::new(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Function));
+        assert_eq!(iter.next(), Some(Token::LParen));
+        assert_eq!(iter.next(), Some(Token::Ellipsis));
+        assert_eq!(iter.next(), Some(Token::Identifier("rest".into())));
+        assert_eq!(iter.next(), Some(Token::RParen));
+        assert_eq!(iter.next(), Some(Token::Dot));
+        assert_eq!(iter.next(), Some(Token::Dot));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn import_export() {
+        let input = r#"
+        import "lib.mk" as lib; export let x = lib.y;
+        "#;
+        let lexer = Lexer::new(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Import));
+        assert_eq!(iter.next(), Some(Token::String("lib.mk".into())));
+        assert_eq!(iter.next(), Some(Token::Identifier("as".into())));
+        assert_eq!(iter.next(), Some(Token::Identifier("lib".into())));
+        assert_eq!(iter.next(), Some(Token::Semicolon));
+        assert_eq!(iter.next(), Some(Token::Export));
+        assert_eq!(iter.next(), Some(Token::Let));
+        assert_eq!(iter.next(), Some(Token::Identifier("x".into())));
+        assert_eq!(iter.next(), Some(Token::Assign));
+        assert_eq!(iter.next(), Some(Token::Identifier("lib".into())));
+        assert_eq!(iter.next(), Some(Token::Dot));
+        assert_eq!(iter.next(), Some(Token::Identifier("y".into())));
+        assert_eq!(iter.next(), Some(Token::Semicolon));
+        assert_eq!(iter.next(), None);
+    }
+
     #[test]
     fn string() {
         let input = r#"
@@ -380,6 +613,156 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn try_catch_finally() {
+        let input = r#"
+        try { x } catch (e) { e } finally { y }
+        "#;
+        let lexer = Lexer::new(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Try));
+        assert_eq!(iter.next(), Some(Token::LBrace));
+        assert_eq!(iter.next(), Some(Token::Identifier("x".into())));
+        assert_eq!(iter.next(), Some(Token::RBrace));
+        assert_eq!(iter.next(), Some(Token::Catch));
+        assert_eq!(iter.next(), Some(Token::LParen));
+        assert_eq!(iter.next(), Some(Token::Identifier("e".into())));
+        assert_eq!(iter.next(), Some(Token::RParen));
+        assert_eq!(iter.next(), Some(Token::LBrace));
+        assert_eq!(iter.next(), Some(Token::Identifier("e".into())));
+        assert_eq!(iter.next(), Some(Token::RBrace));
+        assert_eq!(iter.next(), Some(Token::Finally));
+        assert_eq!(iter.next(), Some(Token::LBrace));
+        assert_eq!(iter.next(), Some(Token::Identifier("y".into())));
+        assert_eq!(iter.next(), Some(Token::RBrace));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn match_expression() {
+        let input = r#"
+        match (x) { [a, _] if a => a }
+        "#;
+        let lexer = Lexer::new(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Match));
+        assert_eq!(iter.next(), Some(Token::LParen));
+        assert_eq!(iter.next(), Some(Token::Identifier("x".into())));
+        assert_eq!(iter.next(), Some(Token::RParen));
+        assert_eq!(iter.next(), Some(Token::LBrace));
+        assert_eq!(iter.next(), Some(Token::LBracket));
+        assert_eq!(iter.next(), Some(Token::Identifier("a".into())));
+        assert_eq!(iter.next(), Some(Token::Comma));
+        assert_eq!(iter.next(), Some(Token::Identifier("_".into())));
+        assert_eq!(iter.next(), Some(Token::RBracket));
+        assert_eq!(iter.next(), Some(Token::If));
+        assert_eq!(iter.next(), Some(Token::Identifier("a".into())));
+        assert_eq!(iter.next(), Some(Token::FatArrow));
+        assert_eq!(iter.next(), Some(Token::Identifier("a".into())));
+        assert_eq!(iter.next(), Some(Token::RBrace));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn null_coalescing_optional_chaining() {
+        let input = r#"
+        a?.["b"]?.(c) ?? d ?
+        "#;
+        let lexer = Lexer::new(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Identifier("a".into())));
+        assert_eq!(iter.next(), Some(Token::QuestionDot));
+        assert_eq!(iter.next(), Some(Token::LBracket));
+        assert_eq!(iter.next(), Some(Token::String("b".into())));
+        assert_eq!(iter.next(), Some(Token::RBracket));
+        assert_eq!(iter.next(), Some(Token::QuestionDot));
+        assert_eq!(iter.next(), Some(Token::LParen));
+        assert_eq!(iter.next(), Some(Token::Identifier("c".into())));
+        assert_eq!(iter.next(), Some(Token::RParen));
+        assert_eq!(iter.next(), Some(Token::QuestionQuestion));
+        assert_eq!(iter.next(), Some(Token::Identifier("d".into())));
+        assert_eq!(iter.next(), Some(Token::Illegal("?".into())));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn comment() {
+        let input = r#"
+        // line comment
+        1 / 2; // trailing
+        /* block
+           comment */ 3
+        /* nested /* block */ comment */ 4 /**/
+        "#;
+        let lexer = Lexer::new(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Int("1".into())));
+        assert_eq!(iter.next(), Some(Token::Slash));
+        assert_eq!(iter.next(), Some(Token::Int("2".into())));
+        assert_eq!(iter.next(), Some(Token::Semicolon));
+        assert_eq!(iter.next(), Some(Token::Int("3".into())));
+        assert_eq!(iter.next(), Some(Token::Int("4".into())));
+        assert_eq!(iter.next(), None);
+
+        let lexer = Lexer::new_with_comments(input);
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Comment("// line comment".into())));
+        assert_eq!(iter.next(), Some(Token::Int("1".into())));
+        assert_eq!(iter.next(), Some(Token::Slash));
+        assert_eq!(iter.next(), Some(Token::Int("2".into())));
+        assert_eq!(iter.next(), Some(Token::Semicolon));
+        assert_eq!(iter.next(), Some(Token::Comment("// trailing".into())));
+        assert_eq!(
+            iter.next(),
+            Some(Token::Comment("/* block\n           comment */".into()))
+        );
+        assert_eq!(iter.next(), Some(Token::Int("3".into())));
+        assert_eq!(
+            iter.next(),
+            Some(Token::Comment("/* nested /* block */ comment */".into()))
+        );
+        assert_eq!(iter.next(), Some(Token::Int("4".into())));
+        assert_eq!(iter.next(), Some(Token::Comment("/**/".into())));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn comment_eof() {
+        let lexer = Lexer::new("1 // comment");
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Int("1".into())));
+        assert_eq!(iter.next(), None);
+
+        let lexer = Lexer::new("1 /* unterminated /* */");
+        let mut iter = lexer.into_iter();
+        assert_eq!(iter.next(), Some(Token::Int("1".into())));
+        assert_eq!(
+            iter.next(),
+            Some(Token::Illegal("/* unterminated /* */".into()))
+        );
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn positioned() {
+        let input = "let a = 1;\n  /* c\n */ \"x\" // d\n\nfn";
+        let lexer = Lexer::new(input);
+        let tokens = lexer.positioned().collect::<Vec<_>>();
+        let position = |line, column| Position { line, column };
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Let, position(0, 0)),
+                (Token::Identifier("a".into()), position(0, 4)),
+                (Token::Assign, position(0, 6)),
+                (Token::Int("1".into()), position(0, 8)),
+                (Token::Semicolon, position(0, 9)),
+                (Token::String("x".into()), position(2, 4)),
+                (Token::Function, position(4, 0)),
+            ]
+        );
+    }
+
     #[test]
     fn define_macro() {
         let input = r#"
@@ -402,4 +785,24 @@ mod tests {
         assert_eq!(iter.next(), Some(Token::Semicolon));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn tokens_with_positions() {
+        let input = "let a = 1 & 2; // c\n\"b";
+        let (dump, diagnostics) = dump_tokens(input);
+        let expected = r#"1:1     Let
+1:5     Identifier("a")
+1:7     Assign
+1:9     Int("1")
+1:11    Illegal("&")
+1:13    Int("2")
+1:14    Semicolon
+1:16    Comment("// c")
+2:1     Illegal("\"b")"#;
+        assert_eq!(dump, expected);
+        assert_eq!(
+            diagnostics,
+            vec!["1:11: illegal character `&`", "2:1: unterminated string"]
+        );
+    }
 }
@@ -2,8 +2,16 @@
 
 mod ast;
 mod evaluator;
+pub mod formatter;
 mod lexer;
+pub mod linter;
+pub mod lsp;
 mod object;
 mod parser;
 pub mod repl;
+mod scope;
 mod token;
+
+pub use ast::serialize;
+pub use evaluator::{run_file, Capabilities, Context, ContextGuard};
+pub use lexer::dump_tokens;
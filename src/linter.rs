@@ -0,0 +1,708 @@
+use crate::ast::{self};
+use crate::evaluator::{builtins, prelude};
+use crate::lexer::{Lexer, Position};
+use crate::parser;
+use crate::scope::{self, DefinitionKind, Scopes};
+use crate::token::Token;
+use std::fmt::{self};
+
+// source -> diagnostics (including parse errors)
+pub fn lint(input: impl Into<String>) -> Vec<Diagnostic> {
+    let input = input.into();
+    let (program, errors, skipped) = parser::parse_with_skipped(Lexer::new(input.as_str()));
+    let mut diagnostics = errors
+        .into_iter()
+        .map(|(e, position)| Diagnostic::new(Rule::ParseError, e, position))
+        .collect::<Vec<_>>();
+    let is_skipped = |p: &Position| {
+        skipped
+            .iter()
+            .any(|(start, end)| start <= p && end.iter().all(|end| p < end))
+    };
+    let tokens = Lexer::new(input)
+        .positioned()
+        .filter(|(t, p)| !matches!(t, Token::Illegal(_)) && !is_skipped(p))
+        .collect();
+    diagnostics.extend(lint_program(&program, tokens));
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+// `tokens` are those of the program, used to locate the diagnostics
+pub(crate) fn lint_program(
+    program: &ast::Program,
+    tokens: Vec<(Token, Position)>,
+) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        scopes: scope::resolve_tokens(&tokens),
+        diagnostics: Vec::new(),
+        tokens,
+        cursor: 0,
+    };
+    linter.statements(&program.statements);
+    linter.check_names();
+    linter.diagnostics
+}
+
+// Diagnostic
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub message: String,
+    // one-based
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Diagnostic {
+    fn new(rule: Rule, message: impl Into<String>, position: Position) -> Self {
+        Self {
+            rule,
+            message: message.into(),
+            line: position.line + 1,
+            column: position.column + 1,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, self.rule, self.message
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Rule {
+    ParseError,
+    UnusedVariable,
+    ShadowedVariable,
+    UndefinedIdentifier,
+    BuiltinArity,
+    UnreachableCode,
+    ConstantCondition,
+    MacroWithoutQuote,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Rule::ParseError => "parse-error",
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ShadowedVariable => "shadowed-variable",
+            Rule::UndefinedIdentifier => "undefined-identifier",
+            Rule::BuiltinArity => "builtin-arity",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::MacroWithoutQuote => "macro-without-quote",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Linter
+
+struct Linter {
+    // the definitions and references of the identifiers
+    scopes: Scopes,
+    diagnostics: Vec<Diagnostic>,
+    // the AST has no positions, the tokens are searched instead: the AST is
+    // visited in source order and `cursor` follows the last located token
+    tokens: Vec<(Token, Position)>,
+    cursor: usize,
+}
+
+impl Linter {
+    fn statements(&mut self, stmts: &[ast::Statement]) {
+        // where the `return` is
+        let mut returned = None;
+        for stmt in stmts {
+            match (stmt, returned) {
+                (ast::Statement::Comment(_), _) => continue,
+                (_, Some(position)) => {
+                    let message = "unreachable code after `return`";
+                    self.report(Rule::UnreachableCode, message, position);
+                    break;
+                }
+                (ast::Statement::Return(_), None) => {
+                    returned = Some(self.locate_token(&Token::Return))
+                }
+                _ => {}
+            }
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &ast::Statement) {
+        match stmt {
+            ast::Statement::Let {
+                identifier,
+                expression,
+            }
+            | ast::Statement::Const {
+                identifier,
+                expression,
+            } => {
+                let position = self.locate_identifier(identifier);
+                if let ast::Expression::Macro(m) = expression {
+                    self.check_macro_returns_quote(identifier, m, position);
+                }
+                self.expression(expression);
+            }
+            ast::Statement::Destructure {
+                pattern,
+                expression,
+            } => {
+                self.locate_identifiers(pattern.identifiers());
+                self.expression(expression);
+            }
+            ast::Statement::Import { binding, .. } => {
+                let ids = match binding {
+                    ast::ImportBinding::Module(id) => vec![id],
+                    ast::ImportBinding::Names(names) => names.iter().collect(),
+                };
+                self.locate_identifiers(ids);
+            }
+            ast::Statement::Export(inner) => self.statement(inner),
+            ast::Statement::Return(expr) => self.expression(expr),
+            ast::Statement::Expression(expr) => self.expression(expr),
+            ast::Statement::Block(block) => self.block(block, &[]),
+            ast::Statement::Commented(_, stmt) => self.statement(stmt),
+            ast::Statement::Comment(_) => {}
+        }
+    }
+
+    fn block(&mut self, block: &ast::BlockStatement, params: &[ast::Parameter]) {
+        for p in params {
+            self.locate_identifiers(p.identifiers());
+            if let ast::Parameter::Default(_, default) = p {
+                self.expression(default);
+            }
+        }
+        self.statements(&block.statements);
+    }
+
+    fn expression(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Identifier(id) => {
+                self.locate_identifier(id);
+            }
+            ast::Expression::Integer(_)
+            | ast::Expression::Boolean(_)
+            | ast::Expression::String(_) => {}
+            ast::Expression::Array(elements) => self.expressions(elements),
+            ast::Expression::Hash(entries) => {
+                for entry in entries {
+                    match entry {
+                        ast::HashEntry::Pair(k, v) => {
+                            self.expression(k);
+                            self.expression(v);
+                        }
+                        ast::HashEntry::Spread(h) => self.expression(h),
+                    }
+                }
+            }
+            ast::Expression::Spread(expr) => self.expression(expr),
+            ast::Expression::Prefix { right, .. } => self.expression(right),
+            ast::Expression::Infix { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ast::Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let position = self.locate_token(&Token::If);
+                if Self::is_constant(condition) {
+                    let message = format!("`if` condition `{}` is constant", condition);
+                    self.report(Rule::ConstantCondition, message, position);
+                }
+                self.expression(condition);
+                self.block(consequence, &[]);
+                if let Some(alt) = alternative {
+                    self.block(alt, &[]);
+                }
+            }
+            ast::Expression::Try {
+                block,
+                catch,
+                finally,
+            } => {
+                self.block(block, &[]);
+                if let Some(c) = catch {
+                    self.block(&c.body, &[c.identifier.clone().into()]);
+                }
+                if let Some(fin) = finally {
+                    self.block(fin, &[]);
+                }
+            }
+            ast::Expression::Match { value, arms } => {
+                self.expression(value);
+                for arm in arms {
+                    self.locate_identifiers(arm.pattern.identifiers());
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    self.expression(&arm.body);
+                }
+            }
+            ast::Expression::Function(f) => self.block(&f.body, &f.params),
+            ast::Expression::Call { function, args } => {
+                match function {
+                    ast::CallExpressionFunction::Identifier(id) => {
+                        let position = self.locate_identifier(id);
+                        // the number of spread arguments is unknown
+                        if !args.iter().any(|a| matches!(a, ast::Expression::Spread(_))) {
+                            self.check_builtin_arity(id, args.len(), position);
+                        }
+                    }
+                    ast::CallExpressionFunction::Function(f) => self.block(&f.body, &f.params),
+                    ast::CallExpressionFunction::Expression(f) => self.expression(f),
+                }
+                self.expressions(args);
+            }
+            ast::Expression::Index { left, index }
+            | ast::Expression::OptionalIndex { left, index } => {
+                self.expression(left);
+                self.expression(index);
+            }
+            ast::Expression::Member { left, .. } => self.expression(left),
+            ast::Expression::OptionalCall { function, args } => {
+                self.expression(function);
+                self.expressions(args);
+            }
+            // identifiers in quoted code belong to the call site
+            ast::Expression::Quote(quoted) => {
+                let end = self.locate_quote_end();
+                self.unquoted(quoted);
+                self.cursor = self.cursor.max(end);
+            }
+            ast::Expression::Unquote(expr) | ast::Expression::Commented(_, expr) => {
+                self.expression(expr)
+            }
+            ast::Expression::Macro(m) => self.block(&m.body, &m.params),
+        }
+    }
+
+    fn expressions(&mut self, exprs: &[ast::Expression]) {
+        for e in exprs {
+            self.expression(e);
+        }
+    }
+
+    fn unquoted(&mut self, quoted: &ast::Expression) {
+        // visits only `unquote(...)` in quoted code
+        let _ = ast::modify(quoted.clone().into(), |node| {
+            if let ast::Node::Expression(ast::Expression::Unquote(expr)) = &node {
+                self.expression(expr);
+            }
+            node
+        });
+    }
+
+    // positions
+
+    // the next identifier token named `id`, except member names (`a.id`)
+    fn locate_identifier(&mut self, id: &ast::Identifier) -> Position {
+        self.locate(|tokens, i| match &tokens[i].0 {
+            Token::Identifier(name) if name == &id.0 => {
+                i == 0 || !matches!(tokens[i - 1].0, Token::Dot | Token::QuestionDot)
+            }
+            _ => false,
+        })
+    }
+
+    fn locate_identifiers<'a>(
+        &mut self,
+        ids: Vec<&'a ast::Identifier>,
+    ) -> Vec<(&'a ast::Identifier, Position)> {
+        ids.into_iter()
+            .map(|id| (id, self.locate_identifier(id)))
+            .collect()
+    }
+
+    fn locate_token(&mut self, token: &Token) -> Position {
+        self.locate(|tokens, i| &tokens[i].0 == token)
+    }
+
+    // the index after the `)` closing the next `quote(`, code in between is
+    // only visited inside `unquote(...)`
+    fn locate_quote_end(&mut self) -> usize {
+        self.locate_token(&Token::Quote);
+        let mut depth = 0;
+        for (i, (token, _)) in self.tokens.iter().enumerate().skip(self.cursor) {
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 1 => return i + 1,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+        }
+        self.tokens.len()
+    }
+
+    // moves the cursor after the first token from the cursor on satisfying `f`,
+    // the cursor stays if there is none
+    fn locate(&mut self, f: impl Fn(&[(Token, Position)], usize) -> bool) -> Position {
+        match (self.cursor..self.tokens.len()).find(|&i| f(&self.tokens, i)) {
+            Some(i) => {
+                self.cursor = i + 1;
+                self.tokens[i].1
+            }
+            None => self
+                .tokens
+                .get(self.cursor.saturating_sub(1))
+                .map(|(_, p)| *p)
+                .unwrap_or_default(),
+        }
+    }
+
+    // checks
+
+    // unused, shadowed and undefined identifiers
+    fn check_names(&mut self) {
+        let mut diagnostics = Vec::new();
+        for (i, d) in self.scopes.definitions.iter().enumerate() {
+            let name = &d.name;
+            if d.shadows.is_some() {
+                let message = format!("`{}` shadows an outer binding", name);
+                diagnostics.push((Rule::ShadowedVariable, message, d.position));
+            } else if d.redefines.is_none() && builtins::is_builtin(name) {
+                let message = format!("`{}` shadows a builtin function", name);
+                diagnostics.push((Rule::ShadowedVariable, message, d.position));
+            }
+            let used = self
+                .scopes
+                .references
+                .iter()
+                .any(|r| r.definition == Some(i));
+            // parameters are not reported as unused
+            if !used && !d.exported && d.kind != DefinitionKind::Param && !name.starts_with('_') {
+                let message = format!("`{}` is never used", name);
+                diagnostics.push((Rule::UnusedVariable, message, d.position));
+            }
+        }
+        for r in &self.scopes.references {
+            let name = &r.name;
+            // `gensym` is defined while macros are expanded
+            let defined = r.definition.is_some()
+                || builtins::is_builtin(name)
+                || prelude::is_defined(name)
+                || (r.in_macro && name == "gensym");
+            if !defined {
+                let message = format!("undefined identifier `{}`", name);
+                diagnostics.push((Rule::UndefinedIdentifier, message, r.position));
+            }
+        }
+        for (rule, message, position) in diagnostics {
+            self.report(rule, message, position);
+        }
+    }
+
+    fn check_builtin_arity(&mut self, id: &ast::Identifier, n: usize, position: Position) {
+        // shadowed by a local definition
+        let defined = self
+            .scopes
+            .references
+            .iter()
+            .any(|r| r.position == position && r.definition.is_some());
+        if defined {
+            return;
+        }
+        if let Some(expected) = builtins::arity(&id.0) {
+            if n != expected {
+                let message = format!(
+                    "`{}` takes {} argument(s) but {} were given",
+                    id, expected, n
+                );
+                self.report(Rule::BuiltinArity, message, position);
+            }
+        }
+    }
+
+    fn check_macro_returns_quote(
+        &mut self,
+        id: &ast::Identifier,
+        m: &ast::MacroExpression,
+        position: Position,
+    ) {
+        if !Self::block_may_return_quote(&m.body) {
+            let message = format!("macro `{}` never returns `quote(...)`", id);
+            self.report(Rule::MacroWithoutQuote, message, position);
+        }
+    }
+
+    fn block_may_return_quote(block: &ast::BlockStatement) -> bool {
+        let stmts = block
+            .statements
+            .iter()
+            .filter(|s| !matches!(s, ast::Statement::Comment(_)))
+            .collect::<Vec<_>>();
+        let returns = stmts.iter().any(|s| match s {
+            ast::Statement::Return(expr) => Self::may_be_quote(expr),
+            _ => false,
+        });
+        let last = match stmts.last() {
+            Some(ast::Statement::Expression(expr)) => Self::may_be_quote(expr),
+            Some(ast::Statement::Block(block)) => Self::block_may_return_quote(block),
+            _ => false,
+        };
+        returns || last
+    }
+
+    fn may_be_quote(expr: &ast::Expression) -> bool {
+        // false only if the value can never be a quote
+        match expr {
+            ast::Expression::Integer(_)
+            | ast::Expression::Boolean(_)
+            | ast::Expression::String(_)
+            | ast::Expression::Array(_)
+            | ast::Expression::Hash(_)
+            | ast::Expression::Prefix { .. }
+            | ast::Expression::Function(_)
+            | ast::Expression::Macro(_) => false,
+            ast::Expression::Infix { operator, .. } => {
+                *operator == ast::InfixOperator::NullCoalescing
+            }
+            ast::Expression::If {
+                consequence,
+                alternative,
+                ..
+            } => {
+                Self::block_may_return_quote(consequence)
+                    || alternative.iter().any(Self::block_may_return_quote)
+            }
+            ast::Expression::Try { block, catch, .. } => {
+                Self::block_may_return_quote(block)
+                    || catch.iter().any(|c| Self::block_may_return_quote(&c.body))
+            }
+            _ => true,
+        }
+    }
+
+    fn is_constant(expr: &ast::Expression) -> bool {
+        match expr {
+            ast::Expression::Integer(_)
+            | ast::Expression::Boolean(_)
+            | ast::Expression::String(_)
+            | ast::Expression::Array(_)
+            | ast::Expression::Hash(_)
+            | ast::Expression::Function(_) => true,
+            ast::Expression::Prefix { right, .. } => Self::is_constant(right),
+            ast::Expression::Infix { left, right, .. } => {
+                Self::is_constant(left) && Self::is_constant(right)
+            }
+            _ => false,
+        }
+    }
+
+    fn report(&mut self, rule: Rule, message: impl Into<String>, position: Position) {
+        self.diagnostics
+            .push(Diagnostic::new(rule, message, position));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, Diagnostic, Rule};
+
+    #[test]
+    fn lint_rules() {
+        // input, expected (rule, (line, column), message)
+        let tests = vec![
+            ("let a = 1; a;", vec![]),
+            (
+                "let a = 1; let _b = 2;",
+                vec![(Rule::UnusedVariable, (1, 5), "`a` is never used")],
+            ),
+            (
+                "let f = fn(x) { let y = x; }; f(1);",
+                vec![(Rule::UnusedVariable, (1, 21), "`y` is never used")],
+            ),
+            ("let f = fn(n) { f(n - 1) }; f(1);", vec![]),
+            ("let x = 1\nlet x = x + 1\nx", vec![]),
+            (
+                "let [a, {b}] = [1, c]; a;",
+                vec![
+                    (Rule::UnusedVariable, (1, 10), "`b` is never used"),
+                    (
+                        Rule::UndefinedIdentifier,
+                        (1, 20),
+                        "undefined identifier `c`",
+                    ),
+                ],
+            ),
+            ("let f = fn([x], {y}) { 1 }; f([1], {});", vec![]),
+            (
+                "let a = 1; let f = fn(a) { a }; f(a);",
+                vec![(
+                    Rule::ShadowedVariable,
+                    (1, 23),
+                    "`a` shadows an outer binding",
+                )],
+            ),
+            (
+                "let len = fn(x) { 0 }; len(1, 2);",
+                vec![(
+                    Rule::ShadowedVariable,
+                    (1, 5),
+                    "`len` shadows a builtin function",
+                )],
+            ),
+            (
+                "b; let a = fn() { c }; a();",
+                vec![
+                    (
+                        Rule::UndefinedIdentifier,
+                        (1, 1),
+                        "undefined identifier `b`",
+                    ),
+                    (
+                        Rule::UndefinedIdentifier,
+                        (1, 19),
+                        "undefined identifier `c`",
+                    ),
+                ],
+            ),
+            ("try { 1 } catch (e) { e }", vec![]),
+            // the prelude
+            ("map([1], fn(x) { x }); unless(true, 1, 2);", vec![]),
+            (
+                r#"import "a.mk" as a; import {b, c} from "b.mk"; export let d = a.x(b);"#,
+                vec![(Rule::UnusedVariable, (1, 32), "`c` is never used")],
+            ),
+            (
+                "match ([1]) { [a, b] if a => c, [a] => a, _ => 0 }",
+                vec![
+                    (Rule::UnusedVariable, (1, 19), "`b` is never used"),
+                    (
+                        Rule::UndefinedIdentifier,
+                        (1, 30),
+                        "undefined identifier `c`",
+                    ),
+                ],
+            ),
+            (
+                "len([1], [2]); push([]); puts(1, 2, 3); push(...[[], 1]);",
+                vec![
+                    (
+                        Rule::BuiltinArity,
+                        (1, 1),
+                        "`len` takes 1 argument(s) but 2 were given",
+                    ),
+                    (
+                        Rule::BuiltinArity,
+                        (1, 16),
+                        "`push` takes 2 argument(s) but 1 were given",
+                    ),
+                ],
+            ),
+            (
+                "let f = fn() { return 1; 2; 3 }; f();",
+                vec![(
+                    Rule::UnreachableCode,
+                    (1, 16),
+                    "unreachable code after `return`",
+                )],
+            ),
+            (
+                "let a = 1; if (true) { a }; if (1 < 2) { a }; if (a) { a }",
+                vec![
+                    (
+                        Rule::ConstantCondition,
+                        (1, 12),
+                        "`if` condition `true` is constant",
+                    ),
+                    (
+                        Rule::ConstantCondition,
+                        (1, 29),
+                        "`if` condition `(1 < 2)` is constant",
+                    ),
+                ],
+            ),
+            (
+                r#"
+                let unless = macro(cond, a, b) {
+                    quote(if (!(unquote(cond))) { unquote(a) } else { unquote(b) })
+                };
+                let m = macro(x) { if (true) { 1 } else { "x" } };
+                unless(false, 1, 2); m(1);
+                "#,
+                vec![
+                    (
+                        Rule::MacroWithoutQuote,
+                        (5, 21),
+                        "macro `m` never returns `quote(...)`",
+                    ),
+                    (
+                        Rule::ConstantCondition,
+                        (5, 36),
+                        "`if` condition `true` is constant",
+                    ),
+                ],
+            ),
+            (
+                r#"let m = macro(e) { let t = gensym("t"); quote(unquote(t) + unquote(e)) }; m(1);"#,
+                vec![],
+            ),
+            // default values are visited like those of functions
+            (
+                "let m = macro(e = [q, 1]) { quote(unquote(e)) }; let q = 2; m(q);",
+                vec![(
+                    Rule::UndefinedIdentifier,
+                    (1, 20),
+                    "undefined identifier `q`",
+                )],
+            ),
+            (
+                "gensym();",
+                vec![(
+                    Rule::UndefinedIdentifier,
+                    (1, 1),
+                    "undefined identifier `gensym`",
+                )],
+            ),
+            // member names and quoted code are not the identifiers reported
+            (
+                r#"import "a.mk" as a; a.b; quote(b + unquote(a)); b"#,
+                vec![(
+                    Rule::UndefinedIdentifier,
+                    (1, 49),
+                    "undefined identifier `b`",
+                )],
+            ),
+            (
+                "let a = ; a",
+                vec![
+                    (
+                        Rule::ParseError,
+                        (1, 9),
+                        "could not parse Semicolon as prefix expression",
+                    ),
+                    (
+                        Rule::UndefinedIdentifier,
+                        (1, 11),
+                        "undefined identifier `a`",
+                    ),
+                ],
+            ),
+        ];
+        for (input, expected) in tests {
+            let expected = expected
+                .into_iter()
+                .map(|(rule, (line, column), message)| Diagnostic {
+                    rule,
+                    message: message.into(),
+                    line,
+                    column,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(lint(input), expected, "input={}", input);
+        }
+    }
+}
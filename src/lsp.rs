@@ -0,0 +1,562 @@
+use crate::evaluator::{builtins, prelude};
+use crate::formatter;
+use crate::lexer::Position;
+use crate::linter::{self, Rule};
+use crate::scope;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+// Language Server Protocol over stdio
+
+pub fn run(input: impl BufRead, output: impl Write) -> io::Result<()> {
+    let mut server = Server {
+        input,
+        output,
+        documents: HashMap::new(),
+    };
+    server.run()
+}
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// CompletionItemKind
+const FUNCTION_KIND: i64 = 3;
+const VARIABLE_KIND: i64 = 6;
+const CONSTANT_KIND: i64 = 21;
+
+struct Server<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    // uri -> text
+    documents: HashMap<String, String>,
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(body) = self.read_message()? {
+            let message = match serde_json::from_slice::<Value>(&body) {
+                Ok(it) => it,
+                Err(e) => {
+                    self.send_error(Value::Null, PARSE_ERROR, e.to_string())?;
+                    continue;
+                }
+            };
+            let method = match message["method"].as_str() {
+                Some(it) => it,
+                // responses from the client
+                None => continue,
+            };
+            if method == "exit" {
+                break;
+            }
+            let params = &message["params"];
+            let result = match method {
+                "initialize" => Some(Self::initialize()),
+                "shutdown" => Some(Value::Null),
+                "textDocument/didOpen" => {
+                    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                    let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                    self.update(uri, text)?;
+                    None
+                }
+                "textDocument/didChange" => {
+                    // full document sync, the last change wins
+                    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                    let changes = params["contentChanges"].as_array();
+                    if let Some(text) = changes.and_then(|c| c.last()).map(|c| &c["text"]) {
+                        self.update(uri, text.as_str().unwrap_or_default())?;
+                    }
+                    None
+                }
+                "textDocument/didClose" => {
+                    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                    self.documents.remove(uri);
+                    self.publish_diagnostics(uri, Vec::new())?;
+                    None
+                }
+                "textDocument/definition" => Some(self.definition(params)),
+                "textDocument/hover" => Some(self.hover(params)),
+                "textDocument/completion" => Some(self.completion(params)),
+                "textDocument/formatting" => Some(self.formatting(params)),
+                _ => {
+                    if let Some(id) = message.get("id") {
+                        let e = format!("method not found: {}", method);
+                        self.send_error(id.clone(), METHOD_NOT_FOUND, e)?;
+                    }
+                    None
+                }
+            };
+            if let (Some(result), Some(id)) = (result, message.get("id")) {
+                self.send(json!({"jsonrpc": "2.0", "id": id, "result": result}))?;
+            }
+        }
+        Ok(())
+    }
+
+    // requests
+
+    fn initialize() -> Value {
+        json!({
+            "capabilities": {
+                // full document sync
+                "textDocumentSync": 1,
+                "definitionProvider": true,
+                "hoverProvider": true,
+                "completionProvider": {},
+                "documentFormattingProvider": true,
+            },
+            "serverInfo": {"name": "monkey"},
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let (uri, text, position) = match self.document_position(params) {
+            Some(it) => it,
+            None => return Value::Null,
+        };
+        match scope::resolve(text).lookup(position) {
+            Some((_, Some(d))) => json!({
+                "uri": uri,
+                "range": range(text, d.position, &d.name),
+            }),
+            _ => Value::Null,
+        }
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (_, text, position) = match self.document_position(params) {
+            Some(it) => it,
+            None => return Value::Null,
+        };
+        let signature = match scope::resolve(text).lookup(position) {
+            Some((_, Some(d))) => d.signature(),
+            Some((name, None)) if builtins::is_builtin(name) => builtin_signature(name),
+            _ => return Value::Null,
+        };
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```monkey\n{}\n```", signature),
+            },
+        })
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let (_, text, position) = match self.document_position(params) {
+            Some(it) => it,
+            None => return Value::Null,
+        };
+        let scopes = scope::resolve(text);
+        let mut items = scopes
+            .visible_at(position)
+            .into_iter()
+            .map(|d| {
+                let kind = match d.kind {
+                    scope::DefinitionKind::Function(_) => FUNCTION_KIND,
+                    scope::DefinitionKind::Const => CONSTANT_KIND,
+                    _ => VARIABLE_KIND,
+                };
+                json!({"label": d.name, "kind": kind, "detail": d.signature()})
+            })
+            .collect::<Vec<_>>();
+        items.extend(builtins::names().into_iter().map(
+            |name| json!({"label": name, "kind": FUNCTION_KIND, "detail": builtin_signature(name)}),
+        ));
+        items.push(json!({"label": "gensym", "kind": FUNCTION_KIND, "detail": GENSYM_SIGNATURE}));
+        items.extend(prelude::signatures().into_iter().map(
+            |(name, signature)| json!({"label": name, "kind": FUNCTION_KIND, "detail": signature}),
+        ));
+        Value::Array(items)
+    }
+
+    fn formatting(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let text = match self.documents.get(uri) {
+            Some(it) => it,
+            None => return Value::Null,
+        };
+        // nothing to do for documents with syntax errors
+        let formatted = match formatter::format(text.as_str()) {
+            Ok(it) => it,
+            Err(_) => return Value::Null,
+        };
+        if &formatted == text {
+            return json!([]);
+        }
+        // the end of the document
+        let last_line = text.rsplit('\n').next().unwrap_or_default();
+        let end = Position {
+            line: text.matches('\n').count(),
+            column: last_line.chars().count(),
+        };
+        json!([{
+            "range": {"start": position_json(text, Position::default()), "end": position_json(text, end)},
+            "newText": formatted,
+        }])
+    }
+
+    fn document_position<'a>(&'a self, params: &'a Value) -> Option<(&'a str, &'a str, Position)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let text = self.documents.get(uri)?;
+        let position = Position {
+            line: params["position"]["line"].as_u64()? as usize,
+            column: params["position"]["character"].as_u64()? as usize,
+        };
+        Some((uri, text, from_utf16(text, position)))
+    }
+
+    // notifications
+
+    fn update(&mut self, uri: &str, text: &str) -> io::Result<()> {
+        let diagnostics = linter::lint(text)
+            .into_iter()
+            .map(|d| {
+                let position = Position {
+                    line: d.line - 1,
+                    column: d.column - 1,
+                };
+                // Error or Warning
+                let severity = if d.rule == Rule::ParseError { 1 } else { 2 };
+                json!({
+                    "range": {"start": position_json(text, position), "end": position_json(text, position)},
+                    "severity": severity,
+                    "code": d.rule.to_string(),
+                    "source": "monkey",
+                    "message": d.message,
+                })
+            })
+            .collect();
+        self.documents.insert(uri.into(), text.into());
+        self.publish_diagnostics(uri, diagnostics)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str, diagnostics: Vec<Value>) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        }))
+    }
+
+    // transport
+
+    fn read_message(&mut self) -> io::Result<Option<Vec<u8>>> {
+        // Content-Length: <n>\r\n
+        // \r\n
+        // <body>
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                if length.is_some() {
+                    break;
+                }
+                continue;
+            }
+            if let Some(n) = line.strip_prefix("Content-Length:") {
+                let n = n
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                length = Some(n);
+            }
+        }
+        let mut body = vec![0; length.unwrap_or_default()];
+        self.input.read_exact(&mut body)?;
+        Ok(Some(body))
+    }
+
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    fn send_error(&mut self, id: Value, code: i64, message: String) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": code, "message": message},
+        }))
+    }
+}
+
+// helpers
+
+// only defined while a macro body is evaluated
+const GENSYM_SIGNATURE: &str = "builtin gensym(name?), in macro bodies";
+
+fn builtin_signature(name: &str) -> String {
+    let params = match builtins::arity(name) {
+        Some(n) => vec!["_"; n].join(", "),
+        None => "...".into(),
+    };
+    format!("builtin {}({})", name, params)
+}
+
+// clients count columns in UTF-16 code units, the lexer in chars
+
+fn position_json(text: &str, p: Position) -> Value {
+    let line = text.split('\n').nth(p.line).unwrap_or_default();
+    let character = line
+        .chars()
+        .take(p.column)
+        .map(char::len_utf16)
+        .sum::<usize>();
+    json!({"line": p.line, "character": character})
+}
+
+fn from_utf16(text: &str, p: Position) -> Position {
+    let line = text.split('\n').nth(p.line).unwrap_or_default();
+    let mut units = 0;
+    let column = line
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= p.column
+        })
+        .count();
+    Position {
+        line: p.line,
+        column,
+    }
+}
+
+fn range(text: &str, start: Position, name: &str) -> Value {
+    let end = Position {
+        line: start.line,
+        column: start.column + name.chars().count(),
+    };
+    json!({"start": position_json(text, start), "end": position_json(text, end)})
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::run;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn lsp_session() {
+        let uri = "file:///a.mk";
+        let text = "let add = fn(a, b) { a + b };\nadd(1, len(\"x\"));\n";
+        let messages = vec![
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": uri, "languageId": "monkey", "version": 1, "text": text},
+            }}),
+            request(2, "textDocument/definition", uri, 1, 1),
+            request(3, "textDocument/hover", uri, 1, 1),
+            request(4, "textDocument/hover", uri, 1, 8),
+            request(5, "textDocument/completion", uri, 0, 21),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "textDocument/formatting", "params": {
+                "textDocument": {"uri": uri},
+                "options": {"tabSize": 4, "insertSpaces": true},
+            }}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+                "textDocument": {"uri": uri, "version": 2},
+                "contentChanges": [{"text": "let a = 1;\nlet b = ;"}],
+            }}),
+            json!({"jsonrpc": "2.0", "id": 7, "method": "unknown", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 8, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+            // ignored after exit
+            json!({"jsonrpc": "2.0", "id": 9, "method": "shutdown"}),
+        ];
+        let responses = session(messages);
+        assert_eq!(responses.len(), 10);
+
+        // initialize
+        let capabilities = &responses[0]["result"]["capabilities"];
+        assert_eq!(capabilities["definitionProvider"], json!(true));
+        assert_eq!(capabilities["documentFormattingProvider"], json!(true));
+
+        // didOpen
+        assert_eq!(
+            responses[1],
+            json!({"jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": {
+                "uri": uri, "diagnostics": [],
+            }})
+        );
+
+        // definition
+        assert_eq!(
+            responses[2]["result"],
+            json!({"uri": uri, "range": {
+                "start": {"line": 0, "character": 4},
+                "end": {"line": 0, "character": 7},
+            }})
+        );
+
+        // hover
+        assert_eq!(
+            responses[3]["result"]["contents"]["value"],
+            json!("```monkey\nlet add = fn(a, b)\n```")
+        );
+        assert_eq!(
+            responses[4]["result"]["contents"]["value"],
+            json!("```monkey\nbuiltin len(_)\n```")
+        );
+
+        // completion
+        let labels = responses[5]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            vec![
+                "a",
+                "add",
+                "b",
+                "append_file",
+                "eprint",
+                "file_exists",
+                "first",
+                "json_parse",
+                "json_stringify",
+                "keys",
+                "last",
+                "len",
+                "list_dir",
+                "macroexpand",
+                "macroexpand1",
+                "push",
+                "puts",
+                "read_file",
+                "read_line",
+                "read_lines",
+                "read_stdin",
+                "rest",
+                "throw",
+                "values",
+                "write_file",
+                "gensym",
+                "all",
+                "any",
+                "contains",
+                "filter",
+                "find",
+                "map",
+                "range",
+                "reduce",
+                "reverse",
+                "sum",
+                "unless"
+            ]
+        );
+
+        // formatting
+        assert_eq!(
+            responses[6]["result"],
+            json!([{
+                "range": {
+                    "start": {"line": 0, "character": 0},
+                    "end": {"line": 2, "character": 0},
+                },
+                "newText": "let add = fn(a, b) {\n    a + b\n};\n\nadd(1, len(\"x\"));\n",
+            }])
+        );
+
+        // didChange
+        let diagnostics = &responses[7]["params"]["diagnostics"];
+        assert_eq!(
+            diagnostics,
+            &json!([{
+                "range": {
+                    "start": {"line": 0, "character": 4},
+                    "end": {"line": 0, "character": 4},
+                },
+                "severity": 2,
+                "code": "unused-variable",
+                "source": "monkey",
+                "message": "`a` is never used",
+            }, {
+                "range": {
+                    "start": {"line": 1, "character": 8},
+                    "end": {"line": 1, "character": 8},
+                },
+                "severity": 1,
+                "code": "parse-error",
+                "source": "monkey",
+                "message": "could not parse Semicolon as prefix expression",
+            }])
+        );
+
+        // unknown method
+        assert_eq!(responses[8]["error"]["code"], json!(-32601));
+
+        // shutdown
+        assert_eq!(
+            responses[9],
+            json!({"jsonrpc": "2.0", "id": 8, "result": null})
+        );
+    }
+
+    #[test]
+    fn lsp_utf16_positions() {
+        let uri = "file:///a.mk";
+        let text = "let s = \"😀\"; let x = s;\nx;\n";
+        let messages = vec![
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": uri, "languageId": "monkey", "version": 1, "text": text},
+            }}),
+            request(1, "textDocument/definition", uri, 0, 22),
+            request(2, "textDocument/definition", uri, 1, 0),
+        ];
+        let responses = session(messages);
+        assert_eq!(
+            responses[1]["result"]["range"],
+            json!({"start": {"line": 0, "character": 4}, "end": {"line": 0, "character": 5}})
+        );
+        assert_eq!(
+            responses[2]["result"]["range"],
+            json!({"start": {"line": 0, "character": 18}, "end": {"line": 0, "character": 19}})
+        );
+    }
+
+    fn request(id: i64, method: &str, uri: &str, line: usize, character: usize) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {
+            "textDocument": {"uri": uri},
+            "position": {"line": line, "character": character},
+        }})
+    }
+
+    fn session(messages: Vec<Value>) -> Vec<Value> {
+        let mut input = String::new();
+        for m in messages {
+            let body = m.to_string();
+            input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        }
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let mut res = Vec::new();
+        let mut rest = output.as_str();
+        while let Some(i) = rest.find("\r\n\r\n") {
+            let length = rest[..i]
+                .trim_start_matches("Content-Length: ")
+                .parse::<usize>()
+                .unwrap();
+            let body = &rest[i + 4..i + 4 + length];
+            res.push(serde_json::from_str(body).unwrap());
+            rest = &rest[i + 4 + length..];
+        }
+        res
+    }
+}
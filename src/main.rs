@@ -1,8 +1,163 @@
+use monkey::formatter;
+use monkey::linter;
+use monkey::lsp;
 use monkey::repl::Repl;
+use monkey::serialize;
+use monkey::{Capabilities, Context};
+use serde_json::{json, Value};
 use std::io::{self};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+const USAGE: &str = "usage:
+    monkey [options]              start the REPL
+    monkey run [options] <file>   evaluate a file
+    monkey fmt [--check] <file>   format a file in place
+    monkey lint [--json] <file>   report problems in a file
+    monkey tokens <file>          print the tokens of a file with their positions
+    monkey parse [--emit ast-json|ast-sexp] <file>
+                                  print the syntax tree of a file
+    monkey lsp                    start the language server on stdio
+
+options:
+    --no-prelude                  evaluate without the prelude
+    --no-io                       disable the file system and I/O builtins";
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let prelude = !take_flag(&mut args, "--no-prelude");
+    let capabilities = Capabilities {
+        io: !take_flag(&mut args, "--no-io"),
+    };
+    let _context = Context::new()
+        .with_capabilities(capabilities)
+        .with_prelude(prelude)
+        .enter();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let res = match args.as_slice() {
+        [] => repl(prelude, capabilities),
+        ["run", path] => run(path),
+        ["fmt", rest @ ..] => fmt(rest),
+        ["lint", rest @ ..] => lint(rest),
+        ["tokens", path] => tokens(path),
+        ["parse", rest @ ..] => parse(rest),
+        ["lsp"] => lsp(),
+        _ => Err(USAGE.into()),
+    };
+    if let Err(e) = res {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// removes the option, whether it was given
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
+}
+
+fn repl(prelude: bool, capabilities: Capabilities) -> Result<(), Box<dyn std::error::Error>> {
     let prompt = ">> ";
-    let repl = Repl::new(prompt);
+    let repl = Repl::new(prompt)
+        .with_prelude(prelude)
+        .with_capabilities(capabilities);
     repl.start(io::stdin(), io::stdout())
 }
+
+fn run(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // imports are relative to the file
+    monkey::run_file(path)?;
+    Ok(())
+}
+
+fn lsp() -> Result<(), Box<dyn std::error::Error>> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    lsp::run(stdin.lock(), stdout.lock())?;
+    Ok(())
+}
+
+fn fmt(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    // monkey fmt [--check] <file>
+    let (check, path) = match args {
+        ["--check", path] => (true, path),
+        [path] => (false, path),
+        _ => return Err(USAGE.into()),
+    };
+    let source = std::fs::read_to_string(path)?;
+    let formatted = formatter::format(source.as_str())?;
+    if formatted == source {
+        return Ok(());
+    }
+    if check {
+        return Err(format!("{} is not formatted", path).into());
+    }
+    std::fs::write(path, formatted)?;
+    Ok(())
+}
+
+fn lint(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    // monkey lint [--json] <file>
+    let (json, path) = match args {
+        ["--json", path] => (true, path),
+        [path] => (false, path),
+        _ => return Err(USAGE.into()),
+    };
+    let source = std::fs::read_to_string(path)?;
+    let diagnostics = linter::lint(source);
+    if json {
+        // [{"file": ..., "line": ..., "column": ..., "rule": ..., "message": ...}, ...]
+        let items = diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "file": path,
+                    "line": d.line,
+                    "column": d.column,
+                    "rule": d.rule.to_string(),
+                    "message": d.message,
+                })
+            })
+            .collect::<Vec<_>>();
+        println!("{}", Value::Array(items));
+    } else {
+        for d in &diagnostics {
+            println!("{}:{}", path, d);
+        }
+    }
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} problem(s) found", diagnostics.len()).into())
+    }
+}
+
+fn tokens(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(path)?;
+    let (dump, diagnostics) = monkey::dump_tokens(source);
+    println!("{}", dump);
+    for d in &diagnostics {
+        eprintln!("{}:{}", path, d);
+    }
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} illegal token(s) found", diagnostics.len()).into())
+    }
+}
+
+fn parse(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    // monkey parse [--emit ast-json|ast-sexp] <file>
+    let (emit, path) = match args {
+        ["--emit", emit, path] => (*emit, path),
+        [path] => ("ast-json", path),
+        _ => return Err(USAGE.into()),
+    };
+    let source = std::fs::read_to_string(path)?;
+    let tree = match emit {
+        "ast-json" => serialize::to_json(source)?,
+        "ast-sexp" => serialize::to_sexp(source)?,
+        _ => return Err(format!("unknown --emit format `{}`", emit).into()),
+    };
+    println!("{}", tree);
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::ast::{self};
+use indexmap::IndexMap;
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::{self};
 use std::fmt::{self};
 
@@ -12,35 +13,42 @@ pub enum Object {
     Boolean(bool),
     String(String),
     Array(Vec<Object>),
-    Hash(HashMap<HashKey, Object>),
+    Hash(IndexMap<HashKey, Object>),
     Null,
     Return(Box<Object>),
-    Error(String),
+    Error(ErrorKind, String),
+    // thrown by `throw(...)`, holds `{"kind": ..., "message": ...}`
+    Throw(Box<Object>),
     Let,
     Function {
-        params: Vec<ast::Identifier>,
+        params: Vec<ast::Parameter>,
         body: ast::BlockStatement,
         env: Environment,
     },
     LetFunction {
         id: ast::Identifier,
-        params: Vec<ast::Identifier>,
+        params: Vec<ast::Parameter>,
         body: ast::BlockStatement,
         env: Environment,
     },
     Builtin(fn(Vec<Object>) -> Object),
     Quote(ast::Node),
     Macro {
-        params: Vec<ast::Identifier>,
+        params: Vec<ast::Parameter>,
         body: ast::BlockStatement,
         env: Environment,
     },
+    // `import "<path>" as <identifier>`, the exported bindings of a file
+    Module {
+        path: String,
+        exports: IndexMap<String, Object>,
+    },
 }
 
 impl Object {
     pub fn is_error(&self) -> bool {
         match self {
-            Self::Error(_) => true,
+            Self::Error(..) | Self::Throw(_) => true,
             _ => false,
         }
     }
@@ -63,7 +71,18 @@ impl Object {
             }
             Object::Null => "null".into(),
             Object::Return(it) => format!("{}", it.inspect()),
-            Object::Error(it) => format!("{}", it),
+            Object::Error(_, it) => format!("{}", it),
+            Object::Throw(it) => match it.as_ref() {
+                Object::Hash(hash) => {
+                    let field = |name: &str| {
+                        hash.get(&HashKey::String(name.into()))
+                            .map(|v| v.to_string())
+                            .unwrap_or_default()
+                    };
+                    format!("{}: {}", field("kind"), field("message"))
+                }
+                other => other.inspect(),
+            },
             Object::Let => "".into(),
             Object::Function { params, body, .. } => {
                 let params = params.iter().join(", ");
@@ -79,6 +98,7 @@ impl Object {
                 let params = params.iter().join(", ");
                 format!("macro({}) {{\n{}\n}}", params, body)
             }
+            Object::Module { path, .. } => format!(r#"module("{}")"#, path),
         }
     }
 }
@@ -93,6 +113,34 @@ impl fmt::Display for Object {
     }
 }
 
+// the `kind` of an error bound by `catch (e)`, decided where the error is created
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorKind {
+    Name,
+    Argument,
+    Type,
+    Import,
+    Match,
+    IO,
+    Runtime,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ErrorKind::Name => "Name",
+            ErrorKind::Argument => "Argument",
+            ErrorKind::Type => "Type",
+            ErrorKind::Import => "Import",
+            ErrorKind::Match => "Match",
+            ErrorKind::IO => "IO",
+            ErrorKind::Runtime => "Runtime",
+        };
+        write!(f, "{}Error", s)
+    }
+}
+
 // HashKey
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -100,6 +148,7 @@ pub enum HashKey {
     Integer(i64),
     String(String),
     Boolean(bool),
+    Array(Vec<HashKey>),
 }
 
 impl fmt::Display for HashKey {
@@ -108,6 +157,7 @@ impl fmt::Display for HashKey {
             HashKey::Integer(it) => write!(f, "{}", it),
             HashKey::String(it) => write!(f, r#""{}""#, it),
             HashKey::Boolean(it) => write!(f, "{}", it),
+            HashKey::Array(it) => write!(f, "[{}]", it.iter().join(", ")),
         }
     }
 }
@@ -120,23 +170,47 @@ impl convert::TryFrom<Object> for HashKey {
             Object::Integer(it) => Ok(Self::Integer(it)),
             Object::String(it) => Ok(Self::String(it)),
             Object::Boolean(it) => Ok(Self::Boolean(it)),
+            Object::Array(it) => match it.iter().cloned().map(Self::try_from).collect() {
+                Ok(keys) => Ok(Self::Array(keys)),
+                Err(_) => {
+                    let o = Object::Array(it);
+                    Err((format!("could not convert `{}` as HashKey", o), o))
+                }
+            },
             o => Err((format!("could not convert `{}` as HashKey", o), o)),
         }
     }
 }
 
+impl convert::From<HashKey> for Object {
+    fn from(key: HashKey) -> Self {
+        match key {
+            HashKey::Integer(it) => Self::Integer(it),
+            HashKey::String(it) => Self::String(it),
+            HashKey::Boolean(it) => Self::Boolean(it),
+            HashKey::Array(it) => Self::Array(it.into_iter().map(Self::from).collect()),
+        }
+    }
+}
+
 // environment
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Environment {
     store: HashMap<String, Object>,
+    // names declared by `const` in this scope
+    consts: HashSet<String>,
     outer: Option<Box<Environment>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
         let store = HashMap::new();
-        Self { store, outer: None }
+        Self {
+            store,
+            consts: HashSet::new(),
+            outer: None,
+        }
     }
 
     pub fn new_with_outer(outer: Environment) -> Self {
@@ -159,4 +233,15 @@ impl Environment {
         let id = id.to_string();
         self.store.insert(id, value);
     }
+
+    // arrays and hashes are values, so the bound value is deeply immutable
+    pub fn set_const(&mut self, id: &ast::Identifier, value: Object) {
+        self.set(id, value);
+        self.consts.insert(id.to_string());
+    }
+
+    // only this scope, inner scopes may shadow constants of outer ones
+    pub fn is_const(&self, id: &ast::Identifier) -> bool {
+        self.consts.contains(&id.0)
+    }
 }
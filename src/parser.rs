@@ -1,19 +1,54 @@
 use crate::ast::{self};
-use crate::lexer::Lexer;
+use crate::lexer::{illegal_token_message, Lexer, Position, PositionedLexer};
 use crate::token::Token;
 use itertools::Itertools;
-use std::collections::BTreeMap;
 
 pub fn parse(lexer: Lexer) -> std::result::Result<ast::Program, Errors> {
+    let (program, errors) = parse_partial(lexer);
+    if errors.0.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+// returns the statements that could be parsed alongside all errors, each
+// prefixed with the `line:column` of the offending token
+pub fn parse_partial(lexer: Lexer) -> (ast::Program, Errors) {
+    let (program, errors) = parse_with_positions(lexer);
+    let errors = errors
+        .into_iter()
+        .map(|(e, position)| format!("{}: {}", position, e))
+        .collect();
+    (program, Errors(errors))
+}
+
+// same as `parse_partial`, errors are paired with the position of the offending token
+// instead
+pub fn parse_with_positions(lexer: Lexer) -> (ast::Program, Vec<(String, Position)>) {
+    let (program, errors, _) = parse_with_skipped(lexer);
+    (program, errors)
+}
+
+// the source left out of the program to recover from errors, `(start, end)`
+// with an exclusive `end`, `None` for the end of the input
+pub(crate) type Skipped = Vec<(Position, Option<Position>)>;
+
+// same as `parse_with_positions`, also returns the skipped source
+pub(crate) fn parse_with_skipped(lexer: Lexer) -> (ast::Program, Vec<(String, Position)>, Skipped) {
     let mut parser = Parser::new(lexer);
     parser.parse()
 }
 
+// the number of errors reported before parsing is abandoned
+const MAX_ERRORS: usize = 20;
+
 // Precedence
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, PartialOrd)]
-enum Precedence {
+pub(crate) enum Precedence {
     Lowest,
+    NullCoalescing, // ??
     Equals,
     LessGreater,
     Sum,     // + or -
@@ -23,6 +58,18 @@ enum Precedence {
     Index,
 }
 
+impl Precedence {
+    pub(crate) fn of_infix(op: ast::InfixOperator) -> Self {
+        match op {
+            ast::InfixOperator::Add | ast::InfixOperator::Sub => Precedence::Sum,
+            ast::InfixOperator::Mul | ast::InfixOperator::Div => Precedence::Product,
+            ast::InfixOperator::LT | ast::InfixOperator::GT => Precedence::LessGreater,
+            ast::InfixOperator::Eq | ast::InfixOperator::NotEq => Precedence::Equals,
+            ast::InfixOperator::NullCoalescing => Precedence::NullCoalescing,
+        }
+    }
+}
+
 // Erros
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -43,22 +90,52 @@ type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 // Parser
 
 struct Parser {
-    lexer: std::iter::Peekable<Lexer>,
+    lexer: std::iter::Peekable<PositionedLexer>,
     current_token: Option<Token>,
+    current_position: Position,
+    errors: Vec<(String, Position)>,
+    skipped: Skipped,
+    // the position of the last illegal token
+    illegal: Option<Position>,
+    // nesting level of block statements
+    depth: usize,
+    // comment tokens read but not attached to a node yet
+    comments: Vec<Comment>,
+}
+
+struct Comment {
+    text: String,
+    position: Position,
+    // on the line of the token before it
+    same_line: bool,
 }
 
 impl Parser {
     fn new(lexer: Lexer) -> Self {
-        let mut iter = lexer.into_iter().peekable();
-        let current_token = iter.next();
-        Self {
-            lexer: iter,
-            current_token,
-        }
+        let mut parser = Self {
+            lexer: lexer.positioned().peekable(),
+            current_token: None,
+            current_position: Position::default(),
+            errors: Vec::new(),
+            skipped: Vec::new(),
+            illegal: None,
+            depth: 0,
+            comments: Vec::new(),
+        };
+        parser.next();
+        parser
     }
 
     fn next(&mut self) {
-        self.current_token = self.lexer.next();
+        self.skip_trivia();
+        // the position stays at the last token on EOF
+        match self.lexer.next() {
+            Some((token, position)) => {
+                self.current_token = Some(token);
+                self.current_position = position;
+            }
+            None => self.current_token = None,
+        }
     }
 
     fn current_token(&self) -> Option<&Token> {
@@ -66,29 +143,149 @@ impl Parser {
     }
 
     fn peek_token(&mut self) -> Option<&Token> {
-        self.lexer.peek()
+        self.skip_trivia();
+        self.lexer.peek().map(|(t, _)| t)
+    }
+
+    // collects comments, reports illegal tokens instead of parsing them
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.lexer.peek() {
+                Some((Token::Comment(_), _)) | Some((Token::Illegal(_), _)) => {}
+                _ => return,
+            }
+            match self.lexer.next() {
+                Some((Token::Comment(text), position)) => {
+                    let same_line =
+                        self.current_token.is_some() && position.line == self.current_position.line;
+                    self.comments.push(Comment {
+                        text,
+                        position,
+                        same_line,
+                    });
+                }
+                Some((Token::Illegal(s), position)) => {
+                    self.errors.push((illegal_token_message(&s), position));
+                    self.illegal = Some(position);
+                }
+                _ => {}
+            }
+        }
     }
 
-    fn parse(&mut self) -> std::result::Result<ast::Program, Errors> {
+    // the comments at the end of a block, followed by no statement
+    fn push_comments(&mut self, statements: &mut Vec<ast::Statement>) {
+        statements.extend(
+            self.comments
+                .drain(..)
+                .map(|c| ast::Statement::Comment(c.text)),
+        );
+    }
+
+    // the comments before `position`, leading the node there
+    fn take_comments_before(&mut self, position: Position) -> Vec<String> {
+        let (before, rest) = self
+            .comments
+            .drain(..)
+            .partition::<Vec<_>, _>(|c| c.position < position);
+        self.comments = rest;
+        before.into_iter().map(|c| c.text).collect()
+    }
+
+    // the comments trailing a node that ends at `end` (e.g. `;` or `,`): those
+    // before `end` and those after it on its line, unless another node follows
+    // on that line too (e.g. `1, /* two */ 2`)
+    fn take_trailing_comments(&mut self, end: Position) -> Vec<String> {
+        self.skip_trivia();
+        let next_line = match self.lexer.peek() {
+            Some((Token::RBrace, _)) | Some((Token::RBracket, _)) | Some((Token::RParen, _)) => {
+                true
+            }
+            Some((_, position)) => position.line > end.line,
+            None => true,
+        };
+        let (trailing, rest) = self
+            .comments
+            .drain(..)
+            .partition::<Vec<_>, _>(|c| c.position < end || (c.same_line && next_line));
+        self.comments = rest;
+        trailing.into_iter().map(|c| c.text).collect()
+    }
+
+    fn parse(&mut self) -> (ast::Program, Vec<(String, Position)>, Skipped) {
         let mut statements = Vec::new();
-        let mut errors = Vec::new();
-        while self.current_token().is_some() {
+        while self.current_token().is_some() && !self.has_too_many_errors() {
+            let start = self.current_position;
+            let leading = self.take_comments_before(start);
             match self.parse_statement() {
-                Ok(stmt) => statements.push(stmt),
-                Err(e) => errors.push(format!("{}", e)),
+                Ok(stmt) => {
+                    let trailing = self.take_trailing_comments(self.current_position);
+                    statements.push(stmt.with_comments(ast::Comments { leading, trailing }));
+                    self.next();
+                }
+                Err(e) => self.recover(e, start),
             }
-            self.next();
         }
-        if errors.is_empty() {
-            Ok(ast::Program { statements })
-        } else {
-            Err(Errors(errors))
+        self.push_comments(&mut statements);
+        let errors = std::mem::take(&mut self.errors);
+        let skipped = std::mem::take(&mut self.skipped);
+        (ast::Program { statements }, errors, skipped)
+    }
+
+    fn recover(&mut self, e: Box<dyn std::error::Error>, start: Position) {
+        // records the error and skips to the beginning of the next statement,
+        // which `start` was the beginning of
+        if self.has_too_many_errors() {
+            return;
+        }
+        let position = self.current_position;
+        // an illegal token in the statement already explains the error (e.g.
+        // an unterminated string leaves the statement unfinished)
+        if self.illegal.iter().all(|illegal| *illegal < start) {
+            self.errors.push((format!("{}", e), position));
         }
+        if self.has_too_many_errors() {
+            let e = format!("too many errors, stopped after {}", MAX_ERRORS);
+            self.errors.push((e, position));
+            self.skipped.push((start, None));
+            return;
+        }
+        self.synchronize();
+        let end = self.current_token().map(|_| self.current_position);
+        self.skipped.push((start, end));
+    }
+
+    fn synchronize(&mut self) {
+        // stops at:
+        //   - the token after `;`
+        //   - `}` closing the current block
+        //   - `let`, `const` or `return`
+        let mut skipped = false;
+        while let Some(t) = self.current_token() {
+            match t {
+                Token::Semicolon => {
+                    self.next();
+                    return;
+                }
+                Token::RBrace if self.depth > 0 => return,
+                Token::Let | Token::Const | Token::Return if skipped => return,
+                _ => {}
+            }
+            self.next();
+            skipped = true;
+        }
+    }
+
+    fn has_too_many_errors(&self) -> bool {
+        self.errors.len() >= MAX_ERRORS
     }
 
     fn parse_statement(&mut self) -> Result<ast::Statement> {
         match self.current_token() {
             Some(Token::Let) => self.parse_let_statement(),
+            Some(Token::Const) => self.parse_const_statement(),
+            Some(Token::Import) => self.parse_import_statement(),
+            Some(Token::Export) => self.parse_export_statement(),
             Some(Token::Return) => self.parse_return_statement(),
             Some(_) => self.parse_expression_statement(),
             t => Err(Self::new_parse_error("statement", t).into()),
@@ -102,6 +299,21 @@ impl Parser {
         self.expect_current_token(Token::Let)?;
         self.next();
 
+        // [<pattern>, ...] = <expression>; | {<pattern>, ...} = <expression>;
+        if let Some(Token::LBracket) | Some(Token::LBrace) = self.current_token() {
+            let pattern = self.parse_pattern()?;
+            self.expect_peek_token_and_next(Token::Assign)?;
+            self.next();
+            let expression = self.parse_expression(Precedence::Lowest)?;
+            if self.peek_token() == Some(&Token::Semicolon) {
+                self.next();
+            }
+            return Ok(ast::Statement::Destructure {
+                pattern,
+                expression,
+            });
+        }
+
         // <identifier>
         let identifier = Self::parse_identifier(self.current_token())?;
 
@@ -121,6 +333,152 @@ impl Parser {
         })
     }
 
+    fn parse_const_statement(&mut self) -> Result<ast::Statement> {
+        // const <identifier> = <expression>;
+
+        // const <identifier>
+        self.expect_current_token(Token::Const)?;
+        self.next();
+        let identifier = Self::parse_identifier(self.current_token())?;
+
+        // = <expression>
+        self.expect_peek_token_and_next(Token::Assign)?;
+        self.next();
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_token() == Some(&Token::Semicolon) {
+            self.next();
+        }
+
+        Ok(ast::Statement::Const {
+            identifier,
+            expression,
+        })
+    }
+
+    fn parse_import_statement(&mut self) -> Result<ast::Statement> {
+        // import "<path>" as <identifier>; | import {<identifier>, ...} from "<path>";
+
+        // import
+        self.expect_current_token(Token::Import)?;
+        self.next();
+        let (path, binding) = if self.current_token() == Some(&Token::LBrace) {
+            // {<identifier>, ...} from "<path>"
+            let names = self.parse_comma_separated_list(Token::RBrace, |parser| {
+                Self::parse_identifier(parser.current_token())
+            })?;
+            self.next();
+            self.expect_contextual_keyword("from")?;
+            self.next();
+            let path = self.parse_path()?;
+            (path, ast::ImportBinding::Names(names))
+        } else {
+            // "<path>" as <identifier>
+            let path = self.parse_path()?;
+            self.next();
+            self.expect_contextual_keyword("as")?;
+            self.next();
+            let id = Self::parse_identifier(self.current_token())?;
+            (path, ast::ImportBinding::Module(id))
+        };
+        if self.peek_token() == Some(&Token::Semicolon) {
+            self.next();
+        }
+
+        Ok(ast::Statement::Import { path, binding })
+    }
+
+    fn parse_path(&mut self) -> Result<String> {
+        match self.current_token() {
+            Some(Token::String(s)) => Ok(s.clone()),
+            t => Err(Self::new_parse_error("path", t).into()),
+        }
+    }
+
+    // `as` and `from` are identifiers elsewhere
+    fn expect_contextual_keyword(&mut self, keyword: &str) -> Result<()> {
+        match self.current_token() {
+            Some(Token::Identifier(id)) if id == keyword => Ok(()),
+            t => Err(Self::new_token_error(format!("`{}`", keyword), t).into()),
+        }
+    }
+
+    fn parse_export_statement(&mut self) -> Result<ast::Statement> {
+        // export let ...; | export const ...;
+        self.expect_current_token(Token::Export)?;
+        self.next();
+        let stmt = match self.current_token() {
+            Some(Token::Let) => self.parse_let_statement()?,
+            Some(Token::Const) => self.parse_const_statement()?,
+            t => return Err(Self::new_token_error("Let or Const", t).into()),
+        };
+        Ok(ast::Statement::Export(stmt.into()))
+    }
+
+    fn parse_pattern(&mut self) -> Result<ast::Pattern> {
+        match self.current_token() {
+            Some(Token::LBracket) => {
+                // [<pattern>, ..., ...<identifier>]
+                let items = self.parse_comma_separated_list(Token::RBracket, |parser| {
+                    let is_rest = parser.current_token() == Some(&Token::Ellipsis);
+                    if is_rest {
+                        parser.next();
+                    }
+                    Ok((is_rest, parser.parse_pattern()?))
+                })?;
+                let n = items.len();
+                let mut elements = Vec::new();
+                let mut rest = None;
+                for (i, (is_rest, p)) in items.into_iter().enumerate() {
+                    match p {
+                        p if !is_rest => elements.push(p),
+                        ast::Pattern::Identifier(id) if i + 1 == n => rest = Some(id),
+                        p => {
+                            return Err(format!(
+                                "rest element `...{}` must be the last element and an identifier",
+                                p
+                            )
+                            .into())
+                        }
+                    }
+                }
+                Ok(ast::Pattern::Array { elements, rest })
+            }
+            Some(Token::LBrace) => {
+                // {<identifier>, <identifier>: <pattern>, ...}
+                let fields = self.parse_comma_separated_list(Token::RBrace, |parser| {
+                    let key = Self::parse_identifier(parser.current_token())?;
+                    if parser.peek_token() != Some(&Token::Colon) {
+                        return Ok((key.clone(), key.into()));
+                    }
+                    parser.next();
+                    parser.next();
+                    Ok((key, parser.parse_pattern()?))
+                })?;
+                Ok(ast::Pattern::Hash(fields))
+            }
+            Some(Token::Identifier(id)) if id == "_" => Ok(ast::Pattern::Wildcard),
+            Some(Token::Int(_)) => Ok(ast::Pattern::Literal(self.parse_integer_expression()?)),
+            Some(Token::Minus) => {
+                // -<integer>
+                self.next();
+                match self.current_token() {
+                    Some(Token::Int(s)) => match format!("-{}", s).parse::<i64>() {
+                        Ok(n) => Ok(ast::Pattern::Literal(ast::Expression::Integer(n))),
+                        Err(_) => {
+                            Err(Self::new_parse_error("integer", self.current_token()).into())
+                        }
+                    },
+                    t => Err(Self::new_parse_error("integer", t).into()),
+                }
+            }
+            Some(Token::String(_)) => Ok(ast::Pattern::Literal(self.parse_string_expression()?)),
+            Some(Token::True) | Some(Token::False) => {
+                Ok(ast::Pattern::Literal(self.parse_boolean_expression()?))
+            }
+            t => Ok(Self::parse_identifier(t)?.into()),
+        }
+    }
+
     fn parse_return_statement(&mut self) -> Result<ast::Statement> {
         // return <expression>;
 
@@ -152,22 +510,36 @@ impl Parser {
     fn parse_block_statement(&mut self) -> Result<ast::BlockStatement> {
         self.expect_current_token(Token::LBrace)?;
         self.next();
+        self.depth += 1;
         let mut statements = Vec::new();
         while self
             .current_token()
             .filter(|&t| t != &Token::RBrace)
             .is_some()
+            && !self.has_too_many_errors()
         {
-            let s = self.parse_statement()?;
-            statements.push(s);
-            self.next();
+            let start = self.current_position;
+            let leading = self.take_comments_before(start);
+            match self.parse_statement() {
+                Ok(stmt) => {
+                    let trailing = self.take_trailing_comments(self.current_position);
+                    statements.push(stmt.with_comments(ast::Comments { leading, trailing }));
+                    self.next();
+                }
+                Err(e) => self.recover(e, start),
+            }
         }
+        self.push_comments(&mut statements);
+        self.depth -= 1;
         Ok(ast::BlockStatement { statements })
     }
 
     fn parse_expression(&mut self, precedence: Precedence) -> Result<ast::Expression> {
         // <expression>
 
+        // e.g. `/* one */ 1`
+        let leading = self.take_comments_before(self.current_position);
+
         // parse prefix
         let mut left = self.parse_prefix()?;
 
@@ -179,12 +551,13 @@ impl Parser {
             && precedence < self.peek_prececence()
         {
             let (expr, parsed) = self.parse_infix(left)?;
+            left = expr;
             if !parsed {
-                return Ok(expr);
+                break;
             }
-            left = expr;
         }
-        Ok(left)
+        let trailing = Vec::new();
+        Ok(left.with_comments(ast::Comments { leading, trailing }))
     }
 
     fn parse_prefix(&mut self) -> Result<ast::Expression> {
@@ -198,6 +571,8 @@ impl Parser {
             Some(Token::Bang) | Some(Token::Minus) => self.parse_prefix_expression(),
             Some(Token::LParen) => self.parse_grouped_expression(),
             Some(Token::If) => self.parse_if_expression(),
+            Some(Token::Try) => self.parse_try_expression(),
+            Some(Token::Match) => self.parse_match_expression(),
             Some(Token::Function) => self.parse_function_expression(),
             Some(Token::Quote) => self.parse_quote_expression(),
             Some(Token::Unquote) => self.parse_unquote_expression(),
@@ -215,11 +590,17 @@ impl Parser {
             | Some(Token::LT)
             | Some(Token::GT)
             | Some(Token::Eq)
-            | Some(Token::NotEq) => {
+            | Some(Token::NotEq)
+            | Some(Token::QuestionQuestion) => {
                 self.next();
                 let expr = self.parse_infix_expression(left)?;
                 Ok((expr, true))
             }
+            Some(Token::QuestionDot) => {
+                self.next();
+                let expr = self.parse_optional_chaining_expression(left)?;
+                Ok((expr, true))
+            }
             Some(Token::LParen) => {
                 self.next();
                 let expr = self.parse_call_expression(left)?;
@@ -230,6 +611,11 @@ impl Parser {
                 let expr = self.parse_index_expression(left)?;
                 Ok((expr, true))
             }
+            Some(Token::Dot) => {
+                self.next();
+                let expr = self.parse_member_expression(left)?;
+                Ok((expr, true))
+            }
             _ => return Ok((left, false)),
         }
     }
@@ -275,22 +661,44 @@ impl Parser {
         self.expect_current_token(Token::LBrace)?;
 
         let is_not_end = |tok: Option<&Token>| tok.filter(|&t| t != &Token::RBrace).is_some();
-        // <expr>: <expr>, [<expr>: <expr>, ...]
-        let mut map = BTreeMap::new();
+        // <expr>: <expr> | ...<expr>, [<expr>: <expr> | ...<expr>, ...]
+        let mut entries = Vec::new();
         while is_not_end(self.peek_token()) {
             self.next();
-            let key = self.parse_expression(Precedence::Lowest)?;
-            self.expect_peek_token_and_next(Token::Colon)?;
-            self.next();
-            let value = self.parse_expression(Precedence::Lowest)?;
-            map.insert(key, value);
+            if self.current_token() == Some(&Token::Ellipsis) {
+                self.next();
+                let hash = self.parse_expression(Precedence::Lowest)?;
+                entries.push(ast::HashEntry::Spread(hash));
+            } else {
+                let key = self.parse_expression(Precedence::Lowest)?;
+                self.expect_peek_token_and_next(Token::Colon)?;
+                self.next();
+                let value = self.parse_expression(Precedence::Lowest)?;
+                entries.push(ast::HashEntry::Pair(key, value));
+            }
             if is_not_end(self.peek_token()) {
                 self.expect_peek_token_and_next(Token::Comma)?;
+                let trailing = self.take_trailing_comments(self.current_position);
+                Self::comment_last(&mut entries, Self::comment_entry, trailing);
             }
         }
         // }
         self.expect_peek_token_and_next(Token::RBrace)?;
-        Ok(ast::Expression::Hash(map))
+        let trailing = self.take_comments_before(self.current_position);
+        Self::comment_last(&mut entries, Self::comment_entry, trailing);
+        Ok(ast::Expression::Hash(entries))
+    }
+
+    // the comments after an entry are kept with its value
+    fn comment_entry(entry: ast::HashEntry, trailing: Vec<String>) -> ast::HashEntry {
+        match entry {
+            ast::HashEntry::Pair(key, value) => {
+                ast::HashEntry::Pair(key, Self::comment_expression(value, trailing))
+            }
+            ast::HashEntry::Spread(hash) => {
+                ast::HashEntry::Spread(Self::comment_expression(hash, trailing))
+            }
+        }
     }
 
     fn parse_prefix_expression(&mut self) -> Result<ast::Expression> {
@@ -317,6 +725,7 @@ impl Parser {
             Some(Token::GT) => ast::InfixOperator::GT,
             Some(Token::Eq) => ast::InfixOperator::Eq,
             Some(Token::NotEq) => ast::InfixOperator::NotEq,
+            Some(Token::QuestionQuestion) => ast::InfixOperator::NullCoalescing,
             t => return Err(Self::new_parse_error("infix operator", t).into()),
         };
         let precedence = self.current_prececence();
@@ -366,6 +775,84 @@ impl Parser {
         })
     }
 
+    fn parse_try_expression(&mut self) -> Result<ast::Expression> {
+        // try { <block> } [ catch (<identifier>) { <body> } ] [ finally { <block> } ]
+
+        // try { <block> }
+        self.expect_current_token(Token::Try)?;
+        self.expect_peek_token_and_next(Token::LBrace)?;
+        let block = self.parse_block_statement()?;
+        // [ catch (<identifier>) { <body> } ]
+        let catch = if self.peek_token() == Some(&Token::Catch) {
+            self.next();
+            self.expect_peek_token_and_next(Token::LParen)?;
+            self.next();
+            let identifier = Self::parse_identifier(self.current_token())?;
+            self.expect_peek_token_and_next(Token::RParen)?;
+            self.expect_peek_token_and_next(Token::LBrace)?;
+            let body = self.parse_block_statement()?;
+            Some(ast::CatchClause { identifier, body })
+        } else {
+            None
+        };
+        // [ finally { <block> } ]
+        let finally = if self.peek_token() == Some(&Token::Finally) {
+            self.next();
+            self.expect_peek_token_and_next(Token::LBrace)?;
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(Self::new_token_error("Catch or Finally", self.peek_token()).into());
+        }
+        Ok(ast::Expression::Try {
+            block,
+            catch,
+            finally,
+        })
+    }
+
+    fn parse_match_expression(&mut self) -> Result<ast::Expression> {
+        // match (<value>) { <pattern> [if <guard>] => <body>, ... }
+
+        // match (<value>)
+        self.expect_current_token(Token::Match)?;
+        self.expect_peek_token_and_next(Token::LParen)?;
+        self.next();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek_token_and_next(Token::RParen)?;
+        // { <arm>, ... }
+        self.expect_peek_token_and_next(Token::LBrace)?;
+        let parser = |parser: &mut Parser| {
+            let pattern = parser.parse_pattern()?;
+            let guard = if parser.peek_token() == Some(&Token::If) {
+                parser.next();
+                parser.next();
+                Some(parser.parse_expression(Precedence::Lowest)?)
+            } else {
+                None
+            };
+            parser.expect_peek_token_and_next(Token::FatArrow)?;
+            parser.next();
+            let body = parser.parse_expression(Precedence::Lowest)?;
+            Ok(ast::MatchArm {
+                pattern,
+                guard,
+                body,
+            })
+        };
+        let comment = |arm: ast::MatchArm, trailing| ast::MatchArm {
+            body: Self::comment_expression(arm.body, trailing),
+            ..arm
+        };
+        let arms = self.parse_commented_list(Token::RBrace, parser, Some(comment))?;
+        Ok(ast::Expression::Match {
+            value: Box::new(value),
+            arms,
+        })
+    }
+
     fn parse_function_expression(&mut self) -> Result<ast::Expression> {
         // fn(<arguments>) { <body> }
 
@@ -385,6 +872,11 @@ impl Parser {
         let function: ast::CallExpressionFunction = match function {
             ast::Expression::Identifier(id) => id.into(),
             ast::Expression::Function(f) => f.into(),
+            expr @ ast::Expression::Member { .. }
+            | expr @ ast::Expression::Index { .. }
+            | expr @ ast::Expression::Commented(..) => {
+                ast::CallExpressionFunction::Expression(expr.into())
+            }
             _ => {
                 return Err(
                     format!("could not parse {:?} as call expression function", function).into(),
@@ -406,6 +898,46 @@ impl Parser {
         })
     }
 
+    fn parse_member_expression(&mut self, left: ast::Expression) -> Result<ast::Expression> {
+        // <left>.<name>
+        self.expect_current_token(Token::Dot)?;
+        self.next();
+        let name = Self::parse_identifier(self.current_token())?;
+        Ok(ast::Expression::Member {
+            left: Box::new(left),
+            name,
+        })
+    }
+
+    fn parse_optional_chaining_expression(
+        &mut self,
+        left: ast::Expression,
+    ) -> Result<ast::Expression> {
+        // <left>?.[<index>] | <left>?.(<arguments>)
+        self.expect_current_token(Token::QuestionDot)?;
+        match self.peek_token() {
+            Some(Token::LBracket) => {
+                self.next();
+                self.next();
+                let index = self.parse_expression(Precedence::Lowest)?;
+                self.expect_peek_token_and_next(Token::RBracket)?;
+                Ok(ast::Expression::OptionalIndex {
+                    left: Box::new(left),
+                    index: Box::new(index),
+                })
+            }
+            Some(Token::LParen) => {
+                self.next();
+                let args = self.parse_expression_list(Token::RParen)?;
+                Ok(ast::Expression::OptionalCall {
+                    function: Box::new(left),
+                    args,
+                })
+            }
+            t => Err(Self::new_token_error("LBracket or LParen", t).into()),
+        }
+    }
+
     fn parse_quote_expression(&mut self) -> Result<ast::Expression> {
         // quote(<expr>)
 
@@ -449,6 +981,17 @@ impl Parser {
         &mut self,
         end: Token,
         parser: F,
+    ) -> Result<Vec<T>> {
+        self.parse_commented_list(end, parser, None)
+    }
+
+    // same as `parse_comma_separated_list`, `comment` attaches the comments
+    // trailing an item (e.g. `1, // one`) to it
+    fn parse_commented_list<T, F: Fn(&mut Parser) -> Result<T>>(
+        &mut self,
+        end: Token,
+        parser: F,
+        comment: Option<fn(T, Vec<String>) -> T>,
     ) -> Result<Vec<T>> {
         // [<any>, ...]<end>
         if self.peek_token() == Some(&end) {
@@ -461,29 +1004,98 @@ impl Parser {
         res.push(parser(self)?);
         while self.peek_token() == Some(&Token::Comma) {
             self.next();
+            if let Some(comment) = comment {
+                let trailing = self.take_trailing_comments(self.current_position);
+                Self::comment_last(&mut res, comment, trailing);
+            }
             self.next();
             res.push(parser(self)?);
         }
         // <end>
         self.expect_peek_token_and_next(end)?;
+        if let Some(comment) = comment {
+            let trailing = self.take_comments_before(self.current_position);
+            Self::comment_last(&mut res, comment, trailing);
+        }
 
         Ok(res)
     }
 
+    fn comment_last<T>(
+        items: &mut Vec<T>,
+        comment: fn(T, Vec<String>) -> T,
+        trailing: Vec<String>,
+    ) {
+        if let Some(last) = items.pop() {
+            items.push(comment(last, trailing));
+        }
+    }
+
     fn parse_expression_list(&mut self, end: Token) -> Result<Vec<ast::Expression>> {
-        // [<expr>, ...]<end>
-        self.parse_comma_separated_list(end, |parser| parser.parse_expression(Precedence::Lowest))
+        // [<expr> | ...<expr>, ...]<end>
+        let parser = |parser: &mut Parser| {
+            if parser.current_token() == Some(&Token::Ellipsis) {
+                parser.next();
+                let expr = parser.parse_expression(Precedence::Lowest)?;
+                return Ok(ast::Expression::Spread(expr.into()));
+            }
+            parser.parse_expression(Precedence::Lowest)
+        };
+        self.parse_commented_list(end, parser, Some(Self::comment_expression))
     }
 
-    fn parse_function_parameters(&mut self) -> Result<Vec<ast::Identifier>> {
+    fn comment_expression(expr: ast::Expression, trailing: Vec<String>) -> ast::Expression {
+        let leading = Vec::new();
+        expr.with_comments(ast::Comments { leading, trailing })
+    }
+
+    fn parse_function_parameters(&mut self) -> Result<Vec<ast::Parameter>> {
         // ([<arg>, ...])
         self.expect_current_token(Token::LParen)?;
-        self.parse_comma_separated_list(Token::RParen, |parser| {
-            Self::parse_identifier(parser.current_token())
-        })
+        let params = self.parse_comma_separated_list(Token::RParen, Self::parse_parameter)?;
+        let mut has_default = false;
+        for (i, param) in params.iter().enumerate() {
+            match param {
+                ast::Parameter::Rest(id) if i + 1 < params.len() => {
+                    return Err(
+                        format!("rest parameter `...{}` must be the last parameter", id).into(),
+                    );
+                }
+                ast::Parameter::Identifier(_) | ast::Parameter::Pattern(_) if has_default => {
+                    return Err(format!(
+                        "parameter `{}` without a default value follows a parameter with one",
+                        param
+                    )
+                    .into());
+                }
+                ast::Parameter::Default(..) => has_default = true,
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+
+    fn parse_parameter(&mut self) -> Result<ast::Parameter> {
+        // <id> | <id> = <expr> | ...<id> | <pattern>
+        if let Some(Token::LBracket) | Some(Token::LBrace) = self.current_token() {
+            return Ok(ast::Parameter::Pattern(self.parse_pattern()?));
+        }
+        if self.current_token() == Some(&Token::Ellipsis) {
+            self.next();
+            let id = Self::parse_identifier(self.current_token())?;
+            return Ok(ast::Parameter::Rest(id));
+        }
+        let id = Self::parse_identifier(self.current_token())?;
+        if self.peek_token() == Some(&Token::Assign) {
+            self.next();
+            self.next();
+            let default = self.parse_expression(Precedence::Lowest)?;
+            return Ok(ast::Parameter::Default(id, default));
+        }
+        Ok(id.into())
     }
 
-    fn parse_macro_parameters(&mut self) -> Result<Vec<ast::Identifier>> {
+    fn parse_macro_parameters(&mut self) -> Result<Vec<ast::Parameter>> {
         // ([<arg>, ...])
         self.parse_function_parameters()
     }
@@ -533,8 +1145,10 @@ impl Parser {
             Some(Token::Asterisk) | Some(Token::Slash) => Precedence::Product,
             Some(Token::LT) | Some(Token::GT) => Precedence::LessGreater,
             Some(Token::Eq) | Some(Token::NotEq) => Precedence::Equals,
+            Some(Token::QuestionQuestion) => Precedence::NullCoalescing,
+            Some(Token::QuestionDot) => Precedence::Index,
             Some(Token::LParen) => Precedence::Call,
-            Some(Token::LBracket) => Precedence::Index,
+            Some(Token::LBracket) | Some(Token::Dot) => Precedence::Index,
             _ => Precedence::Lowest,
         }
     }
@@ -562,9 +1176,9 @@ impl Parser {
 
 #[cfg(test)]
 mod tests {
-    use super::{parse, Result};
+    use super::{parse, parse_partial, parse_with_positions, Result, MAX_ERRORS};
     use crate::ast::{self};
-    use crate::lexer::Lexer;
+    use crate::lexer::{Lexer, Position};
 
     #[test]
     fn parse_let_statement() -> Result<()> {
@@ -588,6 +1202,141 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_const_statement() {
+        // (input, expected)
+        let tests = vec![
+            ("const x = 5;", Ok("const x = 5;")),
+            ("const f = fn(a) { a }", Ok("const f = fn(a) a;")),
+            (
+                "const [a] = x;",
+                Err("1:7: could not parse LBracket as identifier"),
+            ),
+            (
+                "const x;",
+                Err("1:7: expected token to be Assign, got Semicolon instead"),
+            ),
+        ];
+        for (input, expected) in tests {
+            let parsed = parse(Lexer::new(input))
+                .map(|p| p.to_string())
+                .map_err(|e| e.0.join("\n"));
+            assert_eq!(parsed, expected.map(String::from).map_err(String::from));
+        }
+    }
+
+    #[test]
+    fn parse_import_export_statement() {
+        // (input, expected)
+        let tests = vec![
+            (
+                r#"import "lib.mk" as lib;"#,
+                Ok(r#"import "lib.mk" as lib;"#),
+            ),
+            (
+                r#"import {a, b} from "../lib.mk""#,
+                Ok(r#"import {a, b} from "../lib.mk";"#),
+            ),
+            ("export let a = 1;", Ok("export let a = 1;")),
+            (
+                "export const [a] = x;",
+                Err("1:14: could not parse LBracket as identifier"),
+            ),
+            (
+                "export 1;",
+                Err("1:8: expected token to be Let or Const, got Int(\"1\") instead"),
+            ),
+            (
+                r#"import "lib.mk" lib;"#,
+                Err("1:17: expected token to be `as`, got Identifier(\"lib\") instead"),
+            ),
+            (
+                "import lib;",
+                Err("1:8: could not parse Identifier(\"lib\") as path"),
+            ),
+            ("lib.a.b", Ok("((lib.a).b)")),
+            ("lib.f(1) + h[\"g\"](2)", Ok("((lib.f)(1) + (h[\"g\"])(2))")),
+            (
+                "lib.1",
+                Err("1:5: could not parse Int(\"1\") as identifier"),
+            ),
+        ];
+        for (input, expected) in tests {
+            let parsed = parse(Lexer::new(input))
+                .map(|p| p.to_string())
+                .map_err(|e| e.0.join("\n"));
+            assert_eq!(parsed, expected.map(String::from).map_err(String::from));
+        }
+    }
+
+    #[test]
+    fn parse_destructure_statement() {
+        // (input, expected)
+        let tests = vec![
+            ("let [a, b] = x;", Ok("let [a, b] = x;")),
+            (
+                "let [a, [b, c], ...d] = x;",
+                Ok("let [a, [b, c], ...d] = x;"),
+            ),
+            ("let [] = x;", Ok("let [] = x;")),
+            (
+                "let {name, age: years} = x;",
+                Ok("let {name, age: years} = x;"),
+            ),
+            ("let {a: [b, {c}]} = x;", Ok("let {a: [b, {c}]} = x;")),
+            ("fn([a, b], {c}) { a }", Ok("fn([a, b], {c}) a")),
+            (
+                "let [...a, b] = x;",
+                Err("1:13: rest element `...a` must be the last element and an identifier"),
+            ),
+            (
+                "let [...[a]] = x;",
+                Err("1:12: rest element `...[a]` must be the last element and an identifier"),
+            ),
+            (
+                "fn(a = 1, [b]) { b }",
+                Err("1:14: parameter `[b]` without a default value follows a parameter with one"),
+            ),
+        ];
+        for (input, expected) in tests {
+            let parsed = parse(Lexer::new(input))
+                .map(|p| p.to_string())
+                .map_err(|e| e.0.join("\n"));
+            assert_eq!(parsed, expected.map(String::from).map_err(String::from));
+        }
+    }
+
+    #[test]
+    fn parse_match_expression() {
+        // (input, expected)
+        let tests = vec![
+            (
+                "match (x) { 1 => a, -2 => b, \"c\" => c, true => d, _ => e }",
+                Ok(r#"match (x) { 1 => a, -2 => b, "c" => c, true => d, _ => e }"#),
+            ),
+            (
+                "match (f(x)) { [a, _, ...b] if a > 1 => b, {k: 0, v} => v }",
+                Ok("match (f(x)) { [a, _, ...b] if (a > 1) => b, {k: 0, v} => v }"),
+            ),
+            ("match (x) {}", Ok("match (x) {  }")),
+            ("let [_, a] = x;", Ok("let [_, a] = x;")),
+            (
+                "match (x) { a b }",
+                Err("1:13: expected token to be FatArrow, got Identifier(\"b\") instead"),
+            ),
+            (
+                "match (x) { -a => 1 }",
+                Err("1:14: could not parse Identifier(\"a\") as integer"),
+            ),
+        ];
+        for (input, expected) in tests {
+            let parsed = parse(Lexer::new(input))
+                .map(|p| p.to_string())
+                .map_err(|e| e.0.join("\n"));
+            assert_eq!(parsed, expected.map(String::from).map_err(String::from));
+        }
+    }
+
     #[test]
     fn parse_return_statement() -> Result<()> {
         // (input, expression)
@@ -699,9 +1448,9 @@ mod tests {
         parse_expression_statement(s, |expr| match expr {
             ast::Expression::Hash(v) => {
                 assert_eq!(v.len(), 3);
-                test_integer_expression(&v[&new_string_expr("one")], 1);
-                test_integer_expression(&v[&new_string_expr("two")], 2);
-                test_integer_expression(&v[&new_string_expr("three")], 3);
+                test_integer_expression(find_hash_value(v, &new_string_expr("one")), 1);
+                test_integer_expression(find_hash_value(v, &new_string_expr("two")), 2);
+                test_integer_expression(find_hash_value(v, &new_string_expr("three")), 3);
             }
             _ => panic!("expression is not hash. got={:?}", expr),
         });
@@ -734,19 +1483,19 @@ mod tests {
             ast::Expression::Hash(v) => {
                 assert_eq!(v.len(), 3);
                 test_infix_expression(
-                    &v[&new_string_expr("one")],
+                    find_hash_value(v, &new_string_expr("one")),
                     new_int_expr(0),
                     ast::InfixOperator::Add,
                     new_int_expr(1),
                 );
                 test_infix_expression(
-                    &v[&new_string_expr("two")],
+                    find_hash_value(v, &new_string_expr("two")),
                     new_int_expr(10),
                     ast::InfixOperator::Sub,
                     new_int_expr(8),
                 );
                 test_infix_expression(
-                    &v[&new_string_expr("three")],
+                    find_hash_value(v, &new_string_expr("three")),
                     new_int_expr(15),
                     ast::InfixOperator::Div,
                     new_int_expr(5),
@@ -962,6 +1711,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_try_expression() -> Result<()> {
+        // (input, expected)
+        let tests = vec![
+            ("try { x } catch (e) { e }", "try { x } catch (e) { e }"),
+            ("try { x } finally { y }", "try { x } finally { y }"),
+            (
+                "try { x } catch (e) { e } finally { y }",
+                "try { x } catch (e) { e } finally { y }",
+            ),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let program = parse(lexer)?;
+            assert_eq!(program.statements.len(), 1);
+            let s = &program.statements[0];
+            parse_expression_statement(s, |expr| match expr {
+                ast::Expression::Try { .. } => assert_eq!(expr.to_string(), expected),
+                _ => panic!("expression is not try. got={:?}", expr),
+            });
+        }
+
+        let errors = vec![
+            "try { x }",
+            "try { x } catch { e }",
+            "try { x } catch (1) { e }",
+        ];
+        for input in errors {
+            let lexer = Lexer::new(input);
+            assert!(parse(lexer).is_err(), "input={}", input);
+        }
+        Ok(())
+    }
+
     #[test]
     fn parse_function_expression() -> Result<()> {
         let input = r#"
@@ -975,8 +1758,8 @@ mod tests {
             ast::Expression::Function(ast::FunctionExpression { params, body }) => {
                 // parameters
                 assert_eq!(params.len(), 2);
-                test_identifier(&params[0], "x");
-                test_identifier(&params[1], "y");
+                test_identifier(params[0].identifiers()[0], "x");
+                test_identifier(params[1].identifiers()[0], "y");
                 // body
                 assert_eq!(body.statements.len(), 1);
                 let s = &body.statements[0];
@@ -995,6 +1778,54 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_function_parameters() {
+        // (input, expected)
+        let tests = vec![
+            ("fn() { 1 }", Ok("fn() 1")),
+            (
+                "fn(a, b = 1 + 2, ...c) { c }",
+                Ok("fn(a, b = (1 + 2), ...c) c"),
+            ),
+            ("macro(a, ...b) { b }", Ok("macro(a, ...b) b")),
+            (
+                "fn(...a, b) { b }",
+                Err("1:11: rest parameter `...a` must be the last parameter"),
+            ),
+            (
+                "fn(a = 1, b) { b }",
+                Err("1:12: parameter `b` without a default value follows a parameter with one"),
+            ),
+        ];
+        for (input, expected) in tests {
+            let parsed = parse(Lexer::new(input))
+                .map(|p| p.to_string())
+                .map_err(|e| e.0.join("\n"));
+            assert_eq!(parsed, expected.map(String::from).map_err(String::from));
+        }
+    }
+
+    #[test]
+    fn parse_spread_expression() {
+        // (input, expected)
+        let tests = vec![
+            ("[...a, 1, ...b + c]", Ok("[...a, 1, ...(b + c)]")),
+            (r#"{...h, "k": 1}"#, Ok(r#"{...h, "k": 1}"#)),
+            ("f(1, ...args)", Ok("f(1, ...args)")),
+            ("f?.(...args)", Ok("f?.(...args)")),
+            (
+                "...a",
+                Err("1:1: could not parse Ellipsis as prefix expression"),
+            ),
+        ];
+        for (input, expected) in tests {
+            let parsed = parse(Lexer::new(input))
+                .map(|p| p.to_string())
+                .map_err(|e| e.0.join("\n"));
+            assert_eq!(parsed, expected.map(String::from).map_err(String::from));
+        }
+    }
+
     #[test]
     fn parse_call_expression() -> Result<()> {
         let input = r#"
@@ -1068,8 +1899,8 @@ mod tests {
                 let ast::MacroExpression { params, body } = m;
                 // parameters
                 assert_eq!(params.len(), 2);
-                test_identifier(&params[0], "x");
-                test_identifier(&params[1], "y");
+                test_identifier(params[0].identifiers()[0], "x");
+                test_identifier(params[1].identifiers()[0], "y");
                 // body
                 assert_eq!(body.statements.len(), 1);
                 let s = &body.statements[0];
@@ -1133,6 +1964,12 @@ mod tests {
                 "add(a * b[2], b[1], 2 * [1, 2][1])",
                 "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
             ),
+            ("a ?? b == c", "(a ?? (b == c))"),
+            ("a ?? b ?? c", "((a ?? b) ?? c)"),
+            (r#"a["b"]?.["c"] ?? 1"#, r#"(((a["b"])?.["c"]) ?? 1)"#),
+            ("-a?.[0]", "(-(a?.[0]))"),
+            ("f?.(1, 2 * 3)?.[0]", "(f?.(1, (2 * 3))?.[0])"),
+            ("a?.[0]?.(b)", "(a?.[0])?.(b)"),
         ];
         for (input, expected) in cases {
             let lexer = Lexer::new(input);
@@ -1143,6 +1980,247 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_comments() -> Result<()> {
+        let input = r#"
+        // a
+        let a = 1; // b
+        fn() { /* c */ a /* d */ };
+        // e
+        "#;
+        let program = parse(Lexer::new_with_comments(input))?;
+        let function = ast::Expression::Function(ast::FunctionExpression {
+            params: vec![],
+            body: ast::BlockStatement {
+                statements: vec![ast::Statement::Commented(
+                    comments(&["/* c */"], &["/* d */"]),
+                    ast::Statement::Expression(ast::Identifier("a".into()).into()).into(),
+                )],
+            },
+        });
+        let expected = vec![
+            ast::Statement::Commented(
+                comments(&["// a"], &["// b"]),
+                ast::Statement::Let {
+                    identifier: ast::Identifier("a".into()),
+                    expression: ast::Expression::Integer(1),
+                }
+                .into(),
+            ),
+            ast::Statement::Expression(function),
+            ast::Statement::Comment("// e".into()),
+        ];
+        assert_eq!(program.statements, expected);
+
+        // skipped by default
+        let program = parse(Lexer::new(input))?;
+        assert_eq!(program.statements.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_inner_comments() -> Result<()> {
+        let commented = |comments, expr| ast::Expression::Commented(comments, Box::new(expr));
+        let int = ast::Expression::Integer;
+        let id = |s: &str| ast::Expression::Identifier(ast::Identifier(s.into()));
+        // (input, expected)
+        let tests = vec![
+            (
+                "[1, // one\n 2]",
+                ast::Expression::Array(vec![commented(comments(&[], &["// one"]), int(1)), int(2)]),
+            ),
+            (
+                "[/* one */ 1, 2 /* two */]",
+                ast::Expression::Array(vec![
+                    commented(comments(&["/* one */"], &[]), int(1)),
+                    commented(comments(&[], &["/* two */"]), int(2)),
+                ]),
+            ),
+            (
+                "add(1 /* x */, 2)",
+                ast::Expression::Call {
+                    function: ast::Identifier("add".into()).into(),
+                    args: vec![commented(comments(&[], &["/* x */"]), int(1)), int(2)],
+                },
+            ),
+            (
+                "f({\"a\": 1, // one\n \"b\": 2})",
+                ast::Expression::Call {
+                    function: ast::Identifier("f".into()).into(),
+                    args: vec![ast::Expression::Hash(vec![
+                        ast::HashEntry::Pair(
+                            ast::Expression::String("a".into()),
+                            commented(comments(&[], &["// one"]), int(1)),
+                        ),
+                        ast::HashEntry::Pair(ast::Expression::String("b".into()), int(2)),
+                    ])],
+                },
+            ),
+            (
+                "match (x) { 1 => a, // one\n _ => b }",
+                ast::Expression::Match {
+                    value: id("x").into(),
+                    arms: vec![
+                        ast::MatchArm {
+                            pattern: ast::Pattern::Literal(int(1)),
+                            guard: None,
+                            body: commented(comments(&[], &["// one"]), id("a")),
+                        },
+                        ast::MatchArm {
+                            pattern: ast::Pattern::Wildcard,
+                            guard: None,
+                            body: id("b"),
+                        },
+                    ],
+                },
+            ),
+            (
+                "1 + /* two */ 2",
+                ast::Expression::Infix {
+                    left: int(1).into(),
+                    operator: ast::InfixOperator::Add,
+                    right: commented(comments(&["/* two */"], &[]), int(2)).into(),
+                },
+            ),
+        ];
+        for (input, expected) in tests {
+            let program = parse(Lexer::new_with_comments(input))?;
+            let expected = vec![ast::Statement::Expression(expected)];
+            assert_eq!(program.statements, expected, "input={}", input);
+        }
+        Ok(())
+    }
+
+    fn comments(leading: &[&str], trailing: &[&str]) -> ast::Comments {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect();
+        ast::Comments {
+            leading: strings(leading),
+            trailing: strings(trailing),
+        }
+    }
+
+    #[test]
+    fn parse_error_positions() {
+        let input = "let a = 1;\nlet = 2;\nfn(x {}";
+        let (_, errors) = parse_with_positions(Lexer::new(input));
+        let positions = errors.into_iter().map(|(_, p)| p).collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            vec![
+                Position { line: 1, column: 4 },
+                Position { line: 2, column: 3 }
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_illegal_tokens() {
+        // reported where they are, the rest is parsed without them
+        let input = "let a = 1 & 2;\nlet b = \"c";
+        let (program, errors) = parse_with_positions(Lexer::new(input));
+        let errors = errors
+            .into_iter()
+            .map(|(e, p)| (e, p.line, p.column))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            ("illegal character `&`", 0, 10),
+            ("unterminated string", 1, 8),
+        ];
+        let expected = expected
+            .into_iter()
+            .map(|(e, line, column)| (e.to_string(), line, column))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, expected);
+        assert_eq!(program.to_string(), "let a = 1;2");
+    }
+
+    #[test]
+    fn parse_errors() {
+        // (input, errors)
+        let tests = vec![
+            (
+                "let = 1;",
+                vec!["1:5: could not parse Assign as identifier"],
+            ),
+            (
+                "let x 1;",
+                vec!["1:5: expected token to be Assign, got Int(\"1\") instead"],
+            ),
+            (
+                "let x = ; let y = 2; let = 3; y",
+                vec![
+                    "1:9: could not parse Semicolon as prefix expression",
+                    "1:26: could not parse Assign as identifier",
+                ],
+            ),
+            (
+                "add(1, 2 let x = 1; x",
+                vec!["1:8: expected token to be RParen, got Let instead"],
+            ),
+            (
+                "let f = fn(x) { let = 1; x }; let g = fn() { return }; f(1)",
+                vec![
+                    "1:21: could not parse Assign as identifier",
+                    "1:53: could not parse RBrace as prefix expression",
+                ],
+            ),
+            (
+                "} 1; }",
+                vec![
+                    "1:1: could not parse RBrace as prefix expression",
+                    "1:6: could not parse RBrace as prefix expression",
+                ],
+            ),
+            // no follow-on error at the end of the input
+            ("let s = \"a", vec!["1:9: unterminated string"]),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let errors = match parse(lexer) {
+                Ok(_) => panic!("no errors. input={}", input),
+                Err(e) => e.0,
+            };
+            assert_eq!(errors, expected, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn parse_partial_program() {
+        // (input, partial program, number of errors)
+        let tests = vec![
+            ("let x = ; let y = 2; let = 3; y", "let y = 2;y", 2),
+            ("1 + ; 2; 3 *", "2", 2),
+            (
+                "let f = fn(x) { let = 1; x }; f(1)",
+                "let f = fn(x) x;f(1)",
+                1,
+            ),
+            (
+                "if (x) { 1 + } else { let y 2; y }; z",
+                "if x {  } else { y }z",
+                2,
+            ),
+        ];
+        for (input, expected, n) in tests {
+            let lexer = Lexer::new(input);
+            let (program, errors) = parse_partial(lexer);
+            assert_eq!(program.to_string(), expected, "input={}", input);
+            assert_eq!(errors.0.len(), n, "input={}", input);
+        }
+    }
+
+    #[test]
+    fn parse_too_many_errors() {
+        let input = "let = 1;".repeat(MAX_ERRORS * 2);
+        let lexer = Lexer::new(input);
+        let (_, errors) = parse_partial(lexer);
+        assert_eq!(errors.0.len(), MAX_ERRORS + 1);
+        assert_eq!(
+            errors.0.last().unwrap(),
+            &format!("1:157: too many errors, stopped after {}", MAX_ERRORS)
+        );
+    }
+
     #[test]
     fn display() {
         let program = ast::Program {
@@ -1248,6 +2326,20 @@ mod tests {
         }
     }
 
+    fn find_hash_value<'a>(
+        entries: &'a [ast::HashEntry],
+        key: &ast::Expression,
+    ) -> &'a ast::Expression {
+        let value = entries.iter().find_map(|entry| match entry {
+            ast::HashEntry::Pair(k, v) if k == key => Some(v),
+            _ => None,
+        });
+        match value {
+            Some(v) => v,
+            None => panic!("key not found in hash. key={:?}", key),
+        }
+    }
+
     fn new_int_expr(n: i64) -> ast::Expression {
         ast::Expression::Integer(n)
     }
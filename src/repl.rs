@@ -1,23 +1,90 @@
-use crate::evaluator::{define_macros, eval, expand_macros};
-use crate::lexer::Lexer;
+use crate::ast;
+use crate::evaluator::{
+    define_macros, eval, expand_macros, expand_macros_traced, prelude, Capabilities, Context,
+};
+use crate::lexer::{dump_tokens, Lexer};
 use crate::object::Environment;
 use crate::object::{self};
 use crate::parser::{self, parse};
+use std::cell::RefCell;
 use std::io::prelude::*;
 use std::io::{self, BufRead};
+use std::rc::Rc;
 
 pub struct Repl {
     prompt: String,
+    // evaluate the prelude before the first line
+    prelude: bool,
+    capabilities: Capabilities,
 }
 
+// `puts` output, written to the REPL writer before what follows it
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        let bytes = self.0.replace(Vec::new());
+        writer.write_all(&bytes)
+    }
+}
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// commands, other lines are evaluated
+const EXPAND_COMMAND: &str = ":expand ";
+const TOKENS_COMMAND: &str = ":tokens ";
+const TRACE_ON_COMMAND: &str = ":trace on";
+const TRACE_OFF_COMMAND: &str = ":trace off";
+
 impl Repl {
     pub fn new(prompt: impl Into<String>) -> Self {
         Self {
             prompt: prompt.into(),
+            prelude: true,
+            capabilities: Capabilities::default(),
         }
     }
 
+    pub fn with_prelude(mut self, prelude: bool) -> Self {
+        self.prelude = prelude;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
     pub fn start<R, W>(&self, reader: R, writer: W) -> Result<(), Box<dyn std::error::Error>>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        let output = Output::default();
+        let _context = Context::new()
+            .with_capabilities(self.capabilities)
+            .with_output(Box::new(output.clone()))
+            .with_prelude(self.prelude)
+            .enter();
+        self.run(reader, writer, &output)
+    }
+
+    fn run<R, W>(
+        &self,
+        reader: R,
+        writer: W,
+        output: &Output,
+    ) -> Result<(), Box<dyn std::error::Error>>
     where
         R: io::Read,
         W: io::Write,
@@ -28,32 +95,101 @@ impl Repl {
         writer.flush()?;
         let mut env = Environment::new();
         let mut macro_env = Environment::new();
+        if self.prelude {
+            prelude::load(&mut env, &mut macro_env);
+        }
+        // the prelude bindings may be redefined, even with `const`
+        let mut env = Environment::new_with_outer(env);
+        let mut macro_env = Environment::new_with_outer(macro_env);
+        // print each macro expansion step before the result
+        let mut trace = false;
         for l in reader.lines() {
             let l = l?;
-            let lexer = Lexer::new(l);
-            match parse(lexer) {
-                Ok(prog) => {
-                    let prog = define_macros(prog, &mut macro_env);
-                    match expand_macros(prog, &macro_env) {
-                        Ok(expanded) => {
-                            let evaluated = eval(expanded.into(), &mut env);
-                            if object::Object::Let != evaluated {
-                                write!(writer, "{}\n", evaluated.inspect())?;
+            match l.trim() {
+                TRACE_ON_COMMAND => trace = true,
+                TRACE_OFF_COMMAND => trace = false,
+                // prints the tokens with their positions without evaluating them
+                line if line.starts_with(TOKENS_COMMAND) => {
+                    let (dump, diagnostics) = dump_tokens(&line[TOKENS_COMMAND.len()..]);
+                    writeln!(writer, "{}", dump)?;
+                    for d in diagnostics {
+                        writeln!(writer, "{}", d)?;
+                    }
+                }
+                // prints the input after macro expansion without evaluating it
+                line if line.starts_with(EXPAND_COMMAND) => {
+                    let lexer = Lexer::new(&line[EXPAND_COMMAND.len()..]);
+                    match parse(lexer) {
+                        Ok(prog) => {
+                            let mut macro_env = macro_env.clone();
+                            let prog = define_macros(prog, &mut macro_env);
+                            match expand_macros(prog, &macro_env) {
+                                Ok(expanded) => writeln!(writer, "{}", expanded)?,
+                                Err(e) => writeln!(writer, "{}", e)?,
                             }
                         }
-                        Err(e) => write!(writer, "{}\n", e)?,
+                        Err(parser::Errors(e)) => {
+                            Self::print_parse_error(&mut writer, e)?;
+                        }
                     }
                 }
-                Err(parser::Errors(e)) => {
-                    Self::print_parse_error(&mut writer, e)?;
+                _ => {
+                    let lexer = Lexer::new(l);
+                    match parse(lexer) {
+                        Ok(prog) => {
+                            let prog = define_macros(prog, &mut macro_env);
+                            if let Some(expanded) =
+                                Self::expand(&mut writer, prog, &macro_env, trace, output)?
+                            {
+                                let evaluated = eval(expanded.into(), &mut env);
+                                output.write_to(&mut writer)?;
+                                if object::Object::Let != evaluated {
+                                    write!(writer, "{}\n", evaluated.inspect())?;
+                                }
+                            }
+                        }
+                        Err(parser::Errors(e)) => {
+                            Self::print_parse_error(&mut writer, e)?;
+                        }
+                    }
                 }
             }
+            output.write_to(&mut writer)?;
             write!(writer, "{}", self.prompt)?;
             writer.flush()?;
         }
         Ok(())
     }
 
+    // prints the trace and errors, `None` on error
+    fn expand<W: io::Write>(
+        writer: &mut io::BufWriter<W>,
+        prog: ast::Program,
+        macro_env: &Environment,
+        trace: bool,
+        output: &Output,
+    ) -> io::Result<Option<ast::Program>> {
+        let expanded = if trace {
+            expand_macros_traced(prog, macro_env).map(|(prog, steps)| (prog, Some(steps)))
+        } else {
+            expand_macros(prog, macro_env).map(|prog| (prog, None))
+        };
+        // written by the macros
+        output.write_to(writer)?;
+        match expanded {
+            Ok((prog, steps)) => {
+                for step in steps.unwrap_or_default() {
+                    writeln!(writer, "{}", step)?;
+                }
+                Ok(Some(prog))
+            }
+            Err(e) => {
+                writeln!(writer, "{}", e)?;
+                Ok(None)
+            }
+        }
+    }
+
     fn print_parse_error<W: io::Write>(
         writer: &mut io::BufWriter<W>,
         errors: Vec<String>,
@@ -65,3 +201,93 @@ impl Repl {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::repl::Repl;
+
+    #[test]
+    fn repl_commands() {
+        let input = r#"let double = macro(e) { quote(unquote(e) * 2) };
+:tokens double(&)
+:expand double(double(1))
+:expand let twice = macro(e) { quote(unquote(e) + unquote(e)) }; twice(2)
+twice(2)
+:trace on
+double(double(1))
+:trace off
+double(1)
+let s = "a
+"#;
+        let mut output = Vec::new();
+        Repl::new("> ")
+            .with_prelude(false)
+            .start(input.as_bytes(), &mut output)
+            .unwrap();
+        let expected = "> null
+> 1:1     Identifier(\"double\")
+1:7     LParen
+1:8     Illegal(\"&\")
+1:9     RParen
+1:8: illegal character `&`
+> ((1 * 2) * 2)
+> (2 + 2)
+> identifier not found: `twice`
+> > double(double(1)) => (double(1) * 2)
+  double(1) => (1 * 2)
+4
+> > 2
+> parser errors:
+\t1:9: unterminated string
+> ";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn repl_output() {
+        let input = r#"puts("a", 1); 2
+let m = macro() { puts("expanding"); quote(3) };
+:trace on
+m()
+"#;
+        let mut output = Vec::new();
+        Repl::new("> ")
+            .with_prelude(false)
+            .start(input.as_bytes(), &mut output)
+            .unwrap();
+        let expected = "> a
+1
+2
+> null
+> > expanding
+m() => 3
+3
+> ";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn repl_imports_with_prelude() {
+        let dir = std::env::temp_dir().join(format!("monkey-repl-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lib.mk");
+        std::fs::write(&path, "export let total = fn(arr) { sum(arr) };").unwrap();
+        let input = format!(
+            "import {{total}} from \"{}\"; total([1, 2])\n",
+            path.display()
+        );
+        // (prelude, expected)
+        let tests = vec![
+            (true, "> 3\n> "),
+            (false, "> identifier not found: `sum`\n> "),
+        ];
+        for (prelude, expected) in tests {
+            let mut output = Vec::new();
+            Repl::new("> ")
+                .with_prelude(prelude)
+                .start(input.as_bytes(), &mut output)
+                .unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,800 @@
+use crate::lexer::{Lexer, Position};
+use crate::token::Token;
+use itertools::Itertools;
+
+// resolves identifiers to `let` bindings and parameters on the token stream,
+// so that it also works on programs which cannot be parsed, shared by the
+// linter and the language server
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub position: Position,
+    pub kind: DefinitionKind,
+    // visible until the end of the enclosing block, `None` for the top level
+    pub scope_end: Option<Position>,
+    // indexes of `Scopes::definitions`: the definition of the name in an
+    // enclosing scope which this one hides, an earlier one in the same scope
+    pub shadows: Option<usize>,
+    pub redefines: Option<usize>,
+    // `export let ...`, used by the importing files
+    pub exported: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DefinitionKind {
+    Let,
+    // `const x = 1`
+    Const,
+    // `import "lib.mk" as lib`, `import {a} from "lib.mk"`
+    Import,
+    // `let f = fn(a, b) { ... }`
+    Function(Vec<String>),
+    // `let m = macro(a, b) { ... }`
+    Macro(Vec<String>),
+    Param,
+}
+
+impl Definition {
+    pub fn signature(&self) -> String {
+        match &self.kind {
+            DefinitionKind::Let => format!("let {}", self.name),
+            DefinitionKind::Const => format!("const {}", self.name),
+            DefinitionKind::Import => format!("import {}", self.name),
+            DefinitionKind::Function(params) => {
+                format!("let {} = fn({})", self.name, params.iter().join(", "))
+            }
+            DefinitionKind::Macro(params) => {
+                format!("let {} = macro({})", self.name, params.iter().join(", "))
+            }
+            DefinitionKind::Param => format!("{} (parameter)", self.name),
+        }
+    }
+
+    fn is_visible_at(&self, position: Position) -> bool {
+        self.position <= position && self.scope_end.iter().all(|&end| position < end)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub position: Position,
+    // index of `Scopes::definitions`, `None` for builtins and undefined identifiers
+    pub definition: Option<usize>,
+    // in a macro, where `gensym` is defined
+    pub in_macro: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Scopes {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+impl Scopes {
+    // the identifier under the cursor and its definition
+    pub fn lookup(&self, position: Position) -> Option<(&str, Option<&Definition>)> {
+        let contains = |name: &str, start: Position| {
+            start.line == position.line
+                && start.column <= position.column
+                && position.column <= start.column + name.chars().count()
+        };
+        if let Some(d) = self
+            .definitions
+            .iter()
+            .find(|d| contains(&d.name, d.position))
+        {
+            return Some((&d.name, Some(d)));
+        }
+        self.references
+            .iter()
+            .find(|r| contains(&r.name, r.position))
+            .map(|r| (r.name.as_str(), r.definition.map(|i| &self.definitions[i])))
+    }
+
+    // the innermost definition of each name visible at the position
+    pub fn visible_at(&self, position: Position) -> Vec<&Definition> {
+        let mut res: Vec<&Definition> = Vec::new();
+        for d in self.definitions.iter().rev() {
+            if d.is_visible_at(position) && res.iter().all(|r| r.name != d.name) {
+                res.push(d);
+            }
+        }
+        res.sort_by(|a, b| a.name.cmp(&b.name));
+        res
+    }
+}
+
+pub fn resolve(input: &str) -> Scopes {
+    let tokens = Lexer::new(input).positioned().collect::<Vec<_>>();
+    resolve_tokens(&tokens)
+}
+
+pub fn resolve_tokens(tokens: &[(Token, Position)]) -> Scopes {
+    let quoted = quoted_tokens(tokens);
+    let mut resolver = Resolver {
+        tokens,
+        quoted,
+        pos: 0,
+        scopes: vec![Vec::new()],
+        params: Vec::new(),
+        macro_params: false,
+        macro_scopes: Vec::new(),
+        arms: Vec::new(),
+        pending: Vec::new(),
+        res: Scopes::default(),
+    };
+    resolver.resolve();
+    resolver.res
+}
+
+// whether each token is in quoted code, except inside `unquote(...)`
+fn quoted_tokens(tokens: &[(Token, Position)]) -> Vec<bool> {
+    let mut res = Vec::with_capacity(tokens.len());
+    // the paren depth closing a `quote(` or `unquote(`, whether it is quoted
+    let mut regions: Vec<(usize, bool)> = Vec::new();
+    let mut depth = 0;
+    let mut opens = None;
+    for (token, _) in tokens {
+        res.push(regions.last().map(|&(_, quoted)| quoted) == Some(true));
+        match token {
+            Token::Quote => opens = Some(true),
+            Token::Unquote => opens = Some(false),
+            Token::LParen => {
+                depth += 1;
+                if let Some(quoted) = opens.take() {
+                    regions.push((depth, quoted));
+                }
+            }
+            Token::RParen => {
+                if regions.last().map(|&(d, _)| d) == Some(depth) {
+                    regions.pop();
+                }
+                depth = depth.saturating_sub(1);
+                opens = None;
+            }
+            _ => opens = None,
+        }
+    }
+    res
+}
+
+// a `let` without a semicolon ends where another operand follows one
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Identifier(_)
+            | Token::Int(_)
+            | Token::String(_)
+            | Token::True
+            | Token::False
+            | Token::RParen
+            | Token::RBracket
+            | Token::RBrace
+    )
+}
+
+fn starts_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Identifier(_)
+            | Token::Int(_)
+            | Token::String(_)
+            | Token::True
+            | Token::False
+            | Token::Bang
+            | Token::Function
+            | Token::Macro
+            | Token::If
+            | Token::Try
+            | Token::Match
+            | Token::Quote
+            | Token::Unquote
+            | Token::Let
+            | Token::Const
+            | Token::Return
+            | Token::Import
+            | Token::Export
+    )
+}
+
+struct Resolver<'a> {
+    tokens: &'a [(Token, Position)],
+    quoted: Vec<bool>,
+    pos: usize,
+    // definition indexes, innermost scope last
+    scopes: Vec<Vec<usize>>,
+    // parameters waiting for the `{` of their body
+    params: Vec<usize>,
+    // whether `params` are those of a macro
+    macro_params: bool,
+    // the number of scopes where a macro body is open
+    macro_scopes: Vec<usize>,
+    // token indexes of the first and the terminating token of `match` arms
+    arms: Vec<(usize, usize)>,
+    // bindings defined at the end of their statement: the token index of the
+    // end and the definition
+    pending: Vec<(usize, Definition)>,
+    res: Scopes,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self) {
+        while self.pos < self.tokens.len() {
+            self.define_pending();
+            if self.quoted[self.pos] {
+                self.pos += 1;
+                continue;
+            }
+            if self.match_arm() {
+                self.pos += 1;
+                continue;
+            }
+            let (token, position) = &self.tokens[self.pos];
+            let position = *position;
+            let exported = self.pos > 0 && self.tokens[self.pos - 1].0 == Token::Export;
+            match token {
+                Token::Let | Token::Const => match self.tokens.get(self.pos + 1) {
+                    Some((Token::Identifier(name), position)) => {
+                        let name = name.clone();
+                        let position = *position;
+                        let kind = match self.definition_kind(self.pos + 3) {
+                            DefinitionKind::Let if token == &Token::Const => DefinitionKind::Const,
+                            kind => kind,
+                        };
+                        let mut d = Self::definition(name, position, kind);
+                        d.exported = exported;
+                        // functions can refer to themselves
+                        if let DefinitionKind::Function(_) = d.kind {
+                            self.define(d);
+                        } else {
+                            let end = self.statement_end(self.pos + 3);
+                            self.pending.push((end, d));
+                        }
+                        self.pos += 1;
+                    }
+                    Some((Token::LBracket, _)) | Some((Token::LBrace, _)) => {
+                        let (bindings, end) = self.pattern(self.pos + 1);
+                        let statement_end = self.statement_end(end + 2);
+                        for (name, position) in bindings {
+                            let mut d = Self::definition(name, position, DefinitionKind::Let);
+                            d.exported = exported;
+                            self.pending.push((statement_end, d));
+                        }
+                        self.pos = end;
+                    }
+                    _ => {}
+                },
+                Token::Function | Token::Macro | Token::Catch => {
+                    self.macro_params = token == &Token::Macro;
+                    self.params = self
+                        .parameters(self.pos + 1)
+                        .into_iter()
+                        .map(|(name, position)| {
+                            let mut d = Self::definition(name, position, DefinitionKind::Param);
+                            d.shadows = self.find(&d.name, &self.scopes);
+                            self.add(d)
+                        })
+                        .collect();
+                }
+                Token::Import => self.import(),
+                Token::Match => {
+                    let arms = self.match_arms(self.pos + 1);
+                    self.arms.extend(arms);
+                }
+                Token::LBrace => {
+                    let params = std::mem::take(&mut self.params);
+                    self.scopes.push(params);
+                    if std::mem::replace(&mut self.macro_params, false) {
+                        self.macro_scopes.push(self.scopes.len());
+                    }
+                }
+                Token::RBrace if self.scopes.len() > 1 => self.pop_scope(position),
+                // the parameters themselves
+                Token::Identifier(_) if self.is_param(position) => {}
+                // `lib.name`
+                Token::Identifier(_)
+                    if self.pos > 0 && self.tokens[self.pos - 1].0 == Token::Dot => {}
+                Token::Identifier(name) => {
+                    let definition = self.find(name, &self.scopes);
+                    self.res.references.push(Reference {
+                        name: name.clone(),
+                        position,
+                        definition,
+                        in_macro: self.macro_params || !self.macro_scopes.is_empty(),
+                    });
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        self.define_pending();
+    }
+
+    fn define_pending(&mut self) {
+        let pos = self.pos;
+        let (ended, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|&(end, _)| end <= pos);
+        self.pending = pending;
+        for (_, d) in ended {
+            self.define(d);
+        }
+    }
+
+    // the index of the token ending the statement whose expression starts at
+    // `i`: `;`, the bracket closing the enclosing block or the start of the
+    // next statement
+    fn statement_end(&self, i: usize) -> usize {
+        let mut depth = 0;
+        for (j, (token, _)) in self.tokens.iter().enumerate().skip(i) {
+            match token {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace if depth == 0 => return j,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::Semicolon if depth == 0 => return j,
+                token
+                    if depth == 0
+                        && j > i
+                        && ends_operand(&self.tokens[j - 1].0)
+                        && starts_operand(token) =>
+                {
+                    return j
+                }
+                _ => {}
+            }
+        }
+        self.tokens.len()
+    }
+
+    fn import(&mut self) {
+        // `import "<path>" as <identifier>`, `import {<identifier>, ...} from "<path>"`
+        let token = |i: usize| self.tokens.get(i).map(|(t, _)| t);
+        let (bindings, end) = match token(self.pos + 1) {
+            Some(Token::String(_)) => match self.tokens.get(self.pos + 3) {
+                Some((Token::Identifier(name), position)) => {
+                    (vec![(name.clone(), *position)], self.pos + 3)
+                }
+                _ => return,
+            },
+            Some(Token::LBrace) => {
+                let (bindings, end) = self.pattern(self.pos + 1);
+                (bindings, end + 1)
+            }
+            _ => return,
+        };
+        for (name, position) in bindings {
+            self.define(Self::definition(name, position, DefinitionKind::Import));
+        }
+        // skips `as` or `from`
+        self.pos = end;
+    }
+
+    fn pop_scope(&mut self, end: Position) {
+        if self.macro_scopes.last() == Some(&self.scopes.len()) {
+            self.macro_scopes.pop();
+        }
+        if let Some(scope) = self.scopes.pop() {
+            for i in scope {
+                self.res.definitions[i].scope_end = Some(end);
+            }
+        }
+    }
+
+    // opens the scope of a `match` arm at its pattern and closes it at the
+    // following `,` or `}`, true if the pattern has been consumed
+    fn match_arm(&mut self) -> bool {
+        let pos = self.pos;
+        if self.arms.iter().any(|&(_, end)| end == pos) {
+            let end = self.tokens[pos].1;
+            self.pop_scope(end);
+        }
+        if self.arms.iter().all(|&(start, _)| start != pos) {
+            return false;
+        }
+        self.scopes.push(Vec::new());
+        let bindings = match &self.tokens[pos] {
+            (Token::LBracket, _) | (Token::LBrace, _) => {
+                let (bindings, end) = self.pattern(pos);
+                self.pos = end;
+                bindings
+            }
+            (Token::Identifier(name), _) if name == "_" => Vec::new(),
+            (Token::Identifier(name), position) => vec![(name.clone(), *position)],
+            _ => return false,
+        };
+        for (name, position) in bindings {
+            self.define(Self::definition(name, position, DefinitionKind::Let));
+        }
+        true
+    }
+
+    fn match_arms(&self, i: usize) -> Vec<(usize, usize)> {
+        // `(<value>) { <arm>, ... }`
+        let mut res = Vec::new();
+        let mut depth = 0;
+        let mut start = None;
+        for (j, (token, _)) in self.tokens.iter().enumerate().skip(i) {
+            match token {
+                Token::LBrace if depth == 0 && start.is_none() => start = Some(j + 1),
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RBrace if depth == 0 => {
+                    if let Some(s) = start.filter(|&s| s < j) {
+                        res.push((s, j));
+                    }
+                    break;
+                }
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::Comma if depth == 0 => {
+                    if let Some(s) = start {
+                        res.push((s, j));
+                    }
+                    start = Some(j + 1);
+                }
+                _ => {}
+            }
+        }
+        res
+    }
+
+    fn definition_kind(&self, i: usize) -> DefinitionKind {
+        // `fn(...)` or `macro(...)` after `let <identifier> =`
+        let names = |params: Vec<(String, Position)>| params.into_iter().map(|(n, _)| n).collect();
+        match self.tokens.get(i) {
+            Some((Token::Function, _)) => DefinitionKind::Function(names(self.parameters(i + 1))),
+            Some((Token::Macro, _)) => DefinitionKind::Macro(names(self.parameters(i + 1))),
+            _ => DefinitionKind::Let,
+        }
+    }
+
+    fn parameters(&self, i: usize) -> Vec<(String, Position)> {
+        // `(a, b = <expr>, ...c)`
+        let mut res = Vec::new();
+        if let Some((Token::LParen, _)) = self.tokens.get(i) {
+            // nesting of default values
+            let mut depth = 0;
+            let mut expects_param = true;
+            let mut j = i + 1;
+            while let Some((token, position)) = self.tokens.get(j) {
+                match token {
+                    Token::LBracket | Token::LBrace if depth == 0 && expects_param => {
+                        let (bindings, end) = self.pattern(j);
+                        res.extend(bindings);
+                        j = end;
+                        expects_param = false;
+                    }
+                    Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                    Token::RParen | Token::RBracket | Token::RBrace if depth == 0 => break,
+                    Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                    Token::Comma if depth == 0 => expects_param = true,
+                    Token::Identifier(name) if expects_param => {
+                        res.push((name.clone(), *position));
+                        expects_param = false;
+                    }
+                    Token::Ellipsis => {}
+                    _ => expects_param = false,
+                }
+                j += 1;
+            }
+        }
+        res
+    }
+
+    fn pattern(&self, i: usize) -> (Vec<(String, Position)>, usize) {
+        // `[a, [b], ...c]`, `{a, b: c}`, the bindings and the index of the closing bracket
+        let mut res = Vec::new();
+        let mut depth = 0;
+        for (j, (token, position)) in self.tokens.iter().enumerate().skip(i) {
+            match token {
+                Token::LBracket | Token::LBrace => depth += 1,
+                Token::RBracket | Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return (res, j);
+                    }
+                }
+                // not a key of a hash pattern or `_`
+                Token::Identifier(name)
+                    if name != "_"
+                        && self.tokens.get(j + 1).map(|(t, _)| t) != Some(&Token::Colon) =>
+                {
+                    res.push((name.clone(), *position))
+                }
+                _ => {}
+            }
+        }
+        (res, self.tokens.len())
+    }
+
+    fn is_param(&self, position: Position) -> bool {
+        self.params
+            .iter()
+            .any(|&i| self.res.definitions[i].position == position)
+    }
+
+    // the latest definition of the name in the innermost of the scopes
+    fn find(&self, name: &str, scopes: &[Vec<usize>]) -> Option<usize> {
+        scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|&&i| self.res.definitions[i].name == name)
+            .copied()
+    }
+
+    fn definition(name: String, position: Position, kind: DefinitionKind) -> Definition {
+        Definition {
+            name,
+            position,
+            kind,
+            scope_end: None,
+            shadows: None,
+            redefines: None,
+            exported: false,
+        }
+    }
+
+    fn add(&mut self, d: Definition) -> usize {
+        self.res.definitions.push(d);
+        self.res.definitions.len() - 1
+    }
+
+    fn define(&mut self, mut d: Definition) {
+        if let Some((current, outer)) = self.scopes.split_last() {
+            d.redefines = self.find(&d.name, std::slice::from_ref(current));
+            d.shadows = self.find(&d.name, outer);
+        }
+        let i = self.add(d);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Position;
+    use crate::scope::{resolve, DefinitionKind};
+
+    #[test]
+    fn resolve_scopes() {
+        let input = "let x = 1;
+let add = fn(a, b) {
+    let x = a;
+    x + b
+};
+add(x, len([]));
+try { y } catch (e) { e }";
+        let scopes = resolve(input);
+        let position = |line, column| Position { line, column };
+        let definitions = scopes
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.position, d.kind.clone(), d.scope_end))
+            .collect::<Vec<_>>();
+        let params = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(
+            definitions,
+            vec![
+                ("x", position(0, 4), DefinitionKind::Let, None),
+                (
+                    "add",
+                    position(1, 4),
+                    DefinitionKind::Function(params),
+                    None
+                ),
+                (
+                    "a",
+                    position(1, 13),
+                    DefinitionKind::Param,
+                    Some(position(4, 0))
+                ),
+                (
+                    "b",
+                    position(1, 16),
+                    DefinitionKind::Param,
+                    Some(position(4, 0))
+                ),
+                (
+                    "x",
+                    position(2, 8),
+                    DefinitionKind::Let,
+                    Some(position(4, 0))
+                ),
+                (
+                    "e",
+                    position(6, 17),
+                    DefinitionKind::Param,
+                    Some(position(6, 24))
+                ),
+            ]
+        );
+        let references = scopes
+            .references
+            .iter()
+            .map(|r| (r.name.as_str(), r.position, r.definition))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            vec![
+                ("a", position(2, 12), Some(2)),
+                ("x", position(3, 4), Some(4)),
+                ("b", position(3, 8), Some(3)),
+                ("add", position(5, 0), Some(1)),
+                ("x", position(5, 4), Some(0)),
+                ("len", position(5, 7), None),
+                ("y", position(6, 6), None),
+                ("e", position(6, 22), Some(5)),
+            ]
+        );
+
+        // lookup
+        let (name, d) = scopes.lookup(position(3, 5)).unwrap();
+        assert_eq!((name, d.map(|d| d.position)), ("x", Some(position(2, 8))));
+        let (name, d) = scopes.lookup(position(5, 8)).unwrap();
+        assert_eq!((name, d.map(|d| d.position)), ("len", None));
+        assert_eq!(scopes.lookup(position(5, 11)), None);
+
+        // visible_at
+        let visible = |p| {
+            scopes
+                .visible_at(p)
+                .iter()
+                .map(|d| (d.name.as_str(), d.position))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            visible(position(3, 0)),
+            vec![
+                ("a", position(1, 13)),
+                ("add", position(1, 4)),
+                ("b", position(1, 16)),
+                ("x", position(2, 8))
+            ]
+        );
+        assert_eq!(
+            visible(position(5, 0)),
+            vec![("add", position(1, 4)), ("x", position(0, 4))]
+        );
+    }
+
+    #[test]
+    fn resolve_parameters() {
+        let scopes =
+            resolve("let x = 1;\nlet f = fn(a = [x], ...b) { b }\nlet [c, {d: e}] = f([1]);");
+        let definitions = scopes
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.kind.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            vec![
+                ("x", DefinitionKind::Let),
+                ("f", DefinitionKind::Function(vec!["a".into(), "b".into()])),
+                ("a", DefinitionKind::Param),
+                ("b", DefinitionKind::Param),
+                ("c", DefinitionKind::Let),
+                ("e", DefinitionKind::Let),
+            ]
+        );
+        let references = scopes
+            .references
+            .iter()
+            .map(|r| (r.name.as_str(), r.definition))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            vec![("x", Some(0)), ("b", Some(3)), ("f", Some(1))]
+        );
+    }
+
+    #[test]
+    fn resolve_imports() {
+        let scopes = resolve("import \"a.mk\" as a;\nimport {b, c} from \"b.mk\";\na.b(b, c);");
+        let definitions = scopes
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.kind.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            vec![
+                ("a", DefinitionKind::Import),
+                ("b", DefinitionKind::Import),
+                ("c", DefinitionKind::Import),
+            ]
+        );
+        let references = scopes
+            .references
+            .iter()
+            .map(|r| (r.name.as_str(), r.definition))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            vec![("a", Some(0)), ("b", Some(1)), ("c", Some(2))]
+        );
+    }
+
+    #[test]
+    fn resolve_const() {
+        let scopes = resolve("const x = 1;\nlet y = x;");
+        let signatures = scopes
+            .definitions
+            .iter()
+            .map(|d| d.signature())
+            .collect::<Vec<_>>();
+        assert_eq!(signatures, vec!["const x", "let y"]);
+    }
+
+    #[test]
+    fn resolve_statements_and_quotes() {
+        let input = "let a = a;
+export let b = 1
+let m = macro(x) { quote(unquote(x) + y) };
+fn(b) { gensym }";
+        let scopes = resolve(input);
+        let definitions = scopes
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.shadows, d.exported))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            vec![
+                ("a", None, false),
+                ("b", None, true),
+                ("x", None, false),
+                ("m", None, false),
+                ("b", Some(1), false),
+            ]
+        );
+        let references = scopes
+            .references
+            .iter()
+            .map(|r| (r.name.as_str(), r.definition, r.in_macro))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            vec![
+                ("a", None, false),
+                ("x", Some(2), true),
+                ("gensym", None, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_match_arms() {
+        let scopes = resolve("match (x) {\n  [a, _] if a => a,\n  {k: b} => [b, a],\n  _ => 1\n}");
+        let position = |line, column| Position { line, column };
+        let definitions = scopes
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.position, d.scope_end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            vec![
+                ("a", position(1, 3), Some(position(1, 18))),
+                ("b", position(2, 6), Some(position(2, 18))),
+            ]
+        );
+        let references = scopes
+            .references
+            .iter()
+            .map(|r| (r.name.as_str(), r.definition))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            vec![
+                ("x", None),
+                ("a", Some(0)),
+                ("a", Some(0)),
+                ("b", Some(1)),
+                ("a", None)
+            ]
+        );
+    }
+}
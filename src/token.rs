@@ -12,6 +12,8 @@ pub enum Token {
     Identifier(String),
     /// =
     Assign,
+    /// =>
+    FatArrow,
     /// !
     Bang,
     /// +
@@ -26,6 +28,10 @@ pub enum Token {
     Eq,
     /// !=
     NotEq,
+    /// ??
+    QuestionQuestion,
+    /// ?.
+    QuestionDot,
     /// fn
     Function,
     /// >=
@@ -38,8 +44,22 @@ pub enum Token {
     Else,
     /// let
     Let,
+    /// const
+    Const,
     /// return
     Return,
+    /// try
+    Try,
+    /// catch
+    Catch,
+    /// finally
+    Finally,
+    /// match
+    Match,
+    /// import
+    Import,
+    /// export
+    Export,
     /// (
     LParen,
     /// )
@@ -58,11 +78,18 @@ pub enum Token {
     Colon,
     /// ;
     Semicolon,
+    /// ...
+    Ellipsis,
+    /// .
+    Dot,
     /// quote
     Quote,
     /// unquote
     Unquote,
     // macro
     Macro,
+    /// // comment, /* comment */
+    /// only emitted by `Lexer::new_with_comments`
+    Comment(String),
     Illegal(String),
 }